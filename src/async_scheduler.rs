@@ -0,0 +1,382 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use rand::Rng;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::time::Sleep;
+
+use crate::backends::{Backend, BackendError};
+use crate::clock::Clock;
+use crate::event::{Event, Priority};
+use crate::scheduler::EventId;
+
+// an output driven from a tokio task rather than a thread of its own, shaped
+// like a futures Sink: poll_ready until it can take an event, start_send to
+// hand one over, poll_flush to see them out. None of it may block, the task
+// shares its runtime thread with everything else
+pub trait AsyncBackend: Send {
+    fn start(&mut self) -> Result<(), BackendError> {
+        Ok(())
+    }
+
+    fn poll_ready(&mut self, _cx: &mut Context) -> Poll<Result<(), BackendError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(&mut self, event: &Event) -> Result<(), BackendError>;
+
+    fn poll_flush(&mut self, _cx: &mut Context) -> Poll<Result<(), BackendError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn stop(&mut self) -> Result<(), BackendError> {
+        Ok(())
+    }
+
+    fn name(&self) -> String;
+}
+
+// a regular backend on the async scheduler, for the ones quick enough to
+// call from a task (UDP, OSC, in memory); one that can block on a device or
+// a connection belongs on the threaded Scheduler. poll() isn't called
+pub struct Blocking(pub Box<dyn Backend>);
+
+impl AsyncBackend for Blocking {
+    fn start(&mut self) -> Result<(), BackendError> {
+        self.0.start()
+    }
+
+    fn start_send(&mut self, event: &Event) -> Result<(), BackendError> {
+        self.0.send(event)
+    }
+
+    fn poll_flush(&mut self, _cx: &mut Context) -> Poll<Result<(), BackendError>> {
+        Poll::Ready(self.0.flush())
+    }
+
+    fn stop(&mut self) -> Result<(), BackendError> {
+        self.0.stop()
+    }
+
+    fn name(&self) -> String {
+        self.0.name()
+    }
+}
+
+enum Command {
+    Schedule(Instant, EventId, Event),
+    Cancel(EventId),
+    CancelTag(String),
+    Flush,
+    Shutdown,
+}
+
+// keeps time: sleeps on tokio's timer until the earliest event is due, then
+// hands everything due to the backends' tasks
+struct Timer {
+    commands: UnboundedReceiver<Command>,
+    // cancelling only removes from `events`, stale slots are skipped
+    heap: BinaryHeap<Reverse<(Instant, Reverse<Priority>, EventId)>>,
+    events: HashMap<EventId, Event>,
+    outputs: Vec<UnboundedSender<Event>>,
+    sleep: Pin<Box<Sleep>>,
+}
+
+impl Timer {
+    // false once it's time to go
+    fn apply(&mut self, command: Command) -> bool {
+        match command {
+            Command::Schedule(at, id, event) => {
+                self.heap.push(Reverse((at, Reverse(event.priority), id)));
+                self.events.insert(id, event);
+            }
+            Command::Cancel(id) => {
+                self.events.remove(&id);
+            }
+            // note-offs stay so nothing hangs, as with Scheduler::cancel_tag
+            Command::CancelTag(tag) => self.events.retain(|_, event| {
+                event.tag.as_ref().is_none_or(|t| *t != tag) || event.is_note_off()
+            }),
+            // note-offs stay here too
+            Command::Flush => self.events.retain(|_, event| event.is_note_off()),
+            Command::Shutdown => {
+                self.release();
+                return false;
+            }
+        }
+        true
+    }
+
+    // sends the note-offs still pending right away, in the order they were
+    // due, and drops the rest
+    fn release(&mut self) {
+        while let Some(Reverse((_, _, id))) = self.heap.pop() {
+            match self.events.remove(&id) {
+                Some(event) if event.is_note_off() => self.output(event),
+                _ => {}
+            }
+        }
+        self.events.clear();
+    }
+
+    // a backend whose task has ended just misses out
+    fn output(&self, event: Event) {
+        for output in self.outputs.iter() {
+            for event in event.expand() {
+                let _ = output.send(event);
+            }
+        }
+    }
+
+    // sends what's due, returning when the next thing is
+    fn send_due(&mut self, now: Instant) -> Option<Instant> {
+        while let Some(&Reverse((at, _, id))) = self.heap.peek() {
+            if !self.events.contains_key(&id) {
+                self.heap.pop();
+                continue;
+            }
+            if at > now {
+                return Some(at);
+            }
+            self.heap.pop();
+            let event = self.events.remove(&id).unwrap();
+            self.output(event);
+        }
+        None
+    }
+}
+
+impl Future for Timer {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let timer = &mut *self;
+        loop {
+            match timer.commands.poll_recv(cx) {
+                Poll::Ready(Some(command)) => {
+                    if !timer.apply(command) {
+                        return Poll::Ready(());
+                    }
+                }
+                // every scheduler is gone, as good as a shutdown
+                Poll::Ready(None) => {
+                    timer.release();
+                    return Poll::Ready(());
+                }
+                Poll::Pending => break,
+            }
+        }
+        loop {
+            let next = match timer.send_due(Instant::now()) {
+                Some(next) => next,
+                None => return Poll::Pending,
+            };
+            timer
+                .sleep
+                .as_mut()
+                .reset(tokio::time::Instant::from_std(next));
+            if timer.sleep.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+// one backend's task: events in as they come, flushed whenever there's a
+// lull, stopped once the timer is gone
+struct Output {
+    backend: Box<dyn AsyncBackend>,
+    events: UnboundedReceiver<Event>,
+    unflushed: bool,
+    open: bool,
+}
+
+impl Output {
+    fn report(&self, result: Result<(), BackendError>) {
+        if let Err(error) = result {
+            eprintln!("[async] {}: {}", self.backend.name(), error);
+        }
+    }
+}
+
+impl Future for Output {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let output = &mut *self;
+        while output.open {
+            match output.backend.poll_ready(cx) {
+                Poll::Ready(result) => output.report(result),
+                Poll::Pending => return Poll::Pending,
+            }
+            match output.events.poll_recv(cx) {
+                Poll::Ready(Some(event)) => {
+                    let sent = output.backend.start_send(&event);
+                    output.report(sent);
+                    output.unflushed = true;
+                }
+                Poll::Ready(None) => output.open = false,
+                Poll::Pending => break,
+            }
+        }
+        if output.unflushed {
+            match output.backend.poll_flush(cx) {
+                Poll::Ready(result) => {
+                    output.report(result);
+                    output.unflushed = false;
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        if output.open {
+            return Poll::Pending;
+        }
+        let stopped = output.backend.stop();
+        output.report(stopped);
+        Poll::Ready(())
+    }
+}
+
+// the Scheduler for async applications: a task keeps time on tokio's timer
+// and a task per backend takes events over a channel, so no threads are
+// started at all. Beats are placed on the clock they're scheduled with,
+// note-offs, ratchets and probability included as with Scheduler::schedule;
+// routing, humanize, late policies and following a clock's tempo changes are
+// the threaded Scheduler's. Clones schedule on the same tasks, which end
+// with shutdown() or once the last clone is dropped
+//
+//   let scheduler = AsyncScheduler::new(vec![Box::new(Blocking(osc))]);
+//   scheduler.schedule(&clock, Event::note(60).dur_beats(0.5).at(1));
+#[derive(Clone)]
+pub struct AsyncScheduler {
+    commands: UnboundedSender<Command>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl AsyncScheduler {
+    // starts the backends and spawns the tasks on the current tokio runtime,
+    // so it has to be called from within one; backends that fail to start
+    // are reported and left out
+    pub fn new(backends: Vec<Box<dyn AsyncBackend>>) -> Self {
+        let mut outputs = vec![];
+        for mut backend in backends {
+            if let Err(error) = backend.start() {
+                eprintln!("[async] can't start {}: {}", backend.name(), error);
+                continue;
+            }
+            let (sender, events) = unbounded_channel();
+            outputs.push(sender);
+            tokio::spawn(Output {
+                backend,
+                events,
+                unflushed: false,
+                open: true,
+            });
+        }
+        let (commands, receiver) = unbounded_channel();
+        tokio::spawn(Timer {
+            commands: receiver,
+            heap: BinaryHeap::new(),
+            events: HashMap::new(),
+            outputs,
+            sleep: Box::pin(tokio::time::sleep_until(tokio::time::Instant::now())),
+        });
+        Self {
+            commands,
+            next_id: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    fn command(&self, command: Command) {
+        // the timer is gone after a shutdown, nothing's listening
+        let _ = self.commands.send(command);
+    }
+
+    pub fn schedule_at(&self, at: Instant, event: Event) -> EventId {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        if triggers(event.probability) {
+            self.command(Command::Schedule(at, id, event));
+        }
+        id
+    }
+
+    // schedules the event at its beat and tick, along with its note-off;
+    // the id returned is the event's own (the first one's for a ratchet)
+    pub fn schedule(&self, clock: &Clock, event: Event) -> EventId {
+        self.schedule_batch(clock, vec![event])[0]
+    }
+
+    pub fn schedule_batch(&self, clock: &Clock, events: Vec<Event>) -> Vec<EventId> {
+        events
+            .into_iter()
+            .map(|event| {
+                let ids: Vec<EventId> = place(clock, event)
+                    .into_iter()
+                    .map(|(at, event)| {
+                        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+                        self.command(Command::Schedule(at, id, event));
+                        id
+                    })
+                    .collect();
+                match ids.first() {
+                    Some(&id) => id,
+                    None => self.next_id.fetch_add(1, Ordering::SeqCst),
+                }
+            })
+            .collect()
+    }
+
+    pub fn cancel(&self, id: EventId) {
+        self.command(Command::Cancel(id));
+    }
+
+    // drops what's pending under `tag`, note-offs aside
+    pub fn cancel_tag(&self, tag: &str) {
+        self.command(Command::CancelTag(tag.to_string()));
+    }
+
+    // drops everything pending except note-offs, so nothing hangs
+    pub fn flush(&self) {
+        self.command(Command::Flush);
+    }
+
+    // ends the tasks, sending the note-offs still pending right away and
+    // dropping the rest; backends are flushed and stopped on their way out
+    pub fn shutdown(&self) {
+        self.command(Command::Shutdown);
+    }
+}
+
+fn triggers(probability: f64) -> bool {
+    probability >= 1.0 || rand::thread_rng().gen::<f64>() < probability
+}
+
+// the event at its beat and tick followed by its note-off, or a ratchet's
+// retriggers with theirs; nothing if the probability roll says no
+fn place(clock: &Clock, mut event: Event) -> Vec<(Instant, Event)> {
+    if event.repeats > 1 {
+        return event
+            .retriggers(clock.ppqn())
+            .into_iter()
+            .flat_map(|retrigger| place(clock, retrigger))
+            .collect();
+    }
+    // one roll for the note and its note-off together
+    if !triggers(event.probability) {
+        return vec![];
+    }
+    event.probability = 1.0;
+    let at = clock.tick_at(event.beat, event.tick);
+    let note_off = event.note_off(clock.ppqn());
+    let mut placed = vec![(at, event)];
+    if let Some(note_off) = note_off {
+        placed.push((clock.tick_at(note_off.beat, note_off.tick), note_off));
+    }
+    placed
+}
@@ -0,0 +1,179 @@
+use std::ffi::CString;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::backends::midi::{all_notes_off, find_port, panic_messages, MidiEvent};
+use crate::backends::{Backend, BackendError};
+use crate::event::{Control, Event};
+
+// sequencer ports that take MIDI from other clients, with their addresses
+fn writable_ports(seq: &alsa::seq::Seq) -> Vec<(String, alsa::seq::Addr)> {
+    let wanted = alsa::seq::PortCap::WRITE | alsa::seq::PortCap::SUBS_WRITE;
+    let mut ports = vec![];
+    for client in alsa::seq::ClientIter::new(seq) {
+        for port in alsa::seq::PortIter::new(seq, client.get_client()) {
+            if !port.get_capability().contains(wanted) {
+                continue;
+            }
+            let name = format!(
+                "{}:{}",
+                client.get_name().unwrap_or_default(),
+                port.get_name().unwrap_or_default()
+            );
+            let addr = alsa::seq::Addr {
+                client: port.get_client(),
+                port: port.get_port(),
+            };
+            ports.push((name, addr));
+        }
+    }
+    ports
+}
+
+// the sequencer as start() leaves it: our port, subscribed to the device,
+// and a running queue
+struct Session {
+    seq: alsa::seq::Seq,
+    port: i32,
+    queue: i32,
+}
+
+impl Session {
+    fn open(client_name: &str, device_name: &str) -> Result<Self, BackendError> {
+        let seq = alsa::seq::Seq::open(None, Some(alsa::Direction::Playback), false)
+            .map_err(|error| format!("can't open the ALSA sequencer: {}", error))?;
+        seq.set_client_name(&CString::new(client_name).unwrap())
+            .map_err(BackendError::new)?;
+        let port = seq
+            .create_simple_port(
+                &CString::new("out").unwrap(),
+                alsa::seq::PortCap::READ | alsa::seq::PortCap::SUBS_READ,
+                alsa::seq::PortType::MIDI_GENERIC | alsa::seq::PortType::APPLICATION,
+            )
+            .map_err(BackendError::new)?;
+
+        let ports = writable_ports(&seq);
+        let names: Vec<String> = ports.iter().map(|(name, _)| name.clone()).collect();
+        let index = find_port(device_name, &names)?;
+        let subscription = alsa::seq::PortSubscribe::empty().map_err(BackendError::new)?;
+        subscription.set_sender(alsa::seq::Addr {
+            client: seq.client_id().map_err(BackendError::new)?,
+            port,
+        });
+        subscription.set_dest(ports[index].1);
+        seq.subscribe_port(&subscription)
+            .map_err(BackendError::new)?;
+        println!("[alsa] sending to {}", names[index]);
+
+        let queue = seq.alloc_queue().map_err(BackendError::new)?;
+        seq.control_queue(queue, alsa::seq::EventType::Start, 0, None)
+            .map_err(BackendError::new)?;
+        seq.drain_output().map_err(BackendError::new)?;
+        Ok(Self { seq, port, queue })
+    }
+
+    // buffered until drain_output(), relative to the queue's clock right
+    // now; the kernel takes it from there
+    fn output(&self, midi: &[u8], delay: Duration) -> Result<(), alsa::Error> {
+        let mut encoder = alsa::seq::MidiEvent::new(256)?;
+        let mut event = match encoder.encode(midi)? {
+            (_, Some(event)) => event,
+            (_, None) => return Ok(()),
+        };
+        event.set_source(self.port);
+        event.set_subs();
+        event.schedule_real(self.queue, true, delay);
+        self.seq.event_output(&mut event)?;
+        Ok(())
+    }
+}
+
+// MIDI out through the ALSA sequencer (Linux only): every message is handed
+// to the kernel with a timestamp on a queue of our own, and the kernel sends
+// it when it's due, so delivery doesn't depend on this thread waking up on
+// time; `delay` has every message land that much after it was due to be
+// sent, so pair it
+// with the same send-ahead on the scheduler (Scheduler::set_offset) for
+// events to sound on time
+pub struct AlsaSeqBackend {
+    pub device_name: String,
+    pub client_name: String,
+    pub delay: Duration,
+    session: Option<Session>,
+}
+
+impl AlsaSeqBackend {
+    pub fn new(device_name: &str) -> Self {
+        Self {
+            device_name: device_name.to_string(),
+            client_name: "tonic".to_string(),
+            delay: Duration::from_millis(10),
+            session: None,
+        }
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+impl Backend for AlsaSeqBackend {
+    fn start(&mut self) -> Result<(), BackendError> {
+        self.session = Some(Session::open(&self.client_name, &self.device_name)?);
+        Ok(())
+    }
+
+    fn send(&mut self, event: &Event) -> Result<(), BackendError> {
+        self.send_at(event, Instant::now())
+    }
+
+    // queued for `delay` after the event was due rather than after it got
+    // here, so the jitter of the threads on the way doesn't reach the kernel
+    fn send_at(&mut self, event: &Event, due: Instant) -> Result<(), BackendError> {
+        let messages: Vec<Vec<u8>> = match event.control() {
+            Some(Control::Panic) => panic_messages(),
+            _ => event.to_midi_messages(),
+        };
+        if messages.is_empty() {
+            return Ok(());
+        }
+        let session = self.session.as_ref().ok_or("sequencer not open")?;
+        let delay = (due + self.delay).saturating_duration_since(Instant::now());
+        for midi in messages {
+            session.output(&midi, delay).map_err(BackendError::new)?;
+        }
+        Ok(())
+    }
+
+    // events due together reach the kernel in one go
+    fn flush(&mut self) -> Result<(), BackendError> {
+        if let Some(session) = self.session.as_ref() {
+            session.seq.drain_output().map_err(BackendError::new)?;
+        }
+        Ok(())
+    }
+
+    // silences everything after whatever is still queued, and waits for the
+    // kernel to get through it
+    fn stop(&mut self) -> Result<(), BackendError> {
+        let session = match self.session.take() {
+            Some(session) => session,
+            None => return Ok(()),
+        };
+        for midi in all_notes_off() {
+            let _ = session.output(&midi, self.delay);
+        }
+        let _ = session.seq.drain_output();
+        let _ = session.seq.sync_output_queue();
+        thread::sleep(self.delay);
+        session
+            .seq
+            .free_queue(session.queue)
+            .map_err(BackendError::new)
+    }
+
+    fn name(&self) -> String {
+        self.device_name.clone()
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use crate::backends::{Backend, BackendError};
+use crate::event::{Control, Event, Message, Payload};
+
+pub const ARTNET_PORT: u16 = 6454;
+const OP_DMX: u16 = 0x5000;
+const PROTOCOL_VERSION: u16 = 14;
+const CHANNELS: usize = 512;
+// nodes hold the last frame but expect to hear from us now and then
+const REFRESH: Duration = Duration::from_secs(1);
+
+// an ArtDmx packet carrying the whole universe
+fn dmx_packet(universe: u16, sequence: u8, levels: &[u8; CHANNELS]) -> Vec<u8> {
+    let mut packet = b"Art-Net\0".to_vec();
+    packet.extend(&OP_DMX.to_le_bytes());
+    packet.extend(&PROTOCOL_VERSION.to_be_bytes());
+    packet.push(sequence);
+    // physical input port, informational only
+    packet.push(0);
+    // sub-net and universe in the low byte, net in the high one
+    packet.extend(&universe.to_le_bytes());
+    packet.extend(&(CHANNELS as u16).to_be_bytes());
+    packet.extend(levels.iter());
+    packet
+}
+
+// MIDI 0-127 to DMX 0-255
+fn level(value: u8) -> u8 {
+    (value.min(127) as u16 * 255 / 127) as u8
+}
+
+// lights on the same clock as the music, over Art-Net: a note sets the DMX
+// channel it's mapped to, its velocity becoming the intensity and its
+// note-off dropping it back to 0; a mapped CC sets its channel to the value.
+// The universe is sent whole on every change and once a second besides, and
+// blacked out when the backend stops
+pub struct ArtNetBackend {
+    // host:port of the node, or the broadcast address, e.g. "2.255.255.255:6454"
+    pub target: String,
+    // 15 bits: net, sub-net and universe
+    pub universe: u16,
+    // note to DMX channel, 1-512
+    pub notes: HashMap<u8, u16>,
+    // controller to DMX channel, 1-512
+    pub controls: HashMap<u8, u16>,
+    socket: Option<UdpSocket>,
+    levels: [u8; CHANNELS],
+    // 0 would tell the node not to reorder, so it runs 1-255
+    sequence: u8,
+    muted: bool,
+}
+
+impl ArtNetBackend {
+    pub fn new(target: &str, universe: u16) -> Self {
+        Self {
+            target: target.to_string(),
+            universe: universe & 0x7FFF,
+            notes: HashMap::new(),
+            controls: HashMap::new(),
+            socket: None,
+            levels: [0; CHANNELS],
+            sequence: 0,
+            muted: false,
+        }
+    }
+
+    pub fn map_note(mut self, note: u8, channel: u16) -> Self {
+        self.notes.insert(note, channel);
+        self
+    }
+
+    // `count` notes from `first_note` on, to consecutive channels from
+    // `first_channel`, e.g. one octave to the dimmers of six fixtures
+    pub fn map_notes(mut self, first_note: u8, first_channel: u16, count: u8) -> Self {
+        for i in 0..count {
+            self.notes
+                .insert(first_note.saturating_add(i), first_channel + i as u16);
+        }
+        self
+    }
+
+    pub fn map_control(mut self, controller: u8, channel: u16) -> Self {
+        self.controls.insert(controller, channel);
+        self
+    }
+
+    fn transmit(&mut self, levels: &[u8; CHANNELS]) -> Result<(), BackendError> {
+        let socket = self.socket.as_ref().ok_or("socket not open")?;
+        self.sequence = self.sequence % 255 + 1;
+        socket.send_to(
+            &dmx_packet(self.universe, self.sequence, levels),
+            &self.target,
+        )?;
+        Ok(())
+    }
+
+    // every mapped channel back to 0, whatever else is in the universe stays
+    fn panic(&mut self) -> Result<(), BackendError> {
+        for channel in self.notes.values().chain(self.controls.values()) {
+            if *channel >= 1 && *channel as usize <= CHANNELS {
+                self.levels[*channel as usize - 1] = 0;
+            }
+        }
+        let levels = self.levels;
+        self.transmit(&levels)
+    }
+}
+
+impl Backend for ArtNetBackend {
+    fn start(&mut self) -> Result<(), BackendError> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.set_broadcast(true)?;
+        self.socket = Some(socket);
+        self.levels = [0; CHANNELS];
+        self.muted = false;
+        Ok(())
+    }
+
+    fn send(&mut self, event: &Event) -> Result<(), BackendError> {
+        let (channel, value) = match &event.payload {
+            Payload::Control(Control::Mute) => {
+                self.muted = true;
+                return Ok(());
+            }
+            Payload::Control(Control::Unmute) => {
+                self.muted = false;
+                return Ok(());
+            }
+            Payload::Control(Control::Panic) => return self.panic(),
+            Payload::Midi(Message::NoteOff { note, .. }) => match self.notes.get(note) {
+                Some(channel) => (*channel, 0),
+                None => return Ok(()),
+            },
+            Payload::Midi(Message::NoteOn { note, velocity }) => match self.notes.get(note) {
+                Some(_) if self.muted && *velocity > 0 => return Ok(()),
+                Some(channel) => (*channel, level(*velocity)),
+                None => return Ok(()),
+            },
+            Payload::Midi(Message::ControlChange { controller, value }) if !self.muted => {
+                match self.controls.get(controller) {
+                    Some(channel) => (*channel, level(*value)),
+                    None => return Ok(()),
+                }
+            }
+            _ => return Ok(()),
+        };
+        if channel == 0 || channel as usize > CHANNELS {
+            return Ok(());
+        }
+        self.levels[channel as usize - 1] = value;
+        let levels = self.levels;
+        self.transmit(&levels)
+    }
+
+    fn poll_interval(&self) -> Option<Duration> {
+        Some(REFRESH)
+    }
+
+    fn poll(&mut self) -> Result<(), BackendError> {
+        let levels = self.levels;
+        self.transmit(&levels)
+    }
+
+    // lights out
+    fn stop(&mut self) -> Result<(), BackendError> {
+        let blackout = self.transmit(&[0; CHANNELS]);
+        self.socket = None;
+        blackout
+    }
+
+    fn name(&self) -> String {
+        self.target.clone()
+    }
+}
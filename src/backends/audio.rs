@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+use crate::backends::{Backend, BackendError};
+use crate::event::{Control, Event, Message, Payload};
+
+// notes sounding at once; a new one past this cuts off the oldest, so notes
+// that never get a note-off can't pile up
+const MAX_VOICES: usize = 32;
+// how long stop() waits for sounding notes to ring out
+const RING_OUT: Duration = Duration::from_millis(500);
+const ATTACK_SECS: f32 = 0.005;
+const RELEASE_SECS: f32 = 0.05;
+
+fn frequency(note: u8) -> f32 {
+    440.0 * 2f32.powf((note as f32 - 69.0) / 12.0)
+}
+
+// what a channel sounds like
+#[derive(Clone)]
+pub enum Voice {
+    Sine,
+    Square,
+    // mono samples at `rate`, played back at pitch relative to `root`
+    Sampler {
+        samples: Arc<Vec<f32>>,
+        rate: u32,
+        root: u8,
+    },
+}
+
+struct Playing {
+    channel: u8,
+    note: u8,
+    voice: Voice,
+    amplitude: f32,
+    // cycles for the oscillators, a position in the samples for the sampler
+    phase: f32,
+    step: f32,
+    // 0.0-1.0, rising during the attack and falling once released
+    level: f32,
+    released: bool,
+}
+
+impl Playing {
+    fn new(channel: u8, note: u8, velocity: u8, voice: Voice, out_rate: u32) -> Self {
+        let step = match &voice {
+            Voice::Sampler { rate, root, .. } => {
+                frequency(note) / frequency(*root) * *rate as f32 / out_rate as f32
+            }
+            _ => frequency(note) / out_rate as f32,
+        };
+        Self {
+            channel,
+            note,
+            voice,
+            amplitude: velocity as f32 / 127.0,
+            phase: 0.0,
+            step,
+            level: 0.0,
+            released: false,
+        }
+    }
+
+    fn finished(&self) -> bool {
+        let past_end = match &self.voice {
+            Voice::Sampler { samples, .. } => self.phase as usize >= samples.len(),
+            _ => false,
+        };
+        past_end || (self.released && self.level <= 0.0)
+    }
+
+    fn next(&mut self, out_rate: u32) -> f32 {
+        let rate = out_rate as f32;
+        if self.released {
+            self.level -= 1.0 / (RELEASE_SECS * rate);
+        } else if self.level < 1.0 {
+            self.level = (self.level + 1.0 / (ATTACK_SECS * rate)).min(1.0);
+        }
+        let value = match &self.voice {
+            Voice::Sine => (self.phase * 2.0 * PI).sin(),
+            Voice::Square if self.phase < 0.5 => 1.0,
+            Voice::Square => -1.0,
+            Voice::Sampler { samples, .. } => {
+                samples.get(self.phase as usize).cloned().unwrap_or(0.0)
+            }
+        };
+        self.phase += self.step;
+        if !matches!(self.voice, Voice::Sampler { .. }) {
+            self.phase = self.phase.fract();
+        }
+        value * self.amplitude * self.level.max(0.0)
+    }
+}
+
+// what the audio callback keeps between calls: the notes sounding and
+// the events on their way in
+struct Synth {
+    events: Receiver<Event>,
+    voices: HashMap<u8, Voice>,
+    volume: f32,
+    muted: bool,
+    out_rate: u32,
+    playing: Vec<Playing>,
+    // until stop(), after which the notes ring out and `done` is told
+    open: bool,
+    done: Option<Sender<()>>,
+}
+
+impl Synth {
+    // takes whatever arrived since the last call, which is when it's heard
+    fn receive(&mut self) {
+        while self.open {
+            let event = match self.events.try_recv() {
+                Ok(event) => event,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.open = false;
+                    self.playing
+                        .iter_mut()
+                        .for_each(|note| note.released = true);
+                    break;
+                }
+            };
+            let message = match &event.payload {
+                Payload::Control(Control::Mute) => {
+                    self.muted = true;
+                    continue;
+                }
+                Payload::Control(Control::Unmute) => {
+                    self.muted = false;
+                    continue;
+                }
+                Payload::Control(Control::SetVolume(value)) => {
+                    self.volume = *value as f32;
+                    continue;
+                }
+                Payload::Control(Control::Panic) => {
+                    self.playing.clear();
+                    continue;
+                }
+                Payload::Midi(message) => *message,
+                _ => continue,
+            };
+            let channel = event.channel;
+            match message {
+                Message::NoteOn { note, velocity: 0 } | Message::NoteOff { note, .. } => {
+                    self.playing
+                        .iter_mut()
+                        .filter(|playing| playing.channel == channel && playing.note == note)
+                        .for_each(|playing| playing.released = true);
+                }
+                Message::NoteOn { note, velocity } if !self.muted => {
+                    if self.playing.len() >= MAX_VOICES {
+                        self.playing.remove(0);
+                    }
+                    let voice = self.voices.get(&channel).cloned().unwrap_or(Voice::Sine);
+                    let playing = Playing::new(channel, note, velocity, voice, self.out_rate);
+                    self.playing.push(playing);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // the same mono mix on every channel of each frame
+    fn render<T: cpal::SizedSample + cpal::FromSample<f32>>(
+        &mut self,
+        data: &mut [T],
+        channels: usize,
+    ) {
+        self.receive();
+        let out_rate = self.out_rate;
+        for frame in data.chunks_mut(channels) {
+            let mixed: f32 = self
+                .playing
+                .iter_mut()
+                .map(|note| note.next(out_rate))
+                .sum();
+            let value = T::from_sample((mixed * self.volume).clamp(-1.0, 1.0));
+            frame.iter_mut().for_each(|sample| *sample = value);
+        }
+        self.playing.retain(|note| !note.finished());
+        if !self.open && self.playing.is_empty() {
+            if let Some(done) = self.done.take() {
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+fn output_device(name: &str) -> Result<cpal::Device, BackendError> {
+    let host = cpal::default_host();
+    if name == "default" {
+        return host
+            .default_output_device()
+            .ok_or_else(|| BackendError::from("no default audio output"));
+    }
+    host.output_devices()
+        .map_err(BackendError::new)?
+        .find(|device| device.name().is_ok_and(|device_name| device_name == name))
+        .ok_or_else(|| format!("no audio output called {}", name).into())
+}
+
+fn build_stream<T: cpal::SizedSample + cpal::FromSample<f32>>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut synth: Synth,
+) -> Result<cpal::Stream, BackendError> {
+    let channels = config.channels as usize;
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| synth.render(data, channels),
+            |error| eprintln!("[audio] {}", error),
+            None,
+        )
+        .map_err(BackendError::new)
+}
+
+// opens the device at `sample_rate` if it can do that, at its own rate if
+// not, and starts it playing
+fn open_stream(
+    device_name: &str,
+    sample_rate: u32,
+    make_synth: impl FnOnce(u32) -> Synth,
+) -> Result<cpal::Stream, BackendError> {
+    let device = output_device(device_name)
+        .map_err(|error| format!("can't open audio device {}: {}", device_name, error))?;
+    let default = device.default_output_config().map_err(BackendError::new)?;
+    let supported = device
+        .supported_output_configs()
+        .map_err(BackendError::new)?
+        .find(|range| {
+            range.sample_format() == default.sample_format()
+                && range.channels() == default.channels()
+                && (range.min_sample_rate().0..=range.max_sample_rate().0).contains(&sample_rate)
+        })
+        .map(|range| range.with_sample_rate(cpal::SampleRate(sample_rate)))
+        .unwrap_or(default);
+    let config = supported.config();
+    let synth = make_synth(config.sample_rate.0);
+    let stream = match supported.sample_format() {
+        cpal::SampleFormat::F32 => build_stream::<f32>(&device, &config, synth),
+        cpal::SampleFormat::I16 => build_stream::<i16>(&device, &config, synth),
+        cpal::SampleFormat::U16 => build_stream::<u16>(&device, &config, synth),
+        format => Err(format!("unsupported sample format {}", format).into()),
+    }?;
+    stream.play().map_err(BackendError::new)?;
+    Ok(stream)
+}
+
+// plays notes itself on the default audio output (or the one named), no MIDI
+// routing or synth needed; each channel gets a voice, sine unless told
+// otherwise. Notes are mixed in the audio callback, heard from the next
+// buffer after they arrive
+pub struct AudioBackend {
+    pub device_name: String,
+    // asked for, the device's own where it can't do it
+    pub sample_rate: u32,
+    // 0.0-1.0, shared by every note so chords don't clip as easily
+    pub volume: f32,
+    pub voices: HashMap<u8, Voice>,
+    // to the audio callback
+    events: Option<Sender<Event>>,
+    // hung up on by stop()
+    running: Option<Sender<()>>,
+    // holds the stream, which can't move between threads on every platform
+    renderer: Option<JoinHandle<()>>,
+}
+
+impl AudioBackend {
+    pub fn new() -> Self {
+        Self {
+            device_name: "default".to_string(),
+            sample_rate: 44100,
+            volume: 0.25,
+            voices: HashMap::new(),
+            events: None,
+            running: None,
+            renderer: None,
+        }
+    }
+
+    pub fn with_device(mut self, device_name: &str) -> Self {
+        self.device_name = device_name.to_string();
+        self
+    }
+
+    pub fn with_voice(mut self, channel: u8, voice: Voice) -> Self {
+        self.voices.insert(channel, voice);
+        self
+    }
+}
+
+impl Default for AudioBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Backend for AudioBackend {
+    fn start(&mut self) -> Result<(), BackendError> {
+        self.stop()?;
+        let (sender, events) = channel::<Event>();
+        let (running, stopped) = channel::<()>();
+        let (started, ready) = channel();
+        let device_name = self.device_name.clone();
+        let sample_rate = self.sample_rate;
+        let voices = self.voices.clone();
+        let volume = self.volume;
+
+        let renderer = thread::spawn(move || {
+            let (done, finished) = channel();
+            let opened = open_stream(&device_name, sample_rate, |out_rate| Synth {
+                events,
+                voices,
+                volume,
+                muted: false,
+                out_rate,
+                // never grows past this, so the callback doesn't allocate
+                playing: Vec::with_capacity(MAX_VOICES),
+                open: true,
+                done: Some(done),
+            });
+            let stream = match opened {
+                Ok(stream) => stream,
+                Err(error) => {
+                    let _ = started.send(Err(error));
+                    return;
+                }
+            };
+            let _ = started.send(Ok(()));
+            let _ = stopped.recv();
+            let _ = finished.recv_timeout(RING_OUT);
+            drop(stream);
+        });
+        ready
+            .recv()
+            .map_err(|_| BackendError::from("audio thread died"))??;
+        self.events = Some(sender);
+        self.running = Some(running);
+        self.renderer = Some(renderer);
+        Ok(())
+    }
+
+    fn send(&mut self, event: &Event) -> Result<(), BackendError> {
+        let events = self.events.as_ref().ok_or("audio device not open")?;
+        events
+            .send(event.clone())
+            .map_err(|_| BackendError::from("lost the audio device"))
+    }
+
+    // lets sounding notes ring out through their release
+    fn stop(&mut self) -> Result<(), BackendError> {
+        self.events = None;
+        self.running = None;
+        if let Some(renderer) = self.renderer.take() {
+            let _ = renderer.join();
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        self.device_name.clone()
+    }
+}
@@ -1,20 +1,18 @@
-use std::sync::mpsc::Receiver;
-use std::thread;
-
-use crate::backends::Backend;
+use crate::backends::{Backend, BackendError};
 use crate::event::Event;
 
+#[derive(Default)]
 pub struct DummyBackend;
 
+impl DummyBackend {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
 impl Backend for DummyBackend {
-    fn run(&self, receiver: Receiver<Event>) {
-        thread::spawn(move || loop {
-            match receiver.recv() {
-                Ok(event) => {
-                    println!("[dummy] got event: {:?}", event);
-                }
-                Err(_) => {}
-            }
-        });
+    fn send(&mut self, event: &Event) -> Result<(), BackendError> {
+        println!("[dummy] got event: {:?}", event);
+        Ok(())
     }
 }
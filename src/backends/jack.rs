@@ -0,0 +1,245 @@
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_ulong, c_void};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::backends::midi::{all_notes_off, panic_messages, MidiEvent};
+use crate::backends::{Backend, BackendError};
+use crate::event::{Control, Event};
+
+type Frames = u32;
+// microseconds on JACK's clock
+type JackTime = u64;
+
+const NO_START_SERVER: c_int = 0x01;
+const PORT_IS_OUTPUT: c_ulong = 0x2;
+const MIDI_TYPE: &str = "8 bit raw midi";
+
+enum Client {}
+enum Port {}
+
+type ProcessCallback = extern "C" fn(Frames, *mut c_void) -> c_int;
+
+#[link(name = "jack")]
+extern "C" {
+    fn jack_client_open(
+        name: *const c_char,
+        options: c_int,
+        status: *mut c_int,
+        ...
+    ) -> *mut Client;
+    fn jack_client_close(client: *mut Client) -> c_int;
+    fn jack_port_register(
+        client: *mut Client,
+        name: *const c_char,
+        port_type: *const c_char,
+        flags: c_ulong,
+        buffer_size: c_ulong,
+    ) -> *mut Port;
+    fn jack_set_process_callback(
+        client: *mut Client,
+        callback: ProcessCallback,
+        arg: *mut c_void,
+    ) -> c_int;
+    fn jack_activate(client: *mut Client) -> c_int;
+    fn jack_deactivate(client: *mut Client) -> c_int;
+    fn jack_port_get_buffer(port: *mut Port, frames: Frames) -> *mut c_void;
+    fn jack_midi_clear_buffer(buffer: *mut c_void);
+    fn jack_midi_event_write(
+        buffer: *mut c_void,
+        time: Frames,
+        data: *const u8,
+        size: usize,
+    ) -> c_int;
+    fn jack_last_frame_time(client: *const Client) -> Frames;
+    fn jack_time_to_frames(client: *const Client, time: JackTime) -> Frames;
+    fn jack_get_time() -> JackTime;
+}
+
+// what the process callback reads: messages with the JACK time they're due
+struct Shared {
+    client: *mut Client,
+    port: *mut Port,
+    queue: Mutex<VecDeque<(JackTime, Vec<u8>)>>,
+}
+
+// the pointers are only used through JACK, which is thread safe
+unsafe impl Send for Shared {}
+unsafe impl Sync for Shared {}
+
+// runs on JACK's realtime thread once per period: never blocks, a queue
+// that's busy right now is simply looked at next period
+extern "C" fn process(frames: Frames, arg: *mut c_void) -> c_int {
+    let shared = unsafe { &*(arg as *const Shared) };
+    unsafe {
+        let buffer = jack_port_get_buffer(shared.port, frames);
+        jack_midi_clear_buffer(buffer);
+        let cycle_start = jack_last_frame_time(shared.client);
+        let mut queue = match shared.queue.try_lock() {
+            Ok(queue) => queue,
+            Err(_) => return 0,
+        };
+        while let Some((at, _)) = queue.front() {
+            // frame counts wrap, so compare as an offset into this period
+            let offset = jack_time_to_frames(shared.client, *at).wrapping_sub(cycle_start) as i32;
+            if offset >= frames as i32 {
+                break;
+            }
+            let (_, midi) = queue.pop_front().unwrap();
+            // late messages go out at the start of the period
+            let offset = offset.max(0) as Frames;
+            jack_midi_event_write(buffer, offset, midi.as_ptr(), midi.len());
+        }
+    }
+    0
+}
+
+// `at` on JACK's clock, in microseconds; already gone is right now
+fn jack_time_at(at: Instant) -> JackTime {
+    let now = unsafe { jack_get_time() };
+    now + at.saturating_duration_since(Instant::now()).as_micros() as JackTime
+}
+
+// MIDI out through JACK, each message placed on the exact frame it's due
+// rather than wherever the period happens to start; `delay` has every message
+// land that much after it was due to be sent, so pair it with the same send-ahead on
+// the scheduler (Scheduler::set_offset) for events to sound on time.
+// requires the `jack` feature and a running JACK server
+pub struct JackBackend {
+    pub client_name: String,
+    pub port_name: String,
+    pub delay: Duration,
+    shared: Option<Arc<Shared>>,
+}
+
+impl JackBackend {
+    pub fn new(client_name: &str) -> Self {
+        Self {
+            client_name: client_name.to_string(),
+            port_name: "midi_out".to_string(),
+            delay: Duration::from_millis(10),
+            shared: None,
+        }
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    // takes the process callback out of JACK's hands before `shared` can go
+    fn close(&mut self) {
+        if let Some(shared) = self.shared.take() {
+            unsafe {
+                jack_deactivate(shared.client);
+                jack_client_close(shared.client);
+            }
+        }
+    }
+}
+
+impl Backend for JackBackend {
+    fn start(&mut self) -> Result<(), BackendError> {
+        // restarted without a stop, e.g. after a panic
+        self.close();
+        let name = CString::new(self.client_name.as_str()).unwrap();
+        let port_name = CString::new(self.port_name.as_str()).unwrap();
+        let midi_type = CString::new(MIDI_TYPE).unwrap();
+        let mut status = 0;
+        let client = unsafe { jack_client_open(name.as_ptr(), NO_START_SERVER, &mut status) };
+        if client.is_null() {
+            return Err(BackendError(format!(
+                "can't open JACK client {} (status {:#x})",
+                self.client_name, status
+            )));
+        }
+        let port = unsafe {
+            jack_port_register(
+                client,
+                port_name.as_ptr(),
+                midi_type.as_ptr(),
+                PORT_IS_OUTPUT,
+                0,
+            )
+        };
+        if port.is_null() {
+            unsafe { jack_client_close(client) };
+            return Err(BackendError(format!(
+                "can't register JACK port {}",
+                self.port_name
+            )));
+        }
+        let shared = Arc::new(Shared {
+            client,
+            port,
+            queue: Mutex::new(VecDeque::new()),
+        });
+        let arg = Arc::as_ptr(&shared) as *mut c_void;
+        let activated = unsafe {
+            jack_set_process_callback(client, process, arg) == 0 && jack_activate(client) == 0
+        };
+        if !activated {
+            unsafe { jack_client_close(client) };
+            return Err(BackendError(format!(
+                "can't activate JACK client {}",
+                self.client_name
+            )));
+        }
+        self.shared = Some(shared);
+        Ok(())
+    }
+
+    fn send(&mut self, event: &Event) -> Result<(), BackendError> {
+        self.send_at(event, Instant::now())
+    }
+
+    // timestamped from when the event was due rather than when it got here
+    fn send_at(&mut self, event: &Event, due: Instant) -> Result<(), BackendError> {
+        let messages: Vec<Vec<u8>> = match event.control() {
+            Some(Control::Panic) => panic_messages(),
+            _ => event.to_midi_messages(),
+        };
+        if messages.is_empty() {
+            return Ok(());
+        }
+        let shared = self.shared.as_ref().ok_or("JACK client not open")?;
+        let at = jack_time_at(due + self.delay);
+        shared
+            .queue
+            .lock()
+            .unwrap()
+            .extend(messages.into_iter().map(|msg| (at, msg)));
+        Ok(())
+    }
+
+    // silences everything and gives JACK a moment to play it before going
+    // away
+    fn stop(&mut self) -> Result<(), BackendError> {
+        let shared = match self.shared.as_ref() {
+            Some(shared) => shared,
+            None => return Ok(()),
+        };
+        let at = unsafe { jack_get_time() };
+        shared
+            .queue
+            .lock()
+            .unwrap()
+            .extend(all_notes_off().into_iter().map(|msg| (at, msg)));
+        thread::sleep(Duration::from_millis(100) + self.delay);
+        self.close();
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        self.client_name.clone()
+    }
+}
+
+// dropped without a stop, e.g. along with a config that's been replaced
+impl Drop for JackBackend {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
@@ -0,0 +1,139 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::backends::{Backend, BackendError};
+use crate::clock::Clock;
+use crate::event::Event;
+use crate::json;
+
+const CSV_HEADER: &str = "wall_clock,position,beat,tick,channel,tag,payload";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum LogFormat {
+    Csv,
+    // one JSON object per line: wall_clock, position and the whole event
+    JsonLines,
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    wall_clock: String,
+    position: Option<f64>,
+    event: &'a Event,
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// appends every event it gets to a file, with the wall-clock time it arrived
+// and the beat and tick it was due on; given the clock, also the beat
+// position it actually arrived at, so position minus beat is how late it
+// was. For chasing timing problems and for looking at what a generator
+// produced after the fact
+pub struct LogBackend {
+    pub path: PathBuf,
+    pub format: LogFormat,
+    pub clock: Option<Arc<RwLock<Clock>>>,
+    file: Option<BufWriter<File>>,
+}
+
+impl LogBackend {
+    pub fn new<P: Into<PathBuf>>(path: P, format: LogFormat) -> Self {
+        Self {
+            path: path.into(),
+            format,
+            clock: None,
+            file: None,
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<RwLock<Clock>>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+}
+
+impl Backend for LogBackend {
+    fn start(&mut self) -> Result<(), BackendError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|error| format!("can't open {}: {}", self.path.display(), error))?;
+        let fresh = file.metadata().map(|meta| meta.len()).unwrap_or(0) == 0;
+        let mut file = BufWriter::new(file);
+        // a fresh CSV file gets its column names
+        if self.format == LogFormat::Csv && fresh {
+            writeln!(file, "{}", CSV_HEADER)?;
+        }
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn send(&mut self, event: &Event) -> Result<(), BackendError> {
+        let wall_clock = chrono::Utc::now()
+            .format("%Y-%m-%dT%H:%M:%S%.6fZ")
+            .to_string();
+        let position = self
+            .clock
+            .as_ref()
+            .map(|clock| clock.read().unwrap().position());
+        let line = match self.format {
+            LogFormat::Csv => format!(
+                "{},{},{},{},{},{},{}",
+                wall_clock,
+                position
+                    .map(|position| position.to_string())
+                    .unwrap_or_default(),
+                event.beat,
+                event.tick,
+                event.channel,
+                csv_field(event.tag.as_deref().unwrap_or("")),
+                csv_field(&format!("{:?}", event.payload)),
+            ),
+            LogFormat::JsonLines => {
+                let entry = Entry {
+                    wall_clock,
+                    position,
+                    event,
+                };
+                // custom payloads don't serialize
+                match json::to_string(&entry) {
+                    Ok(line) => line,
+                    Err(_) => return Ok(()),
+                }
+            }
+        };
+        let file = self.file.as_mut().ok_or("log not open")?;
+        Ok(writeln!(file, "{}", line)?)
+    }
+
+    // written out whenever the scheduler has nothing else for it, so a crash
+    // takes at most the last few events with it
+    fn flush(&mut self) -> Result<(), BackendError> {
+        if let Some(file) = self.file.as_mut() {
+            file.flush()?;
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), BackendError> {
+        match self.file.take() {
+            Some(mut file) => Ok(file.flush()?),
+            None => Ok(()),
+        }
+    }
+
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+}
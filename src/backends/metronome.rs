@@ -0,0 +1,115 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::backends::midi::{try_open_output, NOTE_OFF_MSG, NOTE_ON_MSG};
+use crate::backends::{Backend, BackendError};
+use crate::clock_service::{ClockService, Tick};
+use crate::dead_letter;
+use crate::event::{Control, Event, Message, DEFAULT_VELOCITY};
+
+// how often the ticking thread looks whether it was stopped, the clock
+// being paused perhaps
+const STOP_POLL: Duration = Duration::from_millis(50);
+
+// clicks on every beat of the clock, accenting the first beat of each bar;
+// without a device it rings the terminal bell instead. Clicks follow the
+// clock's ticks on a thread of their own, events only mute and unmute it
+pub struct MetronomeBackend {
+    pub clock: ClockService,
+    pub device_name: Option<String>,
+    pub accent: u8,
+    pub click: u8,
+    muted: Arc<AtomicBool>,
+    running: Arc<AtomicBool>,
+    ticker: Option<JoinHandle<()>>,
+}
+
+impl MetronomeBackend {
+    // GM hi/low wood block
+    pub fn new(clock: ClockService, device_name: Option<String>) -> Self {
+        Self {
+            clock,
+            device_name,
+            accent: 76,
+            click: 77,
+            muted: Arc::new(AtomicBool::new(false)),
+            running: Arc::new(AtomicBool::new(false)),
+            ticker: None,
+        }
+    }
+}
+
+impl Backend for MetronomeBackend {
+    fn start(&mut self) -> Result<(), BackendError> {
+        let mut out = match self.device_name.as_ref() {
+            Some(name) => Some(try_open_output(name)?),
+            None => None,
+        };
+        let ticks = self.clock.subscribe();
+        let (accent, click) = (self.accent, self.click);
+        let muted = self.muted.clone();
+        // a fresh flag, so a ticker still winding down stays stopped
+        let running = Arc::new(AtomicBool::new(true));
+        self.running = running.clone();
+
+        self.ticker = Some(thread::spawn(move || {
+            let mut downbeat = false;
+            while running.load(Ordering::SeqCst) {
+                let beat = match ticks.recv_timeout(STOP_POLL) {
+                    Ok(Tick::BarTick(_)) => {
+                        downbeat = true;
+                        continue;
+                    }
+                    Ok(Tick::BeatTick(beat)) => beat,
+                    Ok(Tick::Seek(_)) | Err(RecvTimeoutError::Timeout) => continue,
+                    // the clock shut down
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                let note = if downbeat { accent } else { click };
+                match out.as_mut() {
+                    _ if muted.load(Ordering::SeqCst) => {}
+                    Some(out) => {
+                        let sent = out
+                            .send(&[NOTE_ON_MSG, note, DEFAULT_VELOCITY])
+                            .and_then(|_| out.send(&[NOTE_OFF_MSG, note, 0]));
+                        if let Err(error) = sent {
+                            let click = Event::new(Message::note(note), beat);
+                            dead_letter::post(click, "metronome", error);
+                        }
+                    }
+                    None => {
+                        let sound = if downbeat { "TOCK" } else { "tick" };
+                        println!("[metronome] {} {}\x07", sound, beat);
+                    }
+                }
+                downbeat = false;
+            }
+        }));
+        Ok(())
+    }
+
+    // only controls are for us
+    fn send(&mut self, event: &Event) -> Result<(), BackendError> {
+        match event.control() {
+            Some(Control::Mute) => self.muted.store(true, Ordering::SeqCst),
+            Some(Control::Unmute) => self.muted.store(false, Ordering::SeqCst),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), BackendError> {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(ticker) = self.ticker.take() {
+            let _ = ticker.join();
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        "metronome".to_string()
+    }
+}
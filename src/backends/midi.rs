@@ -1,50 +1,679 @@
-use std::sync::mpsc::Receiver;
-use std::thread;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::time::{Duration, Instant};
 
-use crate::backends::Backend;
-use crate::event::Event;
+use serde::Deserialize;
 
-const NOTE_ON_MSG: u8 = 0x90;
-const NOTE_OFF_MSG: u8 = 0x80;
-const VELOCITY: u8 = 0x64;
+use crate::backends::mpe::MpeZone;
+use crate::backends::{Backend, BackendError};
+use crate::dead_letter;
+use crate::event::{Control, Event, Expression, Message, Payload};
 
-trait MidiEvent {
-    fn to_midi(&self) -> [u8; 3];
+pub const NOTE_OFF_MSG: u8 = 0x80;
+pub const NOTE_ON_MSG: u8 = 0x90;
+pub const POLY_PRESSURE_MSG: u8 = 0xA0;
+pub const CONTROL_CHANGE_MSG: u8 = 0xB0;
+pub const PROGRAM_CHANGE_MSG: u8 = 0xC0;
+pub const CHANNEL_PRESSURE_MSG: u8 = 0xD0;
+pub const PITCH_BEND_MSG: u8 = 0xE0;
+pub const SYSEX_START: u8 = 0xF0;
+pub const SYSEX_END: u8 = 0xF7;
+
+// what virtual_port() is usually called, for DAWs to look for
+pub const VIRTUAL_PORT_NAME: &str = "Tonic Out";
+
+// how often a MidiBackend checks its device is still there, or back
+pub const HOTPLUG_POLL: Duration = Duration::from_secs(1);
+
+pub const TIMBRE_CC: u8 = 74;
+pub const DATA_ENTRY_CC: u8 = 6;
+pub const DATA_ENTRY_LSB_CC: u8 = 38;
+pub const NRPN_LSB_CC: u8 = 98;
+pub const NRPN_MSB_CC: u8 = 99;
+pub const RPN_LSB_CC: u8 = 100;
+pub const RPN_MSB_CC: u8 = 101;
+pub const ALL_SOUND_OFF_CC: u8 = 120;
+pub const ALL_NOTES_OFF_CC: u8 = 123;
+
+fn pitch_bend(value: i16) -> Vec<u8> {
+    let bend = (value.clamp(-8192, 8191) + 8192) as u16;
+    vec![PITCH_BEND_MSG, (bend & 0x7F) as u8, (bend >> 7) as u8]
 }
 
+// selects the parameter with the two `select` controllers, MSB first, then
+// sets it with data entry, MSB then LSB
+fn parameter(select: (u8, u8), parameter: u16, value: u16) -> Vec<Vec<u8>> {
+    let (msb, lsb) = select;
+    [
+        (msb, parameter >> 7),
+        (lsb, parameter),
+        (DATA_ENTRY_CC, value >> 7),
+        (DATA_ENTRY_LSB_CC, value),
+    ]
+    .iter()
+    .map(|&(controller, data)| vec![CONTROL_CHANGE_MSG, controller, (data & 0x7F) as u8])
+    .collect()
+}
+
+// None for things that have no MIDI equivalent, or need more than one
+// message for it
+pub trait MidiEvent {
+    fn to_midi(&self) -> Option<Vec<u8>>;
+
+    // every message it takes, in order; NRPN and RPN are four CCs
+    fn to_midi_messages(&self) -> Vec<Vec<u8>> {
+        self.to_midi().into_iter().collect()
+    }
+}
+
+impl MidiEvent for Message {
+    fn to_midi(&self) -> Option<Vec<u8>> {
+        let midi = match *self {
+            Message::NoteOn { note, velocity: 0 } => vec![NOTE_OFF_MSG, note, 0],
+            Message::NoteOn { note, velocity } => vec![NOTE_ON_MSG, note, velocity],
+            Message::NoteOff { note, velocity } => vec![NOTE_OFF_MSG, note, velocity],
+            Message::ControlChange { controller, value } => {
+                vec![CONTROL_CHANGE_MSG, controller, value]
+            }
+            Message::PolyPressure { note, pressure } => vec![POLY_PRESSURE_MSG, note, pressure],
+            Message::ProgramChange { program } => vec![PROGRAM_CHANGE_MSG, program],
+            Message::ChannelPressure { pressure } => vec![CHANNEL_PRESSURE_MSG, pressure],
+            Message::PitchBend { value } => pitch_bend(value),
+            Message::Nrpn { .. } | Message::Rpn { .. } => return None,
+        };
+        Some(midi)
+    }
+
+    fn to_midi_messages(&self) -> Vec<Vec<u8>> {
+        match *self {
+            Message::Nrpn {
+                parameter: number,
+                value,
+            } => parameter((NRPN_MSB_CC, NRPN_LSB_CC), number, value),
+            Message::Rpn {
+                parameter: number,
+                value,
+            } => parameter((RPN_MSB_CC, RPN_LSB_CC), number, value),
+            _ => self.to_midi().into_iter().collect(),
+        }
+    }
+}
+
+// outside MPE mode expression simply applies to the whole channel
+impl MidiEvent for Expression {
+    fn to_midi(&self) -> Option<Vec<u8>> {
+        let midi = match *self {
+            Expression::PitchBend(value) => pitch_bend(value),
+            Expression::Pressure(pressure) => vec![CHANNEL_PRESSURE_MSG, pressure],
+            Expression::Timbre(timbre) => vec![CONTROL_CHANGE_MSG, TIMBRE_CC, timbre],
+        };
+        Some(midi)
+    }
+}
+
+// the data wrapped in F0 ... F7 unless it already is
+pub fn sysex(data: &[u8]) -> Vec<u8> {
+    let mut midi = Vec::with_capacity(data.len() + 2);
+    if data.first() != Some(&SYSEX_START) {
+        midi.push(SYSEX_START);
+    }
+    midi.extend(data);
+    if data.last() != Some(&SYSEX_END) {
+        midi.push(SYSEX_END);
+    }
+    midi
+}
+
+// All Notes Off on every channel
+pub fn all_notes_off() -> Vec<Vec<u8>> {
+    (0..16)
+        .map(|channel| vec![CONTROL_CHANGE_MSG | channel, ALL_NOTES_OFF_CC, 0])
+        .collect()
+}
+
+// All Sound Off, then All Notes Off, on every channel: cuts off release tails
+// and held notes alike, on whatever listens to either
+pub fn panic_messages() -> Vec<Vec<u8>> {
+    (0..16)
+        .map(|channel| vec![CONTROL_CHANGE_MSG | channel, ALL_SOUND_OFF_CC, 0])
+        .chain(all_notes_off())
+        .collect()
+}
+
+// the message's status byte carries the event's channel
 impl MidiEvent for Event {
-    fn to_midi(&self) -> [u8; 3] {
-        let note = self.value.parse::<u8>().unwrap();
-        [NOTE_ON_MSG, note, VELOCITY]
+    fn to_midi(&self) -> Option<Vec<u8>> {
+        let mut midi = match &self.payload {
+            Payload::Midi(message) => message.to_midi()?,
+            Payload::Expression { expression, .. } => expression.to_midi()?,
+            Payload::SysEx(data) => return Some(sysex(data)),
+            _ => return None,
+        };
+        midi[0] |= self.channel & 0x0F;
+        Some(midi)
+    }
+
+    fn to_midi_messages(&self) -> Vec<Vec<u8>> {
+        match &self.payload {
+            Payload::Midi(message) => message
+                .to_midi_messages()
+                .into_iter()
+                .map(|mut midi| {
+                    midi[0] |= self.channel & 0x0F;
+                    midi
+                })
+                .collect(),
+            _ => self.to_midi().into_iter().collect(),
+        }
+    }
+}
+
+// what a MidiBackend does with events while its device is unplugged
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum Unplugged {
+    // reported as dead letters
+    Drop,
+    // keeps up to this many, the oldest giving way, and sends them on reconnect
+    Buffer(usize),
+}
+
+// evens out levels between devices: velocities are scaled, then offset, and
+// kept within 1-127 so that no note-on turns into a note-off
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Velocity {
+    pub scale: f64,
+    pub offset: i16,
+}
+
+impl Default for Velocity {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            offset: 0,
+        }
+    }
+}
+
+impl Velocity {
+    pub fn apply(&self, velocity: u8) -> u8 {
+        if velocity == 0 {
+            return 0;
+        }
+        let scaled = (velocity as f64 * self.scale).round() + self.offset as f64;
+        scaled.clamp(1.0, 127.0) as u8
+    }
+
+    // the event with its note-on velocity adjusted, anything else as it is
+    fn adjust(&self, mut event: Event) -> Event {
+        if let Payload::Midi(Message::NoteOn { note, velocity }) = event.payload {
+            let velocity = self.apply(velocity);
+            event.payload = Payload::Midi(Message::NoteOn { note, velocity });
+        }
+        event
     }
 }
 
 pub struct MidiBackend {
     pub device_name: String,
+    // number of MPE member channels, None for plain MIDI
+    pub mpe: Option<u8>,
+    // creates a port named `device_name` rather than opening one
+    pub virtual_port: bool,
+    pub unplugged: Unplugged,
+    pub velocity: Velocity,
+    // event channel to the channel the device listens on, both 0-15;
+    // unmapped channels go out as they are
+    pub channels: HashMap<u8, u8>,
+    port: Option<Port>,
+    zone: Option<MpeZone>,
+    muted: bool,
 }
 
 impl MidiBackend {
-    fn init_output(&self) -> midir::MidiOutputConnection {
-        let midi_out = midir::MidiOutput::new(self.device_name.as_ref()).unwrap();
-        let out_ports = midi_out.ports();
-        let out_port = out_ports.get(1).unwrap();
-        midi_out.connect(out_port, "tonic-test").unwrap()
+    pub fn new(device_name: &str) -> Self {
+        Self {
+            device_name: device_name.to_string(),
+            mpe: None,
+            virtual_port: false,
+            unplugged: Unplugged::Drop,
+            velocity: Velocity::default(),
+            channels: HashMap::new(),
+            port: None,
+            zone: None,
+            muted: false,
+        }
+    }
+
+    // a port of tonic's own (see VIRTUAL_PORT_NAME) that DAWs and softsynths
+    // connect to, no IAC or loopback device needed; not on Windows
+    pub fn virtual_port(port_name: &str) -> Self {
+        Self {
+            virtual_port: true,
+            ..Self::new(port_name)
+        }
+    }
+
+    // drives an MPE lower zone: channel 1 is the manager and every note gets
+    // one of the next `members` channels to itself
+    pub fn with_mpe(mut self, members: u8) -> Self {
+        self.mpe = Some(members.clamp(1, 15));
+        self
+    }
+
+    // what happens to events while the device is unplugged, Drop by default
+    pub fn when_unplugged(mut self, unplugged: Unplugged) -> Self {
+        self.unplugged = unplugged;
+        self
+    }
+
+    // e.g. (0.8, 0) for a device that plays loud, (1.0, 20) for a quiet one
+    pub fn with_velocity(mut self, scale: f64, offset: i16) -> Self {
+        self.velocity = Velocity { scale, offset };
+        self
+    }
+
+    // plays events on channel `from` on channel `to` instead, e.g. (0, 9)
+    // for a drum machine on GM channel 10, so patterns stay portable
+    pub fn map_channel(mut self, from: u8, to: u8) -> Self {
+        self.channels.insert(from & 0x0F, to & 0x0F);
+        self
+    }
+
+    // for when something hangs during a set: note-offs for every note still
+    // sounding, then All Sound Off and All Notes Off on all 16 channels.
+    // Scheduler::panic gets here through a Control::Panic event
+    pub fn panic(&mut self) {
+        if let Some(port) = self.port.as_mut() {
+            port.panic();
+        }
+        // the zone's notes are gone, their channels free again
+        self.zone = self.mpe.map(MpeZone::new);
+    }
+}
+
+// the port whose name contains `device_name` ignoring case, an exact match
+// winning over a partial one; the error lists what there is to pick from
+pub fn find_port(device_name: &str, names: &[String]) -> Result<usize, String> {
+    let wanted = device_name.to_lowercase();
+    let matching = |exact: bool| {
+        names.iter().position(|name| {
+            let name = name.to_lowercase();
+            if exact {
+                name == wanted
+            } else {
+                name.contains(&wanted)
+            }
+        })
+    };
+    matching(true).or_else(|| matching(false)).ok_or_else(|| {
+        let available = if names.is_empty() {
+            "none".to_string()
+        } else {
+            names.join(", ")
+        };
+        format!(
+            "no MIDI port matching \"{}\", available: {}",
+            device_name, available
+        )
+    })
+}
+
+fn output_names(midi_out: &midir::MidiOutput) -> Vec<String> {
+    midi_out
+        .ports()
+        .iter()
+        .map(|port| midi_out.port_name(port).unwrap_or_default())
+        .collect()
+}
+
+fn input_names(midi_in: &midir::MidiInput) -> Vec<String> {
+    midi_in
+        .ports()
+        .iter()
+        .map(|port| midi_in.port_name(port).unwrap_or_default())
+        .collect()
+}
+
+pub fn try_input_port(
+    midi_in: &midir::MidiInput,
+    device_name: &str,
+) -> Result<midir::MidiInputPort, String> {
+    let index = find_port(device_name, &input_names(midi_in))?;
+    Ok(midi_in.ports()[index].clone())
+}
+
+pub fn input_port(midi_in: &midir::MidiInput, device_name: &str) -> midir::MidiInputPort {
+    try_input_port(midi_in, device_name).unwrap_or_else(|error| panic!("{}", error))
+}
+
+// the MIDI ports there are to open, indexed as the system lists them; any
+// part of a name does as a device name
+#[derive(Debug, Clone, Default)]
+pub struct Devices {
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+}
+
+impl fmt::Display for Devices {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (heading, names) in [("inputs", &self.inputs), ("outputs", &self.outputs)] {
+            writeln!(f, "MIDI {}:", heading)?;
+            if names.is_empty() {
+                writeln!(f, "  (none)")?;
+            }
+            for (index, name) in names.iter().enumerate() {
+                writeln!(f, "  {}: {}", index, name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// an empty list where the MIDI system can't be reached at all
+pub fn list_devices() -> Devices {
+    Devices {
+        inputs: midir::MidiInput::new("tonic")
+            .map(|midi_in| input_names(&midi_in))
+            .unwrap_or_default(),
+        outputs: midir::MidiOutput::new("tonic")
+            .map(|midi_out| output_names(&midi_out))
+            .unwrap_or_default(),
+    }
+}
+
+pub fn try_open_output(device_name: &str) -> Result<midir::MidiOutputConnection, String> {
+    let midi_out = midir::MidiOutput::new(device_name).map_err(|error| error.to_string())?;
+    let index = find_port(device_name, &output_names(&midi_out))?;
+    let out_port = midi_out.ports()[index].clone();
+    midi_out
+        .connect(&out_port, "tonic-test")
+        .map_err(|error| error.to_string())
+}
+
+pub fn open_output(device_name: &str) -> midir::MidiOutputConnection {
+    try_open_output(device_name).unwrap_or_else(|error| panic!("{}", error))
+}
+
+#[cfg(unix)]
+pub fn try_open_virtual(port_name: &str) -> Result<midir::MidiOutputConnection, String> {
+    let midi_out = midir::MidiOutput::new(port_name).map_err(|error| error.to_string())?;
+    midir::os::unix::VirtualOutput::create_virtual(midi_out, port_name)
+        .map_err(|error| error.to_string())
+}
+
+#[cfg(not(unix))]
+pub fn try_open_virtual(port_name: &str) -> Result<midir::MidiOutputConnection, String> {
+    Err(format!(
+        "can't create {}: no virtual MIDI ports on this platform",
+        port_name
+    ))
+}
+
+pub fn open_virtual(port_name: &str) -> midir::MidiOutputConnection {
+    try_open_virtual(port_name).unwrap_or_else(|error| panic!("{}", error))
+}
+
+// the device's output, or where it would be while it's unplugged: events are
+// dropped or buffered per `unplugged` and the device is looked for every
+// HOTPLUG_POLL until it's back
+struct Port {
+    device: String,
+    virtual_port: bool,
+    // sent again on every connect, e.g. the MPE zone configuration
+    setup: Vec<Vec<u8>>,
+    unplugged: Unplugged,
+    out: Option<midir::MidiOutputConnection>,
+    // each event with whatever of its messages is still to go
+    buffered: VecDeque<(Event, Vec<Vec<u8>>)>,
+    // channel and note of every note-on sent without its note-off yet
+    sounding: HashSet<(u8, u8)>,
+    // when it was last seen listed, to notice it going away while idle
+    checked: Instant,
+}
+
+impl Port {
+    fn new(device: String, virtual_port: bool, setup: Vec<Vec<u8>>, unplugged: Unplugged) -> Self {
+        let mut port = Self {
+            device,
+            virtual_port,
+            setup,
+            unplugged,
+            out: None,
+            buffered: VecDeque::new(),
+            sounding: HashSet::new(),
+            checked: Instant::now(),
+        };
+        if let Err(error) = port.connect() {
+            eprintln!("[midi] {}, waiting for {}", error, port.device);
+        }
+        port
+    }
+
+    fn connect(&mut self) -> Result<(), String> {
+        let mut out = if self.virtual_port {
+            try_open_virtual(&self.device)?
+        } else {
+            try_open_output(&self.device)?
+        };
+        for msg in self.setup.iter() {
+            out.send(msg).map_err(|error| error.to_string())?;
+        }
+        self.out = Some(out);
+        self.checked = Instant::now();
+        // replayed from a queue of its own, so whatever fails lands in a
+        // fresh buffer rather than back in front of the loop
+        let mut pending = std::mem::take(&mut self.buffered);
+        while let Some((event, messages)) = pending.pop_front() {
+            self.send(&event, messages);
+            if self.out.is_none() {
+                // gone again, the rest waits for the next connect
+                self.buffered.extend(pending);
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // the event's messages in order; what the device doesn't get is dropped
+    // or buffered as one, so an event is only ever dead-lettered once
+    fn send(&mut self, event: &Event, messages: Vec<Vec<u8>>) {
+        let mut messages = VecDeque::from(messages);
+        while let Some(out) = self.out.as_mut() {
+            let midi = match messages.front() {
+                Some(midi) => midi,
+                None => return,
+            };
+            match out.send(midi) {
+                Ok(()) => {
+                    let midi = messages.pop_front().unwrap();
+                    self.track(&midi);
+                }
+                Err(error) => self.lost(error),
+            }
+        }
+        if messages.is_empty() {
+            return;
+        }
+        match self.unplugged {
+            Unplugged::Drop => dead_letter::post(event.clone(), &self.device, "device unplugged"),
+            Unplugged::Buffer(capacity) => {
+                if self.buffered.len() >= capacity.max(1) {
+                    if let Some((oldest, _)) = self.buffered.pop_front() {
+                        dead_letter::post(oldest, &self.device, "device unplugged");
+                    }
+                }
+                self.buffered.push_back((event.clone(), messages.into()));
+            }
+        }
+    }
+
+    fn lost<E: fmt::Display>(&mut self, error: E) {
+        self.out = None;
+        // whatever was sounding went with the device
+        self.sounding.clear();
+        eprintln!(
+            "[midi] lost {} ({}), waiting for it to come back",
+            self.device, error
+        );
+    }
+
+    // notices the device going away and coming back even with nothing to send
+    fn poll(&mut self) {
+        if self.checked.elapsed() < HOTPLUG_POLL {
+            return;
+        }
+        self.checked = Instant::now();
+        if self.out.is_none() {
+            if self.connect().is_ok() {
+                eprintln!("[midi] {} is back", self.device);
+            }
+            return;
+        }
+        if self.virtual_port {
+            return;
+        }
+        let listed = midir::MidiOutput::new(&self.device)
+            .map(|midi_out| find_port(&self.device, &output_names(&midi_out)).is_ok())
+            .unwrap_or(false);
+        if !listed {
+            self.lost("no longer listed");
+        }
+    }
+
+    fn track(&mut self, midi: &[u8]) {
+        let (status, note, velocity) = match *midi {
+            [status, note, velocity, ..] => (status, note, velocity),
+            _ => return,
+        };
+        let channel = status & 0x0F;
+        match status & 0xF0 {
+            NOTE_ON_MSG if velocity > 0 => {
+                self.sounding.insert((channel, note));
+            }
+            NOTE_ON_MSG | NOTE_OFF_MSG => {
+                self.sounding.remove(&(channel, note));
+            }
+            _ => {}
+        }
+    }
+
+    // a note-off for every note still sounding; not every synth listens to
+    // All Notes Off
+    fn release(&mut self) {
+        let sounding: Vec<(u8, u8)> = self.sounding.drain().collect();
+        if let Some(out) = self.out.as_mut() {
+            for (channel, note) in sounding {
+                let _ = out.send(&[NOTE_OFF_MSG | channel, note, 0]);
+            }
+        }
+    }
+
+    // releases what it knows is sounding and tells every channel to go
+    // quiet; anything buffered while unplugged would only start it again
+    fn panic(&mut self) {
+        for (event, _) in self.buffered.drain(..) {
+            dead_letter::post(event, &self.device, "panic");
+        }
+        self.release();
+        if let Some(out) = self.out.as_mut() {
+            for msg in panic_messages() {
+                let _ = out.send(&msg);
+            }
+        }
+    }
+
+    // switches to another device, buffered events going there instead;
+    // notes sounding on the old one are released first
+    fn switch(&mut self, device: &str) {
+        self.release();
+        self.out = None;
+        self.device = device.to_string();
+        self.virtual_port = false;
+        if let Err(error) = self.connect() {
+            eprintln!("[midi] {}, waiting for {}", error, self.device);
+        }
+    }
+
+    // silences whatever is left before closing the port
+    fn close(mut self) {
+        self.release();
+        if let Some(mut out) = self.out.take() {
+            for msg in all_notes_off() {
+                let _ = out.send(&msg);
+            }
+            out.close();
+        }
     }
 }
 
 impl Backend for MidiBackend {
-    fn run(&self, receiver: Receiver<Event>) {
-        let mut out = self.init_output();
-
-        thread::spawn(move || loop {
-            match receiver.recv() {
-                Ok(event) => {
-                    println!("[midi] got event: {:?}", event);
-                    let midi_event = event.to_midi();
-                    out.send(&midi_event).unwrap();
+    // a device that isn't there yet is waited for rather than failing
+    fn start(&mut self) -> Result<(), BackendError> {
+        let zone = self.mpe.map(MpeZone::new);
+        let setup = zone
+            .as_ref()
+            .map(MpeZone::configuration)
+            .unwrap_or_default();
+        self.port = Some(Port::new(
+            self.device_name.clone(),
+            self.virtual_port,
+            setup,
+            self.unplugged,
+        ));
+        self.zone = zone;
+        self.muted = false;
+        Ok(())
+    }
+
+    fn send(&mut self, event: &Event) -> Result<(), BackendError> {
+        let port = self.port.as_mut().ok_or("port not open")?;
+        match event.control() {
+            Some(Control::Mute) => self.muted = true,
+            Some(Control::Unmute) => self.muted = false,
+            Some(Control::SetDevice(name)) => port.switch(name),
+            Some(Control::SetVolume(_)) => {}
+            Some(Control::Panic) => self.panic(),
+            // note-offs still go out so muting doesn't hang notes
+            None if self.muted && !event.is_note_off() => {}
+            None => {
+                let mut event = self.velocity.adjust(event.clone());
+                if let Some(&channel) = self.channels.get(&event.channel) {
+                    event.channel = channel;
                 }
-                Err(_) => {}
+                let midi_events = match self.zone.as_mut() {
+                    Some(zone) => zone.route(&event),
+                    None => event.to_midi_messages(),
+                };
+                port.send(&event, midi_events);
             }
-        });
+        }
+        Ok(())
+    }
+
+    fn poll_interval(&self) -> Option<Duration> {
+        Some(HOTPLUG_POLL)
+    }
+
+    fn poll(&mut self) -> Result<(), BackendError> {
+        if let Some(port) = self.port.as_mut() {
+            port.poll();
+        }
+        Ok(())
+    }
+
+    // unplugged, with events buffered or dropped until it's back
+    fn is_connected(&self) -> bool {
+        self.port.as_ref().is_some_and(|port| port.out.is_some())
+    }
+
+    fn stop(&mut self) -> Result<(), BackendError> {
+        if let Some(port) = self.port.take() {
+            port.close();
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        self.device_name.clone()
     }
 }
@@ -0,0 +1,257 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::backends::midi::{MidiEvent, SYSEX_END, SYSEX_START};
+use crate::backends::{Backend, BackendError};
+use crate::event::{Event, Payload};
+use crate::midi_input;
+use crate::recorder::Take;
+use crate::tempo::valid_bpm;
+
+const END_OF_TRACK: [u8; 3] = [0xFF, 0x2F, 0x00];
+const META: u8 = 0xFF;
+const TRACK_NAME: u8 = 0x03;
+
+// writes events as a type 0 standard MIDI file once it's stopped, placing
+// them by their beat and tick
+pub struct MidiFileBackend {
+    pub path: PathBuf,
+    pub ppqn: u64,
+    pub bpm: f64,
+    track: Vec<u8>,
+    last_tick: u64,
+}
+
+impl MidiFileBackend {
+    pub fn new<P: Into<PathBuf>>(path: P, ppqn: u64, bpm: f64) -> Self {
+        Self {
+            path: path.into(),
+            ppqn,
+            bpm,
+            track: vec![],
+            last_tick: 0,
+        }
+    }
+}
+
+fn write_varlen(track: &mut Vec<u8>, mut value: u64) {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    value >>= 7;
+    while value > 0 {
+        bytes.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.reverse();
+    track.extend(bytes);
+}
+
+impl Backend for MidiFileBackend {
+    fn start(&mut self) -> Result<(), BackendError> {
+        if !valid_bpm(self.bpm) {
+            return Err(BackendError::new(format!(
+                "invalid tempo: {} bpm",
+                self.bpm
+            )));
+        }
+        // the header's division, which 0 or the top bit would make invalid
+        if self.ppqn == 0 || self.ppqn > 0x7FFF {
+            return Err(BackendError::new(format!("invalid ppqn: {}", self.ppqn)));
+        }
+        let tempo = (60_000_000.0 / self.bpm).round() as u32;
+        self.track = vec![0x00, 0xFF, 0x51, 0x03];
+        self.track.extend(&tempo.to_be_bytes()[1..]);
+        self.last_tick = 0;
+        Ok(())
+    }
+
+    fn send(&mut self, event: &Event) -> Result<(), BackendError> {
+        let track = &mut self.track;
+        let tick = event.beat * self.ppqn + event.tick;
+        for midi in event.to_midi_messages() {
+            write_varlen(track, tick.saturating_sub(self.last_tick));
+            // sysex is stored with its length after the F0
+            if midi[0] == SYSEX_START {
+                track.push(SYSEX_START);
+                write_varlen(track, midi.len() as u64 - 1);
+                track.extend(&midi[1..]);
+            } else {
+                track.extend(&midi);
+            }
+            self.last_tick = self.last_tick.max(tick);
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), BackendError> {
+        let mut track = std::mem::take(&mut self.track);
+        track.push(0x00);
+        track.extend(&END_OF_TRACK);
+
+        let mut file = File::create(&self.path)?;
+        file.write_all(b"MThd")?;
+        file.write_all(&6u32.to_be_bytes())?;
+        file.write_all(&[0, 0, 0, 1])?;
+        file.write_all(&(self.ppqn as u16).to_be_bytes())?;
+        file.write_all(b"MTrk")?;
+        file.write_all(&(track.len() as u32).to_be_bytes())?;
+        file.write_all(&track)?;
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+}
+
+// reads the bytes a chunk or event is made of, failing on a truncated file
+struct Reader<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    // `count` can come from the file, so it's no bigger than what's left
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .at
+            .checked_add(count)
+            .and_then(|end| self.data.get(self.at..end))
+            .ok_or_else(|| "unexpected end of file".to_string())?;
+        self.at += count;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    // four bytes at most, as the format has it
+    fn varlen(&mut self) -> Result<u64, String> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.byte()?;
+            value = value << 7 | (byte & 0x7F) as u64;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("variable-length number over 4 bytes".to_string())
+    }
+
+    fn done(&self) -> bool {
+        self.at >= self.data.len()
+    }
+}
+
+// one MTrk chunk's events, with the file's ticks rescaled to `ppqn`; the
+// track's name, if it has one, becomes the tag of all its events
+fn read_track(track: &[u8], division: u64, ppqn: u64) -> Result<Vec<Event>, String> {
+    let mut reader = Reader { data: track, at: 0 };
+    let mut events = vec![];
+    let mut name = None;
+    let mut elapsed = 0;
+    let mut running_status = None;
+    while !reader.done() {
+        elapsed += reader.varlen()?;
+        let ticks = elapsed
+            .checked_mul(ppqn)
+            .ok_or_else(|| "track too long".to_string())?
+            / division;
+        let (beat, tick) = (ticks / ppqn, ticks % ppqn);
+        let status = match reader.byte()? {
+            status if status & 0x80 != 0 => status,
+            // running status: a data byte, the status is the last one's
+            _ => {
+                reader.at -= 1;
+                running_status.ok_or_else(|| "data byte without a status".to_string())?
+            }
+        };
+        match status {
+            META => {
+                let kind = reader.byte()?;
+                let length = reader.varlen()? as usize;
+                let data = reader.bytes(length)?;
+                if kind == TRACK_NAME {
+                    name = Some(String::from_utf8_lossy(data).into_owned());
+                }
+            }
+            SYSEX_START | SYSEX_END => {
+                let length = reader.varlen()? as usize;
+                let data = reader.bytes(length)?.to_vec();
+                events.push(Event::with_tick(Payload::SysEx(data), beat, tick));
+            }
+            _ => {
+                running_status = Some(status);
+                let length = match status & 0xF0 {
+                    0xC0 | 0xD0 => 1,
+                    _ => 2,
+                };
+                let mut midi = vec![status];
+                midi.extend(reader.bytes(length)?);
+                if let Some((channel, message)) = midi_input::parse(&midi) {
+                    events.push(Event::with_tick(message, beat, tick).on_channel(channel));
+                }
+            }
+        }
+    }
+    if let Some(name) = name {
+        events
+            .iter_mut()
+            .for_each(|event| event.tag = Some(name.clone()));
+    }
+    Ok(events)
+}
+
+// the reverse of MidiFileBackend: a standard MIDI file's tracks as one take,
+// every event placed by its beat and tick at `ppqn`, ready for
+// Transport::replay to play it on the live clock next to the generators
+// (Take::shifted moves it along). Tempo changes in the file are left out,
+// the clock sets the tempo
+pub fn read<P: AsRef<Path>>(path: P, ppqn: u64) -> Result<Take, String> {
+    if ppqn == 0 {
+        return Err("ppqn must be at least 1".to_string());
+    }
+    let data = fs::read(path.as_ref()).map_err(|error| error.to_string())?;
+    let mut reader = Reader { data: &data, at: 0 };
+    if reader.bytes(4)? != b"MThd" {
+        return Err("not a standard MIDI file".to_string());
+    }
+    let header_length = reader.u32()? as usize;
+    let header = reader.bytes(header_length)?;
+    let mut header = Reader {
+        data: header,
+        at: 0,
+    };
+    let _format = header.u16()?;
+    let tracks = header.u16()?;
+    let division = header.u16()?;
+    // negative: SMPTE frames rather than ticks per quarter note
+    if division & 0x8000 != 0 || division == 0 {
+        return Err("SMPTE timed MIDI files aren't supported".to_string());
+    }
+
+    let mut events = vec![];
+    let mut found = 0;
+    while found < tracks && !reader.done() {
+        let kind = reader.bytes(4)?;
+        let length = reader.u32()? as usize;
+        let chunk = reader.bytes(length)?;
+        // unknown chunks are to be skipped
+        if kind == b"MTrk" {
+            events.extend(read_track(chunk, division as u64, ppqn)?);
+            found += 1;
+        }
+    }
+    Ok(Take::new(events))
+}
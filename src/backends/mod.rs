@@ -1,10 +1,125 @@
-use std::sync::mpsc::Receiver;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::{Duration, Instant};
 
 use crate::event::Event;
 
+#[cfg(target_os = "linux")]
+pub mod alsa_seq;
+pub mod artnet;
+pub mod audio;
 pub mod dummy;
+#[cfg(feature = "jack")]
+pub mod jack;
+pub mod log;
+pub mod metronome;
 pub mod midi;
+pub mod midi_file;
+pub mod mpe;
+pub mod net;
+pub mod osc;
+pub mod plugin;
+pub mod process;
+pub mod rtp_midi;
+pub mod scsynth;
+pub mod serial;
+pub mod stream;
+pub mod throttle;
+pub mod websocket;
 
-pub trait Backend {
-    fn run(&self, receiver: Receiver<Event>);
+// why a backend couldn't start, send, flush or stop
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackendError(pub String);
+
+impl BackendError {
+    pub fn new<E: fmt::Display>(error: E) -> Self {
+        BackendError(error.to_string())
+    }
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for BackendError {}
+
+impl From<String> for BackendError {
+    fn from(error: String) -> Self {
+        BackendError(error)
+    }
+}
+
+impl From<&str> for BackendError {
+    fn from(error: &str) -> Self {
+        BackendError(error.to_string())
+    }
+}
+
+impl From<io::Error> for BackendError {
+    fn from(error: io::Error) -> Self {
+        BackendError::new(error)
+    }
+}
+
+// an output the scheduler plays events through. The scheduler runs each
+// backend on a thread of its own: start(), then send() for every event as it
+// comes due, flush() whenever no more are waiting, and stop() once it's
+// disconnected. Failures are reported on Scheduler::backend_errors, a failed
+// event is dead-lettered besides, and a backend that fails to start counts as
+// stopped (see outputs::Restart). A stopped backend may be started again
+pub trait Backend: Send {
+    // opens whatever the backend plays through
+    fn start(&mut self) -> Result<(), BackendError> {
+        Ok(())
+    }
+
+    fn send(&mut self, event: &Event) -> Result<(), BackendError>;
+
+    // send() along with the instant the event was due to go out, for
+    // backends that timestamp their output (JACK, the ALSA sequencer) so the
+    // wakeup jitter of the threads on the way here doesn't reach the wire
+    fn send_at(&mut self, event: &Event, _due: Instant) -> Result<(), BackendError> {
+        self.send(event)
+    }
+
+    // pushes out anything held back to go out together
+    fn flush(&mut self) -> Result<(), BackendError> {
+        Ok(())
+    }
+
+    // silences and closes what start() opened
+    fn stop(&mut self) -> Result<(), BackendError> {
+        Ok(())
+    }
+
+    // how long to wait for an event before calling poll(), None for never
+    fn poll_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    // upkeep while no events come, e.g. looking for an unplugged device
+    fn poll(&mut self) -> Result<(), BackendError> {
+        Ok(())
+    }
+
+    // false while what it plays through is missing and being waited for,
+    // e.g. an unplugged device; see Scheduler::status
+    fn is_connected(&self) -> bool {
+        true
+    }
+
+    // why it isn't connected, if it knows, e.g. what opening the device
+    // failed with; shown as the backend's health instead of reconnecting
+    fn missing(&self) -> Option<String> {
+        None
+    }
+
+    // what failures and dead letters call it, e.g. a device name
+    fn name(&self) -> String {
+        let path = std::any::type_name::<Self>();
+        path.rsplit("::").next().unwrap_or(path).to_string()
+    }
 }
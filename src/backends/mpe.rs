@@ -0,0 +1,112 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::backends::midi::{
+    MidiEvent, CONTROL_CHANGE_MSG, DATA_ENTRY_CC, NOTE_OFF_MSG, RPN_LSB_CC, RPN_MSB_CC,
+};
+use crate::event::{Event, Expression, Message, Payload};
+
+const MANAGER_CHANNEL: u8 = 0;
+
+// RPN 6 sets up the zone, sent once on the manager channel
+const MPE_CONFIGURATION_RPN: u8 = 6;
+
+// hands out member channels of an MPE lower zone, one per sounding note,
+// and turns events into messages on the right channel
+pub struct MpeZone {
+    members: u8,
+    // channels least recently released first
+    free: VecDeque<u8>,
+    // (channel, note) in the order the notes started
+    active: VecDeque<(u8, u8)>,
+    // expression for notes yet to start, sent on their channel before the note-on
+    pending: HashMap<u8, Vec<Expression>>,
+}
+
+fn on_channel(mut midi: Vec<u8>, channel: u8) -> Vec<u8> {
+    midi[0] |= channel;
+    midi
+}
+
+impl MpeZone {
+    pub fn new(members: u8) -> Self {
+        Self {
+            members,
+            free: (1..=members).collect(),
+            active: VecDeque::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    pub fn configuration(&self) -> Vec<Vec<u8>> {
+        let status = CONTROL_CHANGE_MSG | MANAGER_CHANNEL;
+        vec![
+            vec![status, RPN_MSB_CC, 0],
+            vec![status, RPN_LSB_CC, MPE_CONFIGURATION_RPN],
+            vec![status, DATA_ENTRY_CC, self.members],
+        ]
+    }
+
+    pub fn route(&mut self, event: &Event) -> Vec<Vec<u8>> {
+        match event.payload {
+            Payload::Midi(Message::NoteOn { note, velocity }) if velocity > 0 => {
+                self.note_on(note, velocity)
+            }
+            Payload::Midi(Message::NoteOn { note, .. })
+            | Payload::Midi(Message::NoteOff { note, .. }) => self.note_off(note),
+            Payload::Expression { note, expression } => self.expression(note, expression),
+            // anything else goes out as addressed, zone-wide messages
+            // belong on the manager channel
+            _ => event.to_midi_messages(),
+        }
+    }
+
+    fn note_on(&mut self, note: u8, velocity: u8) -> Vec<Vec<u8>> {
+        let mut midi = vec![];
+        // out of channels, the oldest note makes room
+        let channel = match self.free.pop_front() {
+            Some(channel) => channel,
+            None => {
+                let (channel, stolen) = self.active.pop_front().unwrap();
+                midi.push(vec![NOTE_OFF_MSG | channel, stolen, 0]);
+                channel
+            }
+        };
+        for expression in self.pending.remove(&note).unwrap_or_default() {
+            midi.extend(expression.to_midi().map(|msg| on_channel(msg, channel)));
+        }
+        let note_on = Message::NoteOn { note, velocity };
+        midi.extend(note_on.to_midi().map(|msg| on_channel(msg, channel)));
+        self.active.push_back((channel, note));
+        midi
+    }
+
+    fn note_off(&mut self, note: u8) -> Vec<Vec<u8>> {
+        match self.active.iter().position(|&(_, active)| active == note) {
+            Some(i) => {
+                let (channel, _) = self.active.remove(i).unwrap();
+                self.free.push_back(channel);
+                vec![vec![NOTE_OFF_MSG | channel, note, 0]]
+            }
+            None => vec![],
+        }
+    }
+
+    fn expression(&mut self, note: u8, expression: Expression) -> Vec<Vec<u8>> {
+        match self
+            .active
+            .iter()
+            .rev()
+            .find(|&&(_, active)| active == note)
+        {
+            Some(&(channel, _)) => expression
+                .to_midi()
+                .map(|msg| on_channel(msg, channel))
+                .into_iter()
+                .collect(),
+            None => {
+                self.pending.entry(note).or_default().push(expression);
+                vec![]
+            }
+        }
+    }
+}
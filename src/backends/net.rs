@@ -0,0 +1,137 @@
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::backends::{Backend, BackendError};
+use crate::event::Event;
+use crate::json;
+
+// longest a connect or a write may hold up the backend's thread
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+// waits between failed connects, doubling from the first up to the last
+const FIRST_RETRY: Duration = Duration::from_millis(100);
+const LAST_RETRY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Protocol {
+    // one datagram per event; cheap, but nothing is resent
+    Udp,
+    // one line per event over a connection that's reopened when it drops
+    Tcp,
+}
+
+// forwards events to another tonic process (see remote::Remote) as JSON,
+// each still carrying the beat and tick it's due on, so the receiving side
+// places it on its own clock. Events reach a backend when they play, so give
+// this one a send-ahead (Scheduler::set_offset) of at least the network's
+// latency, and keep the two clocks together (Link, MIDI clock) for the beats
+// to mean the same thing on both machines
+pub struct NetBackend {
+    // host:port the other side listens on
+    pub target: String,
+    pub protocol: Protocol,
+    udp: Option<UdpSocket>,
+    tcp: Option<TcpStream>,
+    // no connects before this after one fails, events meanwhile fail fast
+    retry_at: Option<Instant>,
+    retry: Duration,
+}
+
+impl NetBackend {
+    pub fn new(target: &str, protocol: Protocol) -> Self {
+        Self {
+            target: target.to_string(),
+            protocol,
+            udp: None,
+            tcp: None,
+            retry_at: None,
+            retry: FIRST_RETRY,
+        }
+    }
+
+    fn connect(&mut self) -> Result<TcpStream, BackendError> {
+        if self.retry_at.is_some_and(|at| Instant::now() < at) {
+            return Err(BackendError::new(format!("{} unreachable", self.target)));
+        }
+        match open(&self.target) {
+            Ok(stream) => {
+                self.retry_at = None;
+                self.retry = FIRST_RETRY;
+                println!("[net] connected to {}", self.target);
+                Ok(stream)
+            }
+            Err(error) => {
+                self.retry_at = Some(Instant::now() + self.retry);
+                self.retry = (self.retry * 2).min(LAST_RETRY);
+                Err(BackendError::new(format!(
+                    "can't connect to {}: {}",
+                    self.target, error
+                )))
+            }
+        }
+    }
+}
+
+// the first of the target's addresses that answers; no Nagle, events are
+// small and due now
+fn open(target: &str) -> std::io::Result<TcpStream> {
+    let mut last = None;
+    for address in target.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, TIMEOUT) {
+            Ok(stream) => {
+                stream.set_nodelay(true)?;
+                stream.set_write_timeout(Some(TIMEOUT))?;
+                return Ok(stream);
+            }
+            Err(error) => last = Some(error),
+        }
+    }
+    Err(last.unwrap_or_else(|| std::io::Error::other("no address")))
+}
+
+impl Backend for NetBackend {
+    fn start(&mut self) -> Result<(), BackendError> {
+        if self.protocol == Protocol::Udp {
+            self.udp = Some(UdpSocket::bind("0.0.0.0:0")?);
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, event: &Event) -> Result<(), BackendError> {
+        let text = json::to_string(event).map_err(BackendError::new)?;
+        match self.protocol {
+            Protocol::Udp => {
+                let udp = self.udp.as_ref().ok_or("socket not open")?;
+                udp.send_to(text.as_bytes(), &self.target)?;
+            }
+            Protocol::Tcp => {
+                if self.tcp.is_none() {
+                    self.tcp = Some(self.connect()?);
+                }
+                let line = text + "\n";
+                let written = self.tcp.as_mut().unwrap().write_all(line.as_bytes());
+                // reconnects with the next event
+                if written.is_err() {
+                    self.tcp = None;
+                }
+                written?;
+            }
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), BackendError> {
+        self.udp = None;
+        self.tcp = None;
+        self.retry_at = None;
+        self.retry = FIRST_RETRY;
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        self.target.clone()
+    }
+}
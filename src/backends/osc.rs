@@ -0,0 +1,162 @@
+use std::net::UdpSocket;
+
+use serde::Deserialize;
+
+use crate::backends::{Backend, BackendError};
+use crate::event::{Control, Event, Message, OscArg, Payload};
+
+impl<'a> From<&'a OscArg> for rosc::OscType {
+    fn from(arg: &'a OscArg) -> Self {
+        match arg {
+            OscArg::Int(value) => rosc::OscType::Int(*value),
+            OscArg::Float(value) => rosc::OscType::Float(*value),
+            OscArg::String(value) => rosc::OscType::String(value.clone()),
+            OscArg::Blob(value) => rosc::OscType::Blob(value.clone()),
+        }
+    }
+}
+
+// where MIDI-style messages go; {channel} (1-16), {note} and {tag} are
+// filled in from the event, e.g. "/synth/{tag}/note" for one synth per tag
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Addresses {
+    // with [note, velocity], velocity 0 for note-offs
+    pub note: String,
+    // with [controller, value]
+    pub control: String,
+    // with [program]
+    pub program: String,
+    // with [pressure], or [note, pressure] for polyphonic aftertouch
+    pub pressure: String,
+    // with [value], -8192..=8191
+    pub bend: String,
+}
+
+impl Default for Addresses {
+    fn default() -> Self {
+        Self {
+            note: "/tonic/{channel}/note".to_string(),
+            control: "/tonic/{channel}/cc".to_string(),
+            program: "/tonic/{channel}/program".to_string(),
+            pressure: "/tonic/{channel}/pressure".to_string(),
+            bend: "/tonic/{channel}/bend".to_string(),
+        }
+    }
+}
+
+fn fill(template: &str, event: &Event, note: Option<u8>) -> String {
+    let mut address = template
+        .replace("{channel}", &(event.channel + 1).to_string())
+        .replace("{tag}", event.tag.as_deref().unwrap_or(""));
+    if let Some(note) = note {
+        address = address.replace("{note}", &note.to_string());
+    }
+    address
+}
+
+impl Addresses {
+    // None for things with no OSC equivalent; Osc payloads go out as they
+    // are, placeholders in their address filled in too
+    pub fn message(&self, event: &Event) -> Option<rosc::OscMessage> {
+        let int = |value: u8| rosc::OscType::Int(value as i32);
+        let (addr, args) = match &event.payload {
+            Payload::Osc { address, args } => (
+                fill(address, event, None),
+                args.iter().map(rosc::OscType::from).collect(),
+            ),
+            Payload::Midi(message) => match *message {
+                Message::NoteOn { note, velocity } => (
+                    fill(&self.note, event, Some(note)),
+                    vec![int(note), int(velocity)],
+                ),
+                Message::NoteOff { note, .. } => {
+                    (fill(&self.note, event, Some(note)), vec![int(note), int(0)])
+                }
+                Message::ControlChange { controller, value } => (
+                    fill(&self.control, event, None),
+                    vec![int(controller), int(value)],
+                ),
+                Message::ProgramChange { program } => {
+                    (fill(&self.program, event, None), vec![int(program)])
+                }
+                Message::ChannelPressure { pressure } => {
+                    (fill(&self.pressure, event, None), vec![int(pressure)])
+                }
+                Message::PolyPressure { note, pressure } => (
+                    fill(&self.pressure, event, Some(note)),
+                    vec![int(note), int(pressure)],
+                ),
+                Message::PitchBend { value } => (
+                    fill(&self.bend, event, None),
+                    vec![rosc::OscType::Int(value as i32)],
+                ),
+                Message::Nrpn { .. } | Message::Rpn { .. } => return None,
+            },
+            _ => return None,
+        };
+        Some(rosc::OscMessage { addr, args })
+    }
+}
+
+// sends events as OSC over UDP, for SuperCollider, Max/MSP, TouchDesigner
+// or visuals; `target` is host:port, e.g. "127.0.0.1:57120"
+pub struct OscBackend {
+    pub target: String,
+    pub addresses: Addresses,
+    socket: Option<UdpSocket>,
+    muted: bool,
+}
+
+impl OscBackend {
+    pub fn new(target: &str) -> Self {
+        Self {
+            target: target.to_string(),
+            addresses: Addresses::default(),
+            socket: None,
+            muted: false,
+        }
+    }
+
+    pub fn with_addresses(mut self, addresses: Addresses) -> Self {
+        self.addresses = addresses;
+        self
+    }
+}
+
+impl Backend for OscBackend {
+    fn start(&mut self) -> Result<(), BackendError> {
+        self.socket = Some(UdpSocket::bind("0.0.0.0:0")?);
+        self.muted = false;
+        Ok(())
+    }
+
+    fn send(&mut self, event: &Event) -> Result<(), BackendError> {
+        match event.control() {
+            Some(Control::Mute) => self.muted = true,
+            Some(Control::Unmute) => self.muted = false,
+            Some(_) => {}
+            None if self.muted && !event.is_note_off() => {}
+            None => {
+                let message = match self.addresses.message(event) {
+                    Some(message) => message,
+                    None => return Ok(()),
+                };
+                let packet = rosc::encoder::encode(&rosc::OscPacket::Message(message))
+                    .map_err(|error| format!("{:?}", error))?;
+                let socket = self.socket.as_ref().ok_or("socket not open")?;
+                socket.send_to(&packet, &self.target)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), BackendError> {
+        self.socket = None;
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        self.target.clone()
+    }
+}
@@ -0,0 +1,269 @@
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::os::raw::{c_char, c_int, c_void};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use crate::backends::{Backend, BackendError};
+use crate::event::Event;
+use crate::json;
+
+// bumped whenever PluginV1 changes; libraries built against another are
+// turned away
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+// the symbol a plugin library exports, see PluginV1
+pub const ENTRY_POINT: &[u8] = b"tonic_plugin\0";
+
+// what a plugin library hands tonic, plain C so it can be written in any
+// language and built with any compiler. The library exports
+//
+//     const PluginV1 *tonic_plugin(void);
+//
+// returning a descriptor that lives as long as the library. `create` makes an
+// instance from its options (JSON text, "null" when there are none) and
+// returns null if they won't do; every other call but `last_error` and
+// `destroy` returns 0 on success, and on failure leaves a message for
+// `last_error` (NUL-terminated, good until the next call on the instance).
+// Events are JSON, as json::to_string writes them. An instance is used by
+// one thread at a time, but not always the one that created it
+#[repr(C)]
+pub struct PluginV1 {
+    // PLUGIN_ABI_VERSION as the library knows it
+    pub abi_version: u32,
+    // NUL-terminated, what configs call the backend
+    pub name: *const c_char,
+    pub create: extern "C" fn(options: *const c_char) -> *mut c_void,
+    pub start: extern "C" fn(instance: *mut c_void) -> c_int,
+    pub send: extern "C" fn(instance: *mut c_void, event: *const c_char) -> c_int,
+    pub flush: extern "C" fn(instance: *mut c_void) -> c_int,
+    pub stop: extern "C" fn(instance: *mut c_void) -> c_int,
+    pub last_error: extern "C" fn(instance: *mut c_void) -> *const c_char,
+    pub destroy: extern "C" fn(instance: *mut c_void),
+}
+
+// makes a backend from its options, as JSON text
+pub type Factory = Arc<dyn Fn(&str) -> Result<Box<dyn Backend>, BackendError> + Send + Sync>;
+
+// process wide, so configs can name plugins whoever loaded them
+static PLUGINS: RwLock<Vec<(String, Factory)>> = RwLock::new(Vec::new());
+
+// makes backends called `name` available to create() and to configs, e.g.
+// from a crate of backends linked in rather than loaded; replaces an earlier
+// registration under the same name
+pub fn register<F>(name: &str, factory: F)
+where
+    F: Fn(&str) -> Result<Box<dyn Backend>, BackendError> + Send + Sync + 'static,
+{
+    let mut plugins = PLUGINS.write().unwrap();
+    plugins.retain(|(registered, _)| registered != name);
+    plugins.push((name.to_string(), Arc::new(factory)));
+}
+
+// the names registered so far, in the order they were
+pub fn names() -> Vec<String> {
+    PLUGINS
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(name, _)| name.clone())
+        .collect()
+}
+
+pub fn create(name: &str, options: &str) -> Result<Box<dyn Backend>, BackendError> {
+    let factory = PLUGINS
+        .read()
+        .unwrap()
+        .iter()
+        .find(|(registered, _)| registered == name)
+        .map(|(_, factory)| factory.clone())
+        .ok_or_else(|| format!("no plugin called \"{}\"", name))?;
+    factory(options)
+}
+
+// loads a plugin library and registers what it provides under the name it
+// gives; the library stays loaded for as long as anything made from it is
+// around
+pub fn load<P: AsRef<Path>>(path: P) -> Result<String, String> {
+    let path = path.as_ref();
+    let failed = |error: String| format!("can't load {}: {}", path.display(), error);
+    // whatever the library runs on loading runs here, only load what you trust
+    let library =
+        unsafe { libloading::Library::new(path) }.map_err(|error| failed(error.to_string()))?;
+    let plugin = unsafe {
+        let entry = library
+            .get::<extern "C" fn() -> *const PluginV1>(ENTRY_POINT)
+            .map_err(|error| failed(error.to_string()))?;
+        entry()
+            .as_ref()
+            .ok_or_else(|| failed("no plugin descriptor".to_string()))?
+    };
+    if plugin.abi_version != PLUGIN_ABI_VERSION {
+        return Err(failed(format!(
+            "built for plugin ABI {}, this is {}",
+            plugin.abi_version, PLUGIN_ABI_VERSION
+        )));
+    }
+    if plugin.name.is_null() {
+        return Err(failed("plugin has no name".to_string()));
+    }
+    let name = unsafe { CStr::from_ptr(plugin.name) }
+        .to_string_lossy()
+        .into_owned();
+    // the descriptor is the library's, it goes where the library goes
+    let plugin = Descriptor(plugin as *const PluginV1);
+    let library = Arc::new(library);
+    let backend_name = name.clone();
+    register(&name, move |options| {
+        let backend = PluginBackend::new(&backend_name, plugin, library.clone(), options)?;
+        Ok(Box::new(backend) as Box<dyn Backend>)
+    });
+    Ok(name)
+}
+
+// loads every shared library in `dir`, in name order; ones that fail are
+// reported and skipped. Nothing happens if there's no such directory. The
+// names of the plugins found
+pub fn discover<P: AsRef<Path>>(dir: P) -> Vec<String> {
+    let mut paths: Vec<_> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == std::env::consts::DLL_EXTENSION)
+            })
+            .collect(),
+        Err(_) => return vec![],
+    };
+    paths.sort();
+    paths
+        .iter()
+        .filter_map(|path| match load(path) {
+            Ok(name) => {
+                println!("[plugin] {} from {}", name, path.display());
+                Some(name)
+            }
+            Err(error) => {
+                eprintln!("[plugin] {}", error);
+                None
+            }
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy)]
+struct Descriptor(*const PluginV1);
+
+// only read, and kept alive by the library next to it
+unsafe impl Send for Descriptor {}
+unsafe impl Sync for Descriptor {}
+
+// an instance of a loaded plugin
+pub struct PluginBackend {
+    name: String,
+    plugin: Descriptor,
+    instance: *mut c_void,
+    // dropped after the instance is destroyed
+    _library: Arc<libloading::Library>,
+}
+
+// plugins promise their instances can move between threads (see PluginV1)
+unsafe impl Send for PluginBackend {}
+
+impl PluginBackend {
+    fn new(
+        name: &str,
+        plugin: Descriptor,
+        library: Arc<libloading::Library>,
+        options: &str,
+    ) -> Result<Self, BackendError> {
+        let options = CString::new(options).map_err(BackendError::new)?;
+        let instance = (unsafe { &*plugin.0 }.create)(options.as_ptr());
+        if instance.is_null() {
+            return Err(format!(
+                "plugin \"{}\" won't take options {}",
+                name,
+                options.to_string_lossy()
+            )
+            .into());
+        }
+        Ok(Self {
+            name: name.to_string(),
+            plugin,
+            instance,
+            _library: library,
+        })
+    }
+
+    fn plugin(&self) -> &PluginV1 {
+        unsafe { &*self.plugin.0 }
+    }
+
+    fn check(&self, result: c_int) -> Result<(), BackendError> {
+        if result == 0 {
+            return Ok(());
+        }
+        let error = (self.plugin().last_error)(self.instance);
+        if error.is_null() {
+            return Err(format!("failed with {}", result).into());
+        }
+        Err(unsafe { CStr::from_ptr(error) }
+            .to_string_lossy()
+            .into_owned()
+            .into())
+    }
+}
+
+impl Backend for PluginBackend {
+    fn start(&mut self) -> Result<(), BackendError> {
+        self.check((self.plugin().start)(self.instance))
+    }
+
+    fn send(&mut self, event: &Event) -> Result<(), BackendError> {
+        // custom payloads don't serialize
+        let text = match json::to_string(event) {
+            Ok(text) => text,
+            Err(_) => return Ok(()),
+        };
+        let text = CString::new(text).map_err(BackendError::new)?;
+        self.check((self.plugin().send)(self.instance, text.as_ptr()))
+    }
+
+    fn flush(&mut self) -> Result<(), BackendError> {
+        self.check((self.plugin().flush)(self.instance))
+    }
+
+    fn stop(&mut self) -> Result<(), BackendError> {
+        self.check((self.plugin().stop)(self.instance))
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl Drop for PluginBackend {
+    fn drop(&mut self) {
+        (self.plugin().destroy)(self.instance);
+    }
+}
+
+// stands in for a backend that couldn't be made, e.g. a plugin missing from
+// this machine, so the rest of a config still plays; fails to start with why
+pub struct Unavailable {
+    pub name: String,
+    pub error: BackendError,
+}
+
+impl Backend for Unavailable {
+    fn start(&mut self) -> Result<(), BackendError> {
+        Err(self.error.clone())
+    }
+
+    fn send(&mut self, _event: &Event) -> Result<(), BackendError> {
+        Err(self.error.clone())
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
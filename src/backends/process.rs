@@ -0,0 +1,216 @@
+use std::io::{BufWriter, Write};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::backends::{Backend, BackendError};
+use crate::event::Event;
+use crate::json;
+
+// a child that keeps exiting is started again no more often than this
+const RESPAWN_DELAY: Duration = Duration::from_secs(1);
+// how long a child gets to finish up once its input ends, before it's killed
+const EXIT_TIMEOUT: Duration = Duration::from_secs(2);
+// how long a child gets to connect to the socket
+#[cfg(unix)]
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const WAIT_POLL: Duration = Duration::from_millis(10);
+
+// how events reach the child
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Channel {
+    Stdin,
+    // tonic listens at the path and the child connects to it, finding the
+    // path in $TONIC_SOCKET; leaves the child's stdin to itself
+    #[cfg(unix)]
+    UnixSocket(PathBuf),
+}
+
+// an event as it goes to the child: its length as 4 bytes big-endian, then
+// that much JSON (see json::to_string). None for custom payloads, which
+// don't serialize
+pub fn frame(event: &Event) -> Option<Vec<u8>> {
+    let text = json::to_string(event).ok()?;
+    let mut frame = (text.len() as u32).to_be_bytes().to_vec();
+    frame.extend(text.as_bytes());
+    Some(frame)
+}
+
+// runs an output in a process of its own and streams events to it as frames
+// (see frame()), for output code that might crash or whose licence keeps it
+// out of tonic; the end of the stream means stop. A child that exits is
+// started again with the next event, no more than once per RESPAWN_DELAY;
+// what it writes to stdout and stderr shows up in tonic's
+pub struct ProcessBackend {
+    pub command: String,
+    pub args: Vec<String>,
+    pub channel: Channel,
+    child: Option<Child>,
+    input: Option<BufWriter<Box<dyn Write + Send>>>,
+    spawned: Option<Instant>,
+}
+
+impl ProcessBackend {
+    pub fn new(command: &str, args: &[&str]) -> Self {
+        Self {
+            command: command.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            channel: Channel::Stdin,
+            child: None,
+            input: None,
+            spawned: None,
+        }
+    }
+
+    #[cfg(unix)]
+    pub fn with_socket<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.channel = Channel::UnixSocket(path.into());
+        self
+    }
+
+    fn spawn(&mut self) -> Result<(), BackendError> {
+        if self
+            .spawned
+            .is_some_and(|spawned| spawned.elapsed() < RESPAWN_DELAY)
+        {
+            return Err(format!("{} keeps exiting", self.command).into());
+        }
+        self.spawned = Some(Instant::now());
+        let mut command = Command::new(&self.command);
+        command.args(&self.args);
+        let failed = |error: std::io::Error| format!("can't start {}: {}", self.command, error);
+        match &self.channel {
+            Channel::Stdin => {
+                let mut child = command.stdin(Stdio::piped()).spawn().map_err(failed)?;
+                let stdin = child.stdin.take().ok_or("no stdin")?;
+                self.input = Some(BufWriter::new(Box::new(stdin)));
+                self.child = Some(child);
+            }
+            #[cfg(unix)]
+            Channel::UnixSocket(path) => {
+                use std::os::unix::net::UnixListener;
+
+                // left over from a run that didn't get to clean up
+                let _ = std::fs::remove_file(path);
+                let listener = UnixListener::bind(path)
+                    .map_err(|error| format!("can't listen on {}: {}", path.display(), error))?;
+                listener.set_nonblocking(true)?;
+                let mut child = command.env("TONIC_SOCKET", path).spawn().map_err(failed)?;
+                let deadline = Instant::now() + CONNECT_TIMEOUT;
+                let stream = loop {
+                    if let Ok((stream, _)) = listener.accept() {
+                        break stream;
+                    }
+                    if Instant::now() > deadline || child.try_wait()?.is_some() {
+                        let _ = child.kill();
+                        let _ = child.wait();
+                        return Err(format!("{} didn't connect", self.command).into());
+                    }
+                    thread::sleep(WAIT_POLL);
+                };
+                stream.set_nonblocking(false)?;
+                self.input = Some(BufWriter::new(Box::new(stream)));
+                self.child = Some(child);
+            }
+        }
+        Ok(())
+    }
+
+    // forgets a child that has exited, and says how it went
+    fn reap(&mut self) -> Option<ExitStatus> {
+        let status = self.child.as_mut()?.try_wait().ok()??;
+        self.child = None;
+        self.input = None;
+        Some(status)
+    }
+
+    // ends the child's input and gives it EXIT_TIMEOUT to exit on its own
+    fn finish(&mut self) -> Result<(), BackendError> {
+        let flushed = match self.input.take() {
+            Some(mut input) => input.flush(),
+            None => Ok(()),
+        };
+        if let Some(mut child) = self.child.take() {
+            let deadline = Instant::now() + EXIT_TIMEOUT;
+            while child.try_wait()?.is_none() {
+                if Instant::now() > deadline {
+                    eprintln!("[process] {} didn't exit, killing it", self.command);
+                    child.kill()?;
+                    child.wait()?;
+                    break;
+                }
+                thread::sleep(WAIT_POLL);
+            }
+        }
+        Ok(flushed?)
+    }
+}
+
+impl Backend for ProcessBackend {
+    fn start(&mut self) -> Result<(), BackendError> {
+        self.spawned = None;
+        self.spawn()
+    }
+
+    fn send(&mut self, event: &Event) -> Result<(), BackendError> {
+        let frame = match frame(event) {
+            Some(frame) => frame,
+            None => return Ok(()),
+        };
+        if let Some(status) = self.reap() {
+            eprintln!("[process] {} exited ({})", self.command, status);
+        }
+        if self.child.is_none() {
+            self.spawn()?;
+        }
+        let input = self.input.as_mut().ok_or("not running")?;
+        let written = input.write_all(&frame);
+        // it's on its way out, reaped with the next event
+        if written.is_err() {
+            self.input = None;
+        }
+        Ok(written?)
+    }
+
+    fn flush(&mut self) -> Result<(), BackendError> {
+        let flushed = match self.input.as_mut() {
+            Some(input) => input.flush(),
+            None => return Ok(()),
+        };
+        if flushed.is_err() {
+            self.input = None;
+        }
+        Ok(flushed?)
+    }
+
+    fn poll_interval(&self) -> Option<Duration> {
+        Some(RESPAWN_DELAY)
+    }
+
+    // an exit shows up in the backend's status even with nothing to send
+    fn poll(&mut self) -> Result<(), BackendError> {
+        match self.reap() {
+            Some(status) => Err(format!("{} exited ({})", self.command, status).into()),
+            None => Ok(()),
+        }
+    }
+
+    fn is_connected(&self) -> bool {
+        self.child.is_some() && self.input.is_some()
+    }
+
+    fn stop(&mut self) -> Result<(), BackendError> {
+        let finished = self.finish();
+        #[cfg(unix)]
+        if let Channel::UnixSocket(path) = &self.channel {
+            let _ = std::fs::remove_file(path);
+        }
+        finished
+    }
+
+    fn name(&self) -> String {
+        self.command.clone()
+    }
+}
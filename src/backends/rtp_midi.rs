@@ -0,0 +1,383 @@
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use crate::backends::midi::{panic_messages, MidiEvent};
+use crate::backends::{Backend, BackendError};
+use crate::event::{Control, Event};
+
+// AppleMIDI session packets start with this where RTP has its header
+const SIGNATURE: [u8; 2] = [0xFF, 0xFF];
+const INVITATION: [u8; 2] = *b"IN";
+const ACCEPTED: [u8; 2] = *b"OK";
+const DECLINED: [u8; 2] = *b"NO";
+const END_SESSION: [u8; 2] = *b"BY";
+const SYNC: [u8; 2] = *b"CK";
+const PROTOCOL_VERSION: u32 = 2;
+// version 2, no padding, extension or CSRCs
+const RTP_FLAGS: u8 = 0x80;
+const MIDI_PAYLOAD_TYPE: u8 = 0x61;
+// the most a command section's long header can give as the MIDI list's length
+const MAX_LIST: usize = 0x0FFF;
+const INVITATION_TIMEOUT: Duration = Duration::from_millis(500);
+const INVITATION_ATTEMPTS: u32 = 3;
+// peers end sessions whose clock sync goes quiet
+const SYNC_INTERVAL: Duration = Duration::from_secs(10);
+// incoming packets are read this often with nothing to send, and a session
+// the other side ended is asked for again
+const POLL: Duration = Duration::from_secs(1);
+
+// IN, OK, NO and BY: the command, protocol version, initiator token, SSRC
+// and, with an invitation, the session name
+fn session_packet(command: [u8; 2], token: u32, ssrc: u32, name: Option<&str>) -> Vec<u8> {
+    let mut packet = SIGNATURE.to_vec();
+    packet.extend(&command);
+    packet.extend(&PROTOCOL_VERSION.to_be_bytes());
+    packet.extend(&token.to_be_bytes());
+    packet.extend(&ssrc.to_be_bytes());
+    if let Some(name) = name {
+        packet.extend(name.as_bytes());
+        packet.push(0);
+    }
+    packet
+}
+
+// CK: count 0 is the initiator's first timestamp, 1 the answer adding the
+// peer's, 2 the initiator's last, from which the peer works out the offset
+fn sync_packet(ssrc: u32, count: u8, timestamps: [u64; 3]) -> Vec<u8> {
+    let mut packet = SIGNATURE.to_vec();
+    packet.extend(&SYNC);
+    packet.extend(&ssrc.to_be_bytes());
+    packet.push(count);
+    packet.extend(&[0; 3]);
+    for timestamp in timestamps.iter() {
+        packet.extend(&timestamp.to_be_bytes());
+    }
+    packet
+}
+
+fn command(packet: &[u8]) -> Option<[u8; 2]> {
+    match packet {
+        [0xFF, 0xFF, first, second, ..] => Some([*first, *second]),
+        _ => None,
+    }
+}
+
+fn word(packet: &[u8], at: usize) -> Option<u32> {
+    let bytes = packet.get(at..at + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn timestamp(packet: &[u8], index: usize) -> Option<u64> {
+    let at = 12 + index * 8;
+    let bytes = packet.get(at..at + 8)?;
+    let mut timestamp = [0; 8];
+    timestamp.copy_from_slice(bytes);
+    Some(u64::from_be_bytes(timestamp))
+}
+
+// an RTP packet with `messages` as one MIDI list, all on its timestamp; there's
+// no recovery journal, so nothing lost is made up for
+fn rtp_packet(
+    sequence: u16,
+    timestamp: u32,
+    ssrc: u32,
+    messages: &[Vec<u8>],
+) -> Result<Vec<u8>, BackendError> {
+    let mut list = vec![];
+    for (index, midi) in messages.iter().enumerate() {
+        // the first command goes without a delta time, the rest are 0 apart
+        if index > 0 {
+            list.push(0);
+        }
+        list.extend(midi);
+    }
+    if list.len() > MAX_LIST {
+        return Err(format!("{} bytes of MIDI don't fit in a packet", list.len()).into());
+    }
+    let mut packet = vec![RTP_FLAGS, MIDI_PAYLOAD_TYPE];
+    packet.extend(&sequence.to_be_bytes());
+    packet.extend(&timestamp.to_be_bytes());
+    packet.extend(&ssrc.to_be_bytes());
+    // the long header when the length needs more than 4 bits
+    if list.len() > 0x0F {
+        packet.push(0x80 | (list.len() >> 8) as u8);
+    }
+    packet.push(list.len() as u8);
+    packet.extend(list);
+    Ok(packet)
+}
+
+// a control socket and a data socket on the port after it, as peers expect
+fn bind_pair() -> io::Result<(UdpSocket, UdpSocket)> {
+    let mut taken = None;
+    for _ in 0..16 {
+        let control = UdpSocket::bind("0.0.0.0:0")?;
+        let port = match control.local_addr()?.port().checked_add(1) {
+            Some(port) => port,
+            None => continue,
+        };
+        match UdpSocket::bind(("0.0.0.0", port)) {
+            Ok(data) => return Ok((control, data)),
+            Err(error) => taken = Some(error),
+        }
+    }
+    Err(taken.unwrap_or_else(|| io::Error::new(io::ErrorKind::AddrInUse, "no two ports in a row")))
+}
+
+// whatever arrived on `socket` since it was last read
+fn incoming(socket: &UdpSocket) -> Vec<Vec<u8>> {
+    let mut packets = vec![];
+    let mut buffer = [0; 1024];
+    if socket.set_nonblocking(true).is_err() {
+        return packets;
+    }
+    while let Ok((read, _)) = socket.recv_from(&mut buffer) {
+        packets.push(buffer[..read].to_vec());
+    }
+    let _ = socket.set_nonblocking(false);
+    packets
+}
+
+struct Session {
+    control: UdpSocket,
+    data: UdpSocket,
+    // the other side's control port, its data port is the next one
+    peer: SocketAddr,
+    token: u32,
+    // false once the other side ended it, until it accepts again
+    joined: bool,
+    synced: Instant,
+}
+
+impl Session {
+    fn data_peer(&self) -> SocketAddr {
+        let mut data = self.peer;
+        data.set_port(self.peer.port().wrapping_add(1));
+        data
+    }
+}
+
+// plays through an RTP-MIDI (AppleMIDI) session over the network: macOS
+// Network MIDI, rtpMIDI on Windows, or interfaces like the iConnectivity
+// mioXM, no cable needed. tonic invites the other side, keeps the session's
+// clocks in sync and sends every event as an RTP packet of its own. There's no
+// recovery journal, so on a lossy network a lost packet stays lost. A session
+// the other side ends is asked for again every second
+pub struct RtpMidiBackend {
+    // host:port of the other side's session (its control port), e.g.
+    // "192.168.1.20:5004"
+    pub target: String,
+    // what the session is listed as over there
+    pub session_name: String,
+    ssrc: u32,
+    session: Option<Session>,
+    sequence: u16,
+    // timestamps count from here, in 100 microsecond steps
+    epoch: Instant,
+}
+
+impl RtpMidiBackend {
+    pub fn new(target: &str) -> Self {
+        Self {
+            target: target.to_string(),
+            session_name: "tonic".to_string(),
+            ssrc: rand::random(),
+            session: None,
+            sequence: 0,
+            epoch: Instant::now(),
+        }
+    }
+
+    pub fn with_session_name(mut self, session_name: &str) -> Self {
+        self.session_name = session_name.to_string();
+        self
+    }
+
+    fn now(&self) -> u64 {
+        (self.epoch.elapsed().as_micros() / 100) as u64
+    }
+
+    // on the control port, then the data port, as the protocol has it
+    fn invite(&mut self) -> Result<(), BackendError> {
+        let session = self.session.as_mut().ok_or("session not open")?;
+        session.token = rand::random();
+        let invitation = session_packet(
+            INVITATION,
+            session.token,
+            self.ssrc,
+            Some(&self.session_name),
+        );
+        let data_peer = session.data_peer();
+        let target = &self.target;
+        for (socket, peer) in [(&session.control, session.peer), (&session.data, data_peer)] {
+            ask(socket, peer, &invitation, session.token)
+                .map_err(|error| format!("{}: {}", target, error))?;
+        }
+        session.joined = true;
+        self.sync()
+    }
+
+    // starts a clock sync, the answer is taken up by receive() so nothing
+    // waits on it; a missed one is made up for SYNC_INTERVAL later
+    fn sync(&mut self) -> Result<(), BackendError> {
+        let first = self.now();
+        let session = self.session.as_mut().ok_or("session not open")?;
+        session.synced = Instant::now();
+        session.data.send_to(
+            &sync_packet(self.ssrc, 0, [first, 0, 0]),
+            session.data_peer(),
+        )?;
+        Ok(())
+    }
+
+    // answers the other side's clock syncs, finishes our own and notices it
+    // ending the session
+    fn receive(&mut self) -> Result<(), BackendError> {
+        let now = self.now();
+        let session = self.session.as_mut().ok_or("session not open")?;
+        let data_peer = session.data_peer();
+        let packets = incoming(&session.control)
+            .into_iter()
+            .chain(incoming(&session.data));
+        for packet in packets {
+            match command(&packet) {
+                Some(END_SESSION) if session.joined => {
+                    session.joined = false;
+                    eprintln!("[rtp-midi] {} ended the session", self.target);
+                }
+                Some(SYNC) if packet.get(8) == Some(&0) => {
+                    let first = timestamp(&packet, 0).unwrap_or(0);
+                    session
+                        .data
+                        .send_to(&sync_packet(self.ssrc, 1, [first, now, 0]), data_peer)?;
+                }
+                Some(SYNC) if packet.get(8) == Some(&1) => {
+                    let first = timestamp(&packet, 0).unwrap_or(0);
+                    let second = timestamp(&packet, 1).unwrap_or(0);
+                    session
+                        .data
+                        .send_to(&sync_packet(self.ssrc, 2, [first, second, now]), data_peer)?;
+                }
+                // receiver feedback, MIDI coming the other way
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn resync(&mut self) -> Result<(), BackendError> {
+        match self.session.as_ref() {
+            Some(session) if session.joined && session.synced.elapsed() >= SYNC_INTERVAL => {
+                self.sync()
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// sends `invitation` until it's accepted, declined or asked too often
+fn ask(socket: &UdpSocket, peer: SocketAddr, invitation: &[u8], token: u32) -> Result<(), String> {
+    socket
+        .set_read_timeout(Some(INVITATION_TIMEOUT))
+        .map_err(|error| error.to_string())?;
+    let mut buffer = [0; 256];
+    for _ in 0..INVITATION_ATTEMPTS {
+        socket
+            .send_to(invitation, peer)
+            .map_err(|error| error.to_string())?;
+        let deadline = Instant::now() + INVITATION_TIMEOUT;
+        while Instant::now() < deadline {
+            let read = match socket.recv_from(&mut buffer) {
+                Ok((read, _)) => read,
+                Err(_) => break,
+            };
+            let answer = &buffer[..read];
+            if word(answer, 8) != Some(token) {
+                continue;
+            }
+            match command(answer) {
+                Some(ACCEPTED) => return Ok(()),
+                Some(DECLINED) => return Err("declined the invitation".to_string()),
+                _ => {}
+            }
+        }
+    }
+    Err("no answer to the invitation".to_string())
+}
+
+impl Backend for RtpMidiBackend {
+    fn start(&mut self) -> Result<(), BackendError> {
+        let peer = self
+            .target
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| format!("can't resolve {}", self.target))?;
+        let (control, data) = bind_pair()?;
+        self.session = Some(Session {
+            control,
+            data,
+            peer,
+            token: 0,
+            joined: false,
+            synced: Instant::now(),
+        });
+        self.invite()
+    }
+
+    fn send(&mut self, event: &Event) -> Result<(), BackendError> {
+        let messages = match event.control() {
+            Some(Control::Panic) => panic_messages(),
+            _ => event.to_midi_messages(),
+        };
+        if messages.is_empty() {
+            return Ok(());
+        }
+        self.receive()?;
+        if !self.is_connected() {
+            return Err(format!("not in a session with {}", self.target).into());
+        }
+        // a steady stream keeps poll() from being called
+        self.resync()?;
+        let packet = rtp_packet(self.sequence, self.now() as u32, self.ssrc, &messages)?;
+        self.sequence = self.sequence.wrapping_add(1);
+        let session = self.session.as_ref().ok_or("session not open")?;
+        session.data.send_to(&packet, session.data_peer())?;
+        Ok(())
+    }
+
+    fn poll_interval(&self) -> Option<Duration> {
+        Some(POLL)
+    }
+
+    fn poll(&mut self) -> Result<(), BackendError> {
+        self.receive()?;
+        if self.is_connected() {
+            return self.resync();
+        }
+        if self.invite().is_ok() {
+            eprintln!("[rtp-midi] back in session with {}", self.target);
+        }
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.session.as_ref().is_some_and(|session| session.joined)
+    }
+
+    fn stop(&mut self) -> Result<(), BackendError> {
+        let session = match self.session.take() {
+            Some(session) => session,
+            None => return Ok(()),
+        };
+        if session.joined {
+            let goodbye = session_packet(END_SESSION, session.token, self.ssrc, None);
+            session.control.send_to(&goodbye, session.peer)?;
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        self.target.clone()
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::net::UdpSocket;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::backends::{Backend, BackendError};
+use crate::event::{Control, Event, Message, Payload};
+
+// seconds from the NTP epoch (1900) to the unix one
+const NTP_OFFSET: u64 = 2_208_988_800;
+// node ids below this are left to sclang and other clients
+const FIRST_NODE: i32 = 1000;
+const ADD_TO_HEAD: i32 = 0;
+
+// an OSC timetag for `delay` from now
+fn timetag(delay: Duration) -> rosc::OscType {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap() + delay;
+    let secs = since_epoch.as_secs() + NTP_OFFSET;
+    let frac = ((since_epoch.subsec_nanos() as u64) << 32) / 1_000_000_000;
+    rosc::OscType::Time(secs as u32, frac as u32)
+}
+
+fn message(addr: &str, args: Vec<rosc::OscType>) -> rosc::OscPacket {
+    rosc::OscPacket::Message(rosc::OscMessage {
+        addr: addr.to_string(),
+        args,
+    })
+}
+
+fn name(value: &str) -> rosc::OscType {
+    rosc::OscType::String(value.to_string())
+}
+
+fn set(node: i32, param: String, value: f32) -> rosc::OscPacket {
+    message(
+        "/n_set",
+        vec![
+            rosc::OscType::Int(node),
+            rosc::OscType::String(param),
+            rosc::OscType::Float(value),
+        ],
+    )
+}
+
+// synth nodes started by the backend, by the channel and note they play
+struct Nodes {
+    next: i32,
+    sounding: HashMap<(u8, u8), Vec<i32>>,
+}
+
+impl Nodes {
+    fn new() -> Self {
+        Self {
+            next: FIRST_NODE,
+            sounding: HashMap::new(),
+        }
+    }
+
+    fn start(&mut self, channel: u8, note: u8) -> i32 {
+        let node = self.next;
+        self.next += 1;
+        self.sounding.entry((channel, note)).or_default().push(node);
+        node
+    }
+
+    // the oldest node still playing the note, so repeated notes end in order
+    fn stop(&mut self, channel: u8, note: u8) -> Option<i32> {
+        let nodes = self.sounding.get_mut(&(channel, note))?;
+        let node = nodes.remove(0);
+        if nodes.is_empty() {
+            self.sounding.remove(&(channel, note));
+        }
+        Some(node)
+    }
+
+    fn on_channel(&self, channel: u8) -> Vec<i32> {
+        self.sounding
+            .iter()
+            .filter(|((on, _), _)| *on == channel)
+            .flat_map(|(_, nodes)| nodes.iter().cloned())
+            .collect()
+    }
+
+    fn all(&mut self) -> Vec<i32> {
+        self.sounding.drain().flat_map(|(_, nodes)| nodes).collect()
+    }
+}
+
+// plays events on SuperCollider's server directly, tonic being the pattern
+// engine: each note-on starts a synth with /s_new and its note-off sets the
+// node's gate to 0, so synthdefs need a `gate` argument driving an envelope
+// with doneAction 2 (the "default" synthdef has one). Synths get freq, amp,
+// note and chan; a CC sets cc<number> (0.0-1.0) and pitch bend sets bend (in
+// semitones, +-2) on the channel's nodes. Every command goes out in a bundle
+// timestamped `latency` after it arrived, so pair that with the same
+// send-ahead on the scheduler (Scheduler::set_offset) for events to sound on
+// time; OSC payloads are passed through as server commands
+pub struct ScsynthBackend {
+    // host:port scsynth listens on
+    pub target: String,
+    // played unless the event's tag has a synthdef of its own
+    pub synthdef: String,
+    pub synthdefs: HashMap<String, String>,
+    pub group: i32,
+    pub latency: Duration,
+    socket: Option<UdpSocket>,
+    nodes: Nodes,
+    muted: bool,
+}
+
+impl ScsynthBackend {
+    pub fn new(target: &str) -> Self {
+        Self {
+            target: target.to_string(),
+            synthdef: "default".to_string(),
+            synthdefs: HashMap::new(),
+            group: 1,
+            latency: Duration::from_millis(50),
+            socket: None,
+            nodes: Nodes::new(),
+            muted: false,
+        }
+    }
+
+    pub fn with_synthdef(mut self, synthdef: &str) -> Self {
+        self.synthdef = synthdef.to_string();
+        self
+    }
+
+    // events tagged `tag` play `synthdef`, e.g. map_tag("bass", "acid")
+    pub fn map_tag(mut self, tag: &str, synthdef: &str) -> Self {
+        self.synthdefs.insert(tag.to_string(), synthdef.to_string());
+        self
+    }
+
+    pub fn with_group(mut self, group: i32) -> Self {
+        self.group = group;
+        self
+    }
+
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    fn bundle(&self, content: Vec<rosc::OscPacket>) -> Result<(), BackendError> {
+        self.packet(&rosc::OscPacket::Bundle(rosc::OscBundle {
+            timetag: timetag(self.latency),
+            content,
+        }))
+    }
+
+    fn packet(&self, packet: &rosc::OscPacket) -> Result<(), BackendError> {
+        let packet = rosc::encoder::encode(packet).map_err(|error| format!("{:?}", error))?;
+        let socket = self.socket.as_ref().ok_or("socket not open")?;
+        socket.send_to(&packet, &self.target)?;
+        Ok(())
+    }
+
+    // frees every node we started, unbundled so the server does it as soon
+    // as it arrives rather than waiting out the latency; other clients'
+    // nodes in the group are left alone
+    fn panic(&mut self) -> Result<(), BackendError> {
+        let nodes: Vec<rosc::OscType> = self
+            .nodes
+            .all()
+            .into_iter()
+            .map(rosc::OscType::Int)
+            .collect();
+        if nodes.is_empty() {
+            return Ok(());
+        }
+        self.packet(&message("/n_free", nodes))
+    }
+}
+
+impl Backend for ScsynthBackend {
+    fn start(&mut self) -> Result<(), BackendError> {
+        self.socket = Some(UdpSocket::bind("0.0.0.0:0")?);
+        self.muted = false;
+        Ok(())
+    }
+
+    fn send(&mut self, event: &Event) -> Result<(), BackendError> {
+        let channel = event.channel;
+        let content = match &event.payload {
+            Payload::Control(Control::Mute) => {
+                self.muted = true;
+                return Ok(());
+            }
+            Payload::Control(Control::Unmute) => {
+                self.muted = false;
+                return Ok(());
+            }
+            Payload::Control(Control::Panic) => return self.panic(),
+            Payload::Osc { address, args } => {
+                vec![message(
+                    address,
+                    args.iter().map(rosc::OscType::from).collect(),
+                )]
+            }
+            Payload::Midi(Message::NoteOn { note, velocity: 0 })
+            | Payload::Midi(Message::NoteOff { note, .. }) => {
+                match self.nodes.stop(channel, *note) {
+                    Some(node) => vec![set(node, "gate".to_string(), 0.0)],
+                    None => return Ok(()),
+                }
+            }
+            Payload::Midi(Message::NoteOn { note, velocity }) if !self.muted => {
+                let synthdefs = &self.synthdefs;
+                let def = event
+                    .tag
+                    .as_ref()
+                    .and_then(|tag| synthdefs.get(tag))
+                    .unwrap_or(&self.synthdef);
+                let freq = 440.0 * 2f32.powf((*note as f32 - 69.0) / 12.0);
+                let node = self.nodes.start(channel, *note);
+                vec![message(
+                    "/s_new",
+                    vec![
+                        name(def),
+                        rosc::OscType::Int(node),
+                        rosc::OscType::Int(ADD_TO_HEAD),
+                        rosc::OscType::Int(self.group),
+                        name("freq"),
+                        rosc::OscType::Float(freq),
+                        name("amp"),
+                        rosc::OscType::Float(*velocity as f32 / 127.0),
+                        name("note"),
+                        rosc::OscType::Int(*note as i32),
+                        name("chan"),
+                        rosc::OscType::Int(channel as i32),
+                    ],
+                )]
+            }
+            Payload::Midi(Message::ControlChange { controller, value }) => self
+                .nodes
+                .on_channel(channel)
+                .into_iter()
+                .map(|node| set(node, format!("cc{}", controller), *value as f32 / 127.0))
+                .collect(),
+            Payload::Midi(Message::PitchBend { value }) => self
+                .nodes
+                .on_channel(channel)
+                .into_iter()
+                .map(|node| set(node, "bend".to_string(), *value as f32 / 8192.0 * 2.0))
+                .collect(),
+            _ => return Ok(()),
+        };
+        if content.is_empty() {
+            return Ok(());
+        }
+        self.bundle(content)
+    }
+
+    // releases whatever is still playing
+    fn stop(&mut self) -> Result<(), BackendError> {
+        let releases: Vec<_> = self
+            .nodes
+            .all()
+            .into_iter()
+            .map(|node| set(node, "gate".to_string(), 0.0))
+            .collect();
+        let released = if releases.is_empty() {
+            Ok(())
+        } else {
+            self.bundle(releases)
+        };
+        self.socket = None;
+        released
+    }
+
+    fn name(&self) -> String {
+        self.target.clone()
+    }
+}
@@ -0,0 +1,174 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+
+use serde::Deserialize;
+
+use crate::backends::midi::{MidiEvent, SYSEX_START};
+use crate::backends::{Backend, BackendError};
+use crate::event::Event;
+
+// starts every frame, never a MIDI status byte
+pub const FRAME_START: u8 = 0x7E;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Wire {
+    // the MIDI bytes as they are, for boards that speak MIDI already
+    Midi,
+    // five bytes per message, easy to parse on a microcontroller and to
+    // pick up again after a dropped byte:
+    //   FRAME_START, status (kind | channel), data 1, data 2 (0 if unused),
+    //   checksum (status ^ data 1 ^ data 2)
+    // sysex doesn't fit and is left out
+    Framed,
+}
+
+pub fn frame(midi: &[u8]) -> Option<[u8; 5]> {
+    let status = *midi.first()?;
+    if status == SYSEX_START {
+        return None;
+    }
+    let data1 = midi.get(1).cloned().unwrap_or(0);
+    let data2 = midi.get(2).cloned().unwrap_or(0);
+    Some([FRAME_START, status, data1, data2, status ^ data1 ^ data2])
+}
+
+// raw mode at `baud`, so the driver neither echoes nor translates bytes
+#[cfg(unix)]
+fn configure(port: &File, baud: u32) -> Result<(), String> {
+    use std::os::unix::io::AsRawFd;
+
+    let speed = match baud {
+        9600 => libc::B9600,
+        19200 => libc::B19200,
+        38400 => libc::B38400,
+        57600 => libc::B57600,
+        115200 => libc::B115200,
+        230400 => libc::B230400,
+        _ => return Err(format!("unsupported baud rate {}", baud)),
+    };
+    unsafe {
+        let mut termios = std::mem::zeroed();
+        if libc::tcgetattr(port.as_raw_fd(), &mut termios) != 0 {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+        libc::cfmakeraw(&mut termios);
+        libc::cfsetspeed(&mut termios, speed);
+        if libc::tcsetattr(port.as_raw_fd(), libc::TCSANOW, &termios) != 0 {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+    }
+    Ok(())
+}
+
+// elsewhere the port keeps whatever it was set up with
+#[cfg(not(unix))]
+fn configure(_port: &File, _baud: u32) -> Result<(), String> {
+    Ok(())
+}
+
+fn open(path: &str, baud: u32) -> Result<File, String> {
+    let port = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .map_err(|error| error.to_string())?;
+    configure(&port, baud)?;
+    Ok(port)
+}
+
+// sequences Arduino/Teensy contraptions (solenoid drummers, motor
+// instruments) over a serial port, as MIDI bytes or compact frames (see
+// Wire); a board that resets or is unplugged is reopened with the next event
+pub struct SerialBackend {
+    // e.g. "/dev/ttyACM0"
+    pub path: String,
+    pub baud: u32,
+    pub wire: Wire,
+    port: Option<File>,
+    // what the port last failed with, until it's open again
+    error: Option<String>,
+}
+
+impl SerialBackend {
+    pub fn new(path: &str, baud: u32) -> Self {
+        Self {
+            path: path.to_string(),
+            baud,
+            wire: Wire::Framed,
+            port: None,
+            error: None,
+        }
+    }
+
+    pub fn with_wire(mut self, wire: Wire) -> Self {
+        self.wire = wire;
+        self
+    }
+
+    fn reopen(&mut self) -> Result<(), String> {
+        match open(&self.path, self.baud) {
+            Ok(port) => {
+                self.port = Some(port);
+                self.error = None;
+                Ok(())
+            }
+            Err(error) => {
+                self.error = Some(error.clone());
+                Err(error)
+            }
+        }
+    }
+}
+
+impl Backend for SerialBackend {
+    // a missing board shows up now rather than with the first note, but
+    // isn't fatal: it's looked for again with every event
+    fn start(&mut self) -> Result<(), BackendError> {
+        self.port = None;
+        if let Err(error) = self.reopen() {
+            eprintln!("[serial] can't open {}: {}", self.path, error);
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, event: &Event) -> Result<(), BackendError> {
+        let bytes: Vec<u8> = event
+            .to_midi_messages()
+            .into_iter()
+            .flat_map(|midi| match self.wire {
+                Wire::Midi => midi,
+                Wire::Framed => frame(&midi).map(|frame| frame.to_vec()).unwrap_or_default(),
+            })
+            .collect();
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        if self.port.is_none() {
+            self.reopen()?;
+        }
+        let written = self.port.as_mut().unwrap().write_all(&bytes);
+        if let Err(error) = &written {
+            self.port = None;
+            self.error = Some(error.to_string());
+        }
+        Ok(written?)
+    }
+
+    // the board is looked for again with the next event
+    fn is_connected(&self) -> bool {
+        self.port.is_some()
+    }
+
+    fn missing(&self) -> Option<String> {
+        self.error.clone()
+    }
+
+    fn stop(&mut self) -> Result<(), BackendError> {
+        self.port = None;
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        self.path.clone()
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use crate::backends::{Backend, BackendError};
+use crate::event::Event;
+
+#[derive(Default)]
+struct Inbox {
+    events: VecDeque<Event>,
+    waker: Option<Waker>,
+    // the backend stopped, nothing more is coming
+    closed: bool,
+}
+
+impl Inbox {
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+// hands events to async code: a task on the application's executor (tokio or
+// any other, nothing here depends on one) awaits them on the EventStream, so a
+// web control surface or network sync can live next to the scheduler without
+// a thread of its own per output
+pub struct StreamBackend {
+    inbox: Arc<Mutex<Inbox>>,
+}
+
+impl StreamBackend {
+    pub fn new() -> (Self, EventStream) {
+        let inbox = Arc::new(Mutex::new(Inbox::default()));
+        let backend = Self {
+            inbox: inbox.clone(),
+        };
+        (backend, EventStream { inbox })
+    }
+}
+
+// events go into the inbox, waking whichever task is waiting
+impl Backend for StreamBackend {
+    fn start(&mut self) -> Result<(), BackendError> {
+        self.inbox.lock().unwrap().closed = false;
+        Ok(())
+    }
+
+    fn send(&mut self, event: &Event) -> Result<(), BackendError> {
+        let mut inbox = self.inbox.lock().unwrap();
+        inbox.events.push_back(event.clone());
+        inbox.wake();
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), BackendError> {
+        let mut inbox = self.inbox.lock().unwrap();
+        inbox.closed = true;
+        inbox.wake();
+        Ok(())
+    }
+}
+
+pub struct EventStream {
+    inbox: Arc<Mutex<Inbox>>,
+}
+
+impl EventStream {
+    // resolves to the next event, or None once the backend stopped
+    //
+    //   while let Some(event) = stream.recv().await { ... }
+    pub fn recv(&mut self) -> Recv<'_> {
+        Recv { stream: self }
+    }
+
+    pub fn try_recv(&mut self) -> Option<Event> {
+        self.inbox.lock().unwrap().events.pop_front()
+    }
+}
+
+pub struct Recv<'a> {
+    stream: &'a mut EventStream,
+}
+
+impl<'a> Future for Recv<'a> {
+    type Output = Option<Event>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Event>> {
+        let mut inbox = self.stream.inbox.lock().unwrap();
+        match inbox.events.pop_front() {
+            Some(event) => Poll::Ready(Some(event)),
+            None if inbox.closed => Poll::Ready(None),
+            None => {
+                inbox.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
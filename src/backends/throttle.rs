@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::backends::{Backend, BackendError};
+use crate::event::{Event, Message, Payload};
+
+// about what a DIN cable carries comfortably alongside notes, per controller
+pub const DEFAULT_INTERVAL: Duration = Duration::from_millis(10);
+
+fn control_change(event: &Event) -> Option<((u8, u8), u8)> {
+    match event.payload {
+        Payload::Midi(Message::ControlChange { controller, value }) => {
+            Some(((event.channel, controller), value))
+        }
+        _ => None,
+    }
+}
+
+// thins out dense controller streams, an LFO on a filter say, on their way to
+// another backend so they don't flood slow hardware: a CC with the value its
+// channel and controller already have is dropped, and a controller changes
+// at most once per `interval`. A change coming sooner is held back, replaced
+// by any newer one, and goes out when the interval is up, so the last value
+// always arrives, if a little after the notes around it. Everything else
+// passes straight through
+pub struct ThrottleBackend {
+    pub inner: Box<dyn Backend>,
+    pub interval: Duration,
+    // (channel, controller) to the value last sent and when
+    sent: HashMap<(u8, u8), (u8, Instant)>,
+    // the newest change held back for each, waiting for its interval
+    held: HashMap<(u8, u8), Event>,
+    // when the inner backend was last polled, it has its own interval
+    polled: Instant,
+}
+
+impl ThrottleBackend {
+    pub fn new(inner: Box<dyn Backend>) -> Self {
+        Self {
+            inner,
+            interval: DEFAULT_INTERVAL,
+            sent: HashMap::new(),
+            held: HashMap::new(),
+            polled: Instant::now(),
+        }
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    fn forward(&mut self, key: (u8, u8), value: u8, event: &Event) -> Result<(), BackendError> {
+        self.sent.insert(key, (value, Instant::now()));
+        self.inner.send(event)
+    }
+
+    // sends the held back changes whose interval is up, or all of them
+    fn release(&mut self, all: bool) -> Result<(), BackendError> {
+        let interval = self.interval;
+        let sent = &self.sent;
+        let due: Vec<(u8, u8)> = self
+            .held
+            .keys()
+            .filter(|key| all || sent.get(key).is_none_or(|(_, at)| at.elapsed() >= interval))
+            .cloned()
+            .collect();
+        for key in due {
+            let event = self.held.remove(&key).unwrap();
+            if let Some((_, value)) = control_change(&event) {
+                self.forward(key, value, &event)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Backend for ThrottleBackend {
+    fn start(&mut self) -> Result<(), BackendError> {
+        self.sent.clear();
+        self.held.clear();
+        self.polled = Instant::now();
+        self.inner.start()
+    }
+
+    fn send(&mut self, event: &Event) -> Result<(), BackendError> {
+        // a steady stream keeps poll() from being called
+        self.release(false)?;
+        let (key, value) = match control_change(event) {
+            Some(change) => change,
+            None => return self.inner.send(event),
+        };
+        match self.sent.get(&key) {
+            // back where it was, whatever was held back no longer matters
+            Some(&(last, _)) if last == value => {
+                self.held.remove(&key);
+                return Ok(());
+            }
+            Some(&(_, at)) if at.elapsed() < self.interval => {
+                self.held.insert(key, event.clone());
+                return Ok(());
+            }
+            _ => {}
+        }
+        self.held.remove(&key);
+        self.forward(key, value, event)
+    }
+
+    // only what passes straight through keeps its due instant, held back
+    // changes go out whenever their interval is up
+    fn send_at(&mut self, event: &Event, due: Instant) -> Result<(), BackendError> {
+        if control_change(event).is_some() {
+            return self.send(event);
+        }
+        self.release(false)?;
+        self.inner.send_at(event, due)
+    }
+
+    fn flush(&mut self) -> Result<(), BackendError> {
+        self.inner.flush()
+    }
+
+    fn poll_interval(&self) -> Option<Duration> {
+        match self.inner.poll_interval() {
+            Some(inner) => Some(inner.min(self.interval)),
+            None => Some(self.interval),
+        }
+    }
+
+    fn poll(&mut self) -> Result<(), BackendError> {
+        self.release(false)?;
+        match self.inner.poll_interval() {
+            Some(interval) if self.polled.elapsed() >= interval => {
+                self.polled = Instant::now();
+                self.inner.poll()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // the last values still go out
+    fn stop(&mut self) -> Result<(), BackendError> {
+        let released = self.release(true).and_then(|_| self.inner.flush());
+        let stopped = self.inner.stop();
+        released.and(stopped)
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn missing(&self) -> Option<String> {
+        self.inner.missing()
+    }
+
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+}
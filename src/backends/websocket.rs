@@ -0,0 +1,187 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+use crate::backends::{Backend, BackendError};
+use crate::event::Event;
+use crate::json;
+
+const ACCEPT_POLL: Duration = Duration::from_millis(50);
+const WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+// how long a client gets to send its request, and how long it may be
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(1);
+const MAX_REQUEST: usize = 8 * 1024;
+
+// what plain HTTP requests get: a page that connects back and draws notes as
+// they come in, scrolling along like a piano roll
+const PAGE: &str = r#"<!doctype html>
+<title>tonic</title>
+<body style="margin:0;background:#111">
+<canvas id="roll" style="width:100vw;height:100vh"></canvas>
+<script>
+const canvas = document.getElementById("roll");
+const context = canvas.getContext("2d");
+canvas.width = innerWidth; canvas.height = innerHeight;
+const notes = [];
+const socket = new WebSocket(`ws://${location.host}/`);
+socket.onmessage = (message) => {
+  const event = JSON.parse(message.data);
+  const midi = event.payload.Midi;
+  if (midi && midi.NoteOn && midi.NoteOn.velocity > 0) {
+    notes.push({note: midi.NoteOn.note, channel: event.channel, at: performance.now()});
+  }
+};
+(function draw(now) {
+  context.fillStyle = "rgba(17,17,17,0.3)";
+  context.fillRect(0, 0, canvas.width, canvas.height);
+  for (const note of notes) {
+    const x = canvas.width - (now - note.at) / 10;
+    const y = canvas.height - note.note / 128 * canvas.height;
+    context.fillStyle = `hsl(${note.channel * 22},80%,60%)`;
+    context.fillRect(x, y, 8, canvas.height / 128 + 2);
+  }
+  while (notes.length && now - notes[0].at > canvas.width * 10) notes.shift();
+  requestAnimationFrame(draw);
+})(0);
+</script>
+"#;
+
+// answers one connection: a WebSocket upgrade joins the clients, anything
+// else gets the page. Runs on a thread of its own, so a client that's slow
+// with its request only holds up itself
+fn handshake(mut stream: TcpStream) -> Option<WebSocket<TcpStream>> {
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)).ok()?;
+    let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+    let mut request = vec![];
+    let mut buffer = [0; 1024];
+    while !request.ends_with(b"\r\n\r\n") {
+        if request.len() > MAX_REQUEST || Instant::now() > deadline {
+            return None;
+        }
+        let read = stream.read(&mut buffer).ok()?;
+        if read == 0 {
+            return None;
+        }
+        request.extend(&buffer[..read]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let key = request.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+            Some(value.trim().to_string())
+        } else {
+            None
+        }
+    });
+    match key {
+        Some(key) => {
+            let response = format!(
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                 Connection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                derive_accept_key(key.as_bytes())
+            );
+            stream.write_all(response.as_bytes()).ok()?;
+            // a tab that stopped reading mustn't hold up everyone else
+            stream.set_write_timeout(Some(WRITE_TIMEOUT)).ok()?;
+            Some(WebSocket::from_raw_socket(stream, Role::Server, None))
+        }
+        None => {
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\n\
+                 Connection: close\r\n\r\n{}",
+                PAGE.len(),
+                PAGE
+            );
+            let _ = stream.write_all(response.as_bytes());
+            None
+        }
+    }
+}
+
+// pushes every event to connected browsers as JSON (see json::to_string) the
+// moment it plays, for live visualizations; open http://<address>/ for a
+// scrolling piano roll, or connect to ws://<address>/ from a page of your
+// own. Nothing is kept for late joiners, and what browsers send is ignored
+pub struct WebSocketBackend {
+    // host:port to listen on, e.g. "127.0.0.1:9001"
+    pub address: String,
+    clients: Arc<Mutex<Vec<WebSocket<TcpStream>>>>,
+    accepting: Arc<AtomicBool>,
+    acceptor: Option<JoinHandle<()>>,
+}
+
+impl WebSocketBackend {
+    pub fn new(address: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            clients: Arc::new(Mutex::new(vec![])),
+            accepting: Arc::new(AtomicBool::new(false)),
+            acceptor: None,
+        }
+    }
+}
+
+impl Backend for WebSocketBackend {
+    fn start(&mut self) -> Result<(), BackendError> {
+        let listener = TcpListener::bind(&self.address)
+            .map_err(|error| format!("can't listen on {}: {}", self.address, error))?;
+        listener.set_nonblocking(true)?;
+        println!("[websocket] listening on {}", self.address);
+        self.accepting.store(true, Ordering::SeqCst);
+
+        // polls so it can stop along with the backend
+        let clients = self.clients.clone();
+        let accepting = self.accepting.clone();
+        self.acceptor = Some(thread::spawn(move || {
+            while accepting.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let _ = stream.set_nonblocking(false);
+                        let clients = clients.clone();
+                        thread::spawn(move || {
+                            if let Some(client) = handshake(stream) {
+                                clients.lock().unwrap().push(client);
+                            }
+                        });
+                    }
+                    Err(_) => thread::sleep(ACCEPT_POLL),
+                }
+            }
+        }));
+        Ok(())
+    }
+
+    fn send(&mut self, event: &Event) -> Result<(), BackendError> {
+        // custom payloads don't serialize, there's nothing to show
+        let text = match json::to_string(event) {
+            Ok(text) => text,
+            Err(_) => return Ok(()),
+        };
+        // a browser that went away fails the write and is dropped
+        self.clients
+            .lock()
+            .unwrap()
+            .retain_mut(|client| client.send(Message::text(text.clone())).is_ok());
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), BackendError> {
+        self.accepting.store(false, Ordering::SeqCst);
+        if let Some(acceptor) = self.acceptor.take() {
+            let _ = acceptor.join();
+        }
+        self.clients.lock().unwrap().clear();
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        self.address.clone()
+    }
+}
@@ -0,0 +1,100 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::DEFAULT_VELOCITY;
+use crate::pitch::{split_pitch_class, Pitch};
+
+// chord symbol suffixes and their intervals in semitones above the root
+const QUALITIES: [(&str, &[u8]); 17] = [
+    ("", &[0, 4, 7]),
+    ("maj", &[0, 4, 7]),
+    ("m", &[0, 3, 7]),
+    ("dim", &[0, 3, 6]),
+    ("aug", &[0, 4, 8]),
+    ("sus2", &[0, 2, 7]),
+    ("sus4", &[0, 5, 7]),
+    ("6", &[0, 4, 7, 9]),
+    ("m6", &[0, 3, 7, 9]),
+    ("7", &[0, 4, 7, 10]),
+    ("maj7", &[0, 4, 7, 11]),
+    ("m7", &[0, 3, 7, 10]),
+    ("mmaj7", &[0, 3, 7, 11]),
+    ("m7b5", &[0, 3, 6, 10]),
+    ("dim7", &[0, 3, 6, 9]),
+    ("9", &[0, 4, 7, 10, 14]),
+    ("maj9", &[0, 4, 7, 11, 14]),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseChordError(String);
+
+impl fmt::Display for ParseChordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid chord symbol: {:?}", self.0)
+    }
+}
+
+impl Error for ParseChordError {}
+
+// notes struck together, sent to backends as one note-on per note
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Chord {
+    pub notes: Vec<u8>,
+    pub velocity: u8,
+}
+
+impl Chord {
+    pub fn new<P: Into<Pitch>>(root: P, intervals: &[u8]) -> Self {
+        let root = root.into().0;
+        Self::from_notes(
+            intervals
+                .iter()
+                .map(|&interval| root.saturating_add(interval).min(127))
+                .collect(),
+        )
+    }
+
+    pub fn from_notes(notes: Vec<u8>) -> Self {
+        Self {
+            notes,
+            velocity: DEFAULT_VELOCITY,
+        }
+    }
+
+    // a symbol like "Cmaj7" or "F#m7b5", rooted in the octave of middle C
+    pub fn parse(symbol: &str) -> Result<Self, ParseChordError> {
+        let error = || ParseChordError(symbol.to_string());
+        let (class, quality) = split_pitch_class(symbol.trim()).ok_or_else(error)?;
+        let &(_, intervals) = QUALITIES
+            .iter()
+            .find(|&&(name, _)| name == quality)
+            .ok_or_else(error)?;
+        Ok(Self::new((60 + class) as u8, intervals))
+    }
+
+    // shifts every note, e.g. by -12 for the octave below
+    pub fn transpose(mut self, semitones: i8) -> Self {
+        for note in self.notes.iter_mut() {
+            *note = (*note as i16 + semitones as i16).clamp(0, 127) as u8;
+        }
+        self
+    }
+}
+
+impl FromStr for Chord {
+    type Err = ParseChordError;
+
+    fn from_str(symbol: &str) -> Result<Self, Self::Err> {
+        Self::parse(symbol)
+    }
+}
+
+// for literals in generators, like Pitch
+impl<'a> From<&'a str> for Chord {
+    fn from(symbol: &'a str) -> Self {
+        Self::parse(symbol).unwrap()
+    }
+}
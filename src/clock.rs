@@ -1,112 +1,450 @@
+use std::collections::VecDeque;
 use std::time::{Duration, Instant};
 
+use crate::tempo::{valid_bpm, TempoMap, TempoPoint};
+use crate::time::MusicalTime;
+use crate::timing::shift;
+
+pub const DEFAULT_PPQN: u64 = 96;
+
+const TAP_WINDOW: usize = 4;
+const TAP_TIMEOUT: Duration = Duration::from_secs(2);
+
 #[derive(Debug, Clone)]
 pub struct Clock {
     start: Instant,
-    bar_start: Instant,
-    bpm: u64,
-    bpb: u64,
+    tempo: TempoMap,
+    ppqn: u64,
+    // (bars elapsed when the meter takes over, meter), sorted, first one at 0
+    meters: Vec<(u64, Meter)>,
+    swing: f64,
+    swing_division: f64,
+    paused: Option<f64>,
+    taps: VecDeque<Instant>,
+}
+
+// time signature, e.g. Meter::new(7, 8) for 7/8
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Meter {
+    pub beats: u64,
+    pub unit: u64,
+}
+
+impl Meter {
+    pub fn new(beats: u64, unit: u64) -> Self {
+        Self { beats, unit }
+    }
+
+    // bar length in clock beats (quarter notes)
+    pub fn length(&self) -> f64 {
+        self.beats as f64 * 4.0 / self.unit as f64
+    }
+}
+
+// lines quantize() snaps to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Grid {
+    Beat,
+    Bar,
+    // a fraction of a beat, e.g. 0.25 for 16ths
+    Subdivision(f64),
+}
+
+// exact to the nanosecond, whole-millisecond beats drift against other gear
+pub fn beat_duration(beats: f64, bpm: f64) -> Duration {
+    Duration::from_secs_f64(beats * 60.0 / bpm)
 }
 
-pub fn beat_ms(beat: u64, bpm: u64) -> Duration {
-    Duration::from_millis(beat * (60000 / bpm))
+// a beat position as whole beats and the nearest tick past them
+pub fn split_position(position: f64, ppqn: u64) -> (u64, u64) {
+    let beat = position.floor();
+    let tick = ((position - beat) * ppqn as f64).round() as u64;
+    (beat as u64 + tick / ppqn, tick % ppqn)
 }
 
 impl Clock {
-    pub fn new(bpm: u64) -> Self {
+    // panics on a tempo valid_bpm() turns down, as TempoMap::new does
+    pub fn new(bpm: f64) -> Self {
         let now = Instant::now();
 
         Self {
             start: now,
-            bar_start: now,
-            bpm: bpm,
-            bpb: 4,
+            tempo: TempoMap::new(bpm),
+            ppqn: DEFAULT_PPQN,
+            meters: vec![(0, Meter::new(4, 4))],
+            swing: 0.0,
+            swing_division: 1.0,
+            paused: None,
+            taps: VecDeque::with_capacity(TAP_WINDOW),
         }
     }
 
-    fn start(&self) -> Instant {
+    pub fn start(&self) -> Instant {
         self.start
     }
 
-    fn start_at(&mut self, start_beat: u64) {
-        let new_start = Instant::now() - self.tick() * start_beat as u32;
-        self.start = new_start;
+    pub fn start_at(&mut self, start_beat: u64) {
+        self.start_from(start_beat as f64);
     }
 
-    fn bar_start(&self) -> Instant {
-        self.bar_start
+    // negative positions leave room for a count-in before beat 0
+    pub fn start_from(&mut self, position: f64) {
+        self.start = shift(Instant::now(), -self.secs_at(position));
+        self.paused = None;
     }
 
-    fn bar_start_at(&mut self, start_bar: u64) {
-        let new_bar_start = Instant::now() - self.tock() * start_bar as u32;
-        self.bar_start = new_bar_start;
+    // jumps to `position`, staying paused if the clock is paused
+    pub fn seek(&mut self, position: f64) {
+        match self.paused {
+            Some(_) => self.paused = Some(position),
+            None => self.start_from(position),
+        }
+    }
+
+    // freezes the position until resume() is called
+    pub fn pause(&mut self) {
+        if self.paused.is_none() {
+            self.paused = Some(self.position());
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if let Some(position) = self.paused.take() {
+            self.start = shift(Instant::now(), -self.secs_at(position));
+        }
     }
 
-    fn tick(&self) -> Duration {
-        beat_ms(1, self.bpm)
+    pub fn is_paused(&self) -> bool {
+        self.paused.is_some()
     }
 
-    fn tock(&self) -> Duration {
-        beat_ms(self.bpb, self.bpm)
+    // rewinds to the top and holds there
+    pub fn stop(&mut self) {
+        self.paused = Some(0.0);
     }
 
-    fn beat(&self) -> u64 {
-        let delta: Duration = Instant::now() - self.start;
-        let current_beat = delta.div_duration_f64(self.tick());
-        (current_beat + 1.0) as u64
+    pub fn tick(&self) -> Duration {
+        beat_duration(1.0, self.bpm())
     }
 
-    pub fn beat_at(&self, beat: u64) -> Instant {
-        self.start + beat as u32 * self.tick()
+    pub fn tock(&self) -> Duration {
+        beat_duration(self.bpb(), self.bpm())
     }
 
-    fn beat_phase(&self) -> f64 {
-        let delta = Instant::now() - self.start;
-        let current_beat = delta.div_duration_f64(self.tick());
+    // seconds from start to the given (fractional) beat position
+    fn secs_at(&self, position: f64) -> f64 {
+        self.tempo.secs_at(position)
+    }
+
+    // (fractional) beat position reached after `secs` seconds from start
+    fn position_at(&self, secs: f64) -> f64 {
+        self.tempo.position_at(secs)
+    }
+
+    pub fn position(&self) -> f64 {
+        self.position_of(Instant::now())
+    }
+
+    // beat position at any instant, past or future
+    pub fn position_of(&self, at: Instant) -> f64 {
+        if let Some(position) = self.paused {
+            return position;
+        }
+        let secs = match at.checked_duration_since(self.start) {
+            Some(since) => since.as_secs_f64(),
+            None => -(self.start - at).as_secs_f64(),
+        };
+        self.position_at(secs)
+    }
+
+    // clock time since the top, as opposed to wall time since start()
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_secs_f64(self.secs_at(self.position().max(0.0)))
+    }
+
+    pub fn beat(&self) -> u64 {
+        (self.position() + 1.0) as u64
+    }
+
+    // fractional positions place 8ths (0.5), 16ths (0.25) or triplets (1/3)
+    pub fn beat_at(&self, position: f64) -> Instant {
+        self.instant_at(self.swung(position))
+    }
+
+    // `tick` is counted in pulses per quarter note past `beat`
+    pub fn tick_at(&self, beat: u64, tick: u64) -> Instant {
+        self.beat_at(beat as f64 + tick as f64 / self.ppqn as f64)
+    }
+
+    pub fn ticks(&self) -> u64 {
+        (self.position() * self.ppqn as f64) as u64
+    }
+
+    pub fn ppqn(&self) -> u64 {
+        self.ppqn
+    }
+
+    // at least 1, positions are split into ticks by it
+    pub fn set_ppqn(&mut self, ppqn: u64) {
+        self.ppqn = ppqn.max(1);
+    }
+
+    pub fn instant_at(&self, position: f64) -> Instant {
+        shift(self.start, self.secs_at(position))
+    }
+
+    pub fn beat_phase(&self) -> f64 {
+        let current_beat = self.position();
         current_beat - current_beat.trunc()
     }
 
-    fn bar(&self) -> u64 {
-        let delta: Duration = Instant::now() - self.bar_start;
-        let current_bar = delta.div_duration_f64(self.tock());
-        (current_bar + 1.0) as u64
+    // beat position reached after `bars` (fractional) bars
+    pub fn bars_position(&self, bars: f64) -> f64 {
+        let mut position = 0.0;
+        for (i, &(from, meter)) in self.meters.iter().enumerate() {
+            match self.meters.get(i + 1) {
+                Some(&(next, _)) if bars > next as f64 => {
+                    position += (next - from) as f64 * meter.length();
+                }
+                _ => return position + (bars - from as f64) * meter.length(),
+            }
+        }
+        position
     }
 
-    fn bar_at(&self, bar: u64) -> Instant {
-        self.bar_start + bar as u32 * self.tock()
+    // (fractional) bars elapsed at the given beat position
+    fn position_bars(&self, position: f64) -> f64 {
+        let mut start = 0.0;
+        for (i, &(from, meter)) in self.meters.iter().enumerate() {
+            let length = meter.length();
+            match self.meters.get(i + 1) {
+                Some(&(next, _)) if position >= start + (next - from) as f64 * length => {
+                    start += (next - from) as f64 * length;
+                }
+                _ => return from as f64 + (position - start) / length,
+            }
+        }
+        0.0
     }
 
-    fn bar_phase(&self) -> f64 {
-        let delta: Duration = Instant::now() - self.start;
-        let current_bar = delta.div_duration_f64(self.tock());
+    pub fn bar(&self) -> u64 {
+        (self.position_bars(self.position()) + 1.0) as u64
+    }
+
+    pub fn bar_at(&self, bar: u64) -> Instant {
+        self.instant_at(self.bars_position(bar as f64))
+    }
+
+    pub fn bar_phase(&self) -> f64 {
+        let current_bar = self.position_bars(self.position());
         current_bar - current_bar.trunc()
     }
 
-    fn bpm(&self) -> u64 {
-        self.bpm
+    // beat position of a bar.beat.tick time under the current meter map
+    pub fn time_position(&self, time: MusicalTime) -> f64 {
+        self.bars_position(time.bar.saturating_sub(1) as f64)
+            + time.beat.saturating_sub(1) as f64
+            + time.tick as f64 / self.ppqn as f64
     }
 
-    fn set_bpm(&mut self, new_bpm: u64) {
-        let current_beat = self.beat();
-        let current_bar = self.bar();
-        let new_tick = beat_ms(1, new_bpm);
-        let new_tock = new_tick * self.bpb as u32;
-        let new_start = self.beat_at(current_beat) - new_tick * current_beat as u32;
-        let new_bar_start = self.bar_at(current_bar) - new_tock * current_bar as u32;
-        self.start = new_start;
-        self.bar_start = new_bar_start;
-        self.bpm = new_bpm;
+    pub fn musical_time(&self, position: f64) -> MusicalTime {
+        let bars = self.position_bars(position).floor();
+        let in_bar = position - self.bars_position(bars);
+        let beat = in_bar.floor();
+        let tick = ((in_bar - beat) * self.ppqn as f64) as u64;
+        MusicalTime::new(bars as u64 + 1, beat as u64 + 1, tick)
+    }
+
+    pub fn time_at(&self, time: MusicalTime) -> Instant {
+        self.instant_at(self.swung(self.time_position(time)))
+    }
+
+    pub fn current_time(&self) -> MusicalTime {
+        self.musical_time(self.position())
+    }
+
+    // whole beats and leftover ticks, as carried by events
+    pub fn beat_tick(&self, time: MusicalTime) -> (u64, u64) {
+        split_position(self.time_position(time), self.ppqn)
+    }
+
+    // quantization points, as beat positions (see instant_at for the Instant)
+
+    pub fn next_beat(&self) -> u64 {
+        self.position().floor() as u64 + 1
+    }
+
+    // may be fractional in meters like 7/8
+    pub fn next_bar(&self) -> f64 {
+        let bars = self.position_bars(self.position()).floor();
+        self.bars_position(bars + 1.0)
+    }
+
+    // next beat that is a multiple of `beats`, e.g. 16 to launch on 4 bars of 4/4
+    pub fn next_multiple_of(&self, beats: u64) -> u64 {
+        (self.position() / beats as f64).floor() as u64 * beats + beats
+    }
+
+    // the grid line nearest `position`
+    pub fn quantize(&self, position: f64, grid: Grid) -> f64 {
+        self.snap(position, grid, f64::round)
+    }
+
+    // the first grid line at or past `position`
+    pub fn quantize_next(&self, position: f64, grid: Grid) -> f64 {
+        self.snap(position, grid, f64::ceil)
+    }
+
+    fn snap(&self, position: f64, grid: Grid, round: fn(f64) -> f64) -> f64 {
+        match grid {
+            Grid::Beat => round(position),
+            Grid::Bar => self.bars_position(round(self.position_bars(position))),
+            Grid::Subdivision(step) => round(position / step) * step,
+        }
+    }
+
+    pub fn meter(&self) -> Meter {
+        self.meter_at(self.bar())
+    }
+
+    pub fn meter_at(&self, bar: u64) -> Meter {
+        let bars = bar.saturating_sub(1);
+        self.meters
+            .iter()
+            .rev()
+            .find(|&&(from, _)| from <= bars)
+            .map(|&(_, meter)| meter)
+            .unwrap()
+    }
+
+    // switches to `meter` from the given bar (1-based) on, dropping later changes
+    pub fn set_meter(&mut self, from_bar: u64, meter: Meter) {
+        let from = from_bar.saturating_sub(1);
+        self.meters.retain(|&(at, _)| at < from);
+        self.meters.push((from, meter));
+    }
+
+    // delays every second `division`-beat step by `amount` of a step
+    // (0.0 is straight, ~0.33 gives a triplet feel)
+    pub fn set_swing(&mut self, amount: f64, division: f64) {
+        self.swing = amount.clamp(0.0, 1.0);
+        self.swing_division = division;
+    }
+
+    fn swung(&self, position: f64) -> f64 {
+        if self.swing == 0.0 {
+            return position;
+        }
+        let pair = 2.0 * self.swing_division;
+        let base = (position / pair).floor() * pair;
+        let phase = (position - base) / pair;
+        let split = 0.5 + self.swing / 2.0;
+        let warped = if phase < 0.5 {
+            phase * 2.0 * split
+        } else {
+            split + (phase - 0.5) * 2.0 * (1.0 - split)
+        };
+        base + warped * pair
+    }
+
+    pub fn bpm(&self) -> f64 {
+        self.bpm_at(self.position())
+    }
+
+    fn bpm_at(&self, position: f64) -> f64 {
+        self.tempo.bpm_at(position)
+    }
+
+    // changes tempo from the next beat on, replacing any later tempo changes
+    // like every tempo change here, one valid_bpm() turns down is ignored
+    pub fn set_bpm(&mut self, new_bpm: f64) {
+        if !valid_bpm(new_bpm) {
+            return;
+        }
+        let from_beat = self.beat() as f64;
+        self.tempo.truncate(from_beat);
+        self.tempo.insert(TempoPoint {
+            beat: from_beat,
+            bpm: new_bpm,
+            ramp_to: None,
+        });
+    }
+
+    // phase-locks the clock to an external source that is at `position` right now
+    pub fn sync(&mut self, position: f64, bpm: f64) {
+        if !position.is_finite() || !valid_bpm(bpm) {
+            return;
+        }
+        self.tempo = TempoMap::new(bpm);
+        self.start = shift(Instant::now(), -self.secs_at(position));
+    }
+
+    // gradually moves tempo to `target_bpm`, starting from the next beat
+    pub fn ramp_bpm(&mut self, target_bpm: f64, over_beats: f64) {
+        if !valid_bpm(target_bpm) || !over_beats.is_finite() {
+            return;
+        }
+        if over_beats <= 0.0 {
+            self.set_bpm(target_bpm);
+            return;
+        }
+        let from_beat = self.beat() as f64;
+        let from_bpm = self.bpm_at(from_beat);
+        self.tempo.truncate(from_beat);
+        self.tempo.insert(TempoPoint {
+            beat: from_beat,
+            bpm: from_bpm,
+            ramp_to: Some(target_bpm),
+        });
+        self.tempo.insert(TempoPoint {
+            beat: from_beat + over_beats,
+            bpm: target_bpm,
+            ramp_to: None,
+        });
+    }
+
+    pub fn tempo_map(&self) -> &TempoMap {
+        &self.tempo
+    }
+
+    // tempo changes that play out as the clock advances, e.g. TempoMap::load
+    pub fn set_tempo_map(&mut self, tempo: TempoMap) {
+        self.tempo = tempo;
+    }
+
+    // tap tempo: averages the intervals of the last few taps, a long gap starts over
+    pub fn tap(&mut self) -> Option<f64> {
+        let now = Instant::now();
+        if let Some(&last) = self.taps.back() {
+            if now - last > TAP_TIMEOUT {
+                self.taps.clear();
+            }
+        }
+        if self.taps.len() == TAP_WINDOW {
+            self.taps.pop_front();
+        }
+        self.taps.push_back(now);
+
+        if self.taps.len() < 2 {
+            return None;
+        }
+        let span = now - self.taps[0];
+        let interval = span.as_secs_f64() / (self.taps.len() - 1) as f64;
+        let bpm = 60.0 / interval;
+        if !valid_bpm(bpm) {
+            return None;
+        }
+        self.set_bpm(bpm);
+        Some(bpm)
     }
 
-    fn bpb(&self) -> u64 {
-        self.bpb
+    pub fn bpb(&self) -> f64 {
+        self.meter().length()
     }
 
-    fn set_bpb(&mut self, new_bpb: u64) {
-        let current_bar = self.bar();
-        let new_tock = beat_ms(new_bpb, self.bpm);
-        let new_bar_start = self.bar_at(current_bar) - new_tock * current_bar as u32;
-        self.bar_start = new_bar_start;
-        self.bpb = new_bpb;
+    // changes beats per bar from the next bar on
+    pub fn set_bpb(&mut self, new_bpb: u64) {
+        let next_bar = self.bar() + 1;
+        self.set_meter(next_bar, Meter::new(new_bpb, 4));
     }
 }
@@ -0,0 +1,134 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::clock::Clock;
+use crate::timing::sleep_until;
+
+const PAUSED_POLL: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tick {
+    BeatTick(u64),
+    BarTick(u64),
+    // the transport jumped, ticks continue from this bar
+    Seek(u64),
+}
+
+fn broadcast(subscribers: &Mutex<Vec<Sender<Tick>>>, tick: Tick) {
+    subscribers
+        .lock()
+        .unwrap()
+        .retain(|subscriber| subscriber.send(tick).is_ok());
+}
+
+// the beat and bar last ticked, None for none since the last rewind
+type Ticked = (Option<u64>, Option<u64>);
+
+// shares one clock between threads and broadcasts its beats and bars
+#[derive(Clone)]
+pub struct ClockService {
+    clock: Arc<RwLock<Clock>>,
+    subscribers: Arc<Mutex<Vec<Sender<Tick>>>>,
+    ticked: Arc<Mutex<Ticked>>,
+    running: Arc<AtomicBool>,
+    ticker: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl ClockService {
+    pub fn new(clock: Clock) -> Self {
+        let service = Self {
+            clock: Arc::new(RwLock::new(clock)),
+            subscribers: Arc::new(Mutex::new(vec![])),
+            ticked: Arc::new(Mutex::new((None, None))),
+            running: Arc::new(AtomicBool::new(true)),
+            ticker: Arc::new(Mutex::new(None)),
+        };
+        service.run();
+        service
+    }
+
+    pub fn clock(&self) -> &Arc<RwLock<Clock>> {
+        &self.clock
+    }
+
+    pub fn subscribe(&self) -> Receiver<Tick> {
+        let (sender, receiver) = channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    pub fn notify(&self, tick: Tick) {
+        broadcast(&self.subscribers, tick);
+    }
+
+    // moves the clock somewhere else, e.g. to seek or stop, so ticking starts
+    // over from there: the beat and bar it lands on tick even if they already
+    // did. Pausing and resuming leave the clock where it was, and don't need it
+    pub fn rewind<F: FnOnce(&mut Clock)>(&self, change: F) {
+        let mut ticked = self.ticked.lock().unwrap();
+        change(&mut self.clock.write().unwrap());
+        *ticked = (None, None);
+    }
+
+    // stops ticking and hangs up on the subscribers, whose receivers then end
+    pub fn shutdown(&self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(ticker) = self.ticker.lock().unwrap().take() {
+            ticker.join().unwrap();
+        }
+        self.subscribers.lock().unwrap().clear();
+    }
+
+    fn run(&self) {
+        let clock = self.clock.clone();
+        let subscribers = self.subscribers.clone();
+        let running = self.running.clone();
+        let ticked = self.ticked.clone();
+
+        let ticker = thread::spawn(move || {
+            while running.load(Ordering::SeqCst) {
+                // held while ticking so a rewind can't land in between
+                let mut ticked = ticked.lock().unwrap();
+                let (paused, beat, bar, next_beat, downbeat) = {
+                    let clock = clock.read().unwrap();
+                    let beat = clock.beat();
+                    (
+                        clock.is_paused(),
+                        beat,
+                        clock.bar(),
+                        clock.instant_at(beat as f64),
+                        clock.instant_at(0.0),
+                    )
+                };
+                // a paused clock stays on the beat it was about to tick, which
+                // has been ticked already and isn't again on continuing
+                if paused {
+                    drop(ticked);
+                    thread::sleep(PAUSED_POLL);
+                    continue;
+                }
+                // nothing is ticked during a count-in, wait for the downbeat
+                if Instant::now() < downbeat {
+                    drop(ticked);
+                    sleep_until(downbeat);
+                    continue;
+                }
+                let (last_beat, last_bar) = &mut *ticked;
+                if *last_bar != Some(bar) {
+                    broadcast(&subscribers, Tick::BarTick(bar));
+                    *last_bar = Some(bar);
+                }
+                if *last_beat != Some(beat) {
+                    broadcast(&subscribers, Tick::BeatTick(beat));
+                    *last_beat = Some(beat);
+                }
+                drop(ticked);
+                sleep_until(next_beat);
+            }
+        });
+        *self.ticker.lock().unwrap() = Some(ticker);
+    }
+}
@@ -0,0 +1,515 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::backends::artnet::ArtNetBackend;
+use crate::backends::dummy::DummyBackend;
+use crate::backends::log::{LogBackend, LogFormat};
+use crate::backends::midi::{MidiBackend, Unplugged};
+use crate::backends::midi_file::MidiFileBackend;
+use crate::backends::net::{NetBackend, Protocol};
+use crate::backends::osc::{Addresses, OscBackend};
+use crate::backends::plugin::{self, Unavailable};
+use crate::backends::process::ProcessBackend;
+use crate::backends::rtp_midi::RtpMidiBackend;
+use crate::backends::scsynth::ScsynthBackend;
+use crate::backends::serial::{SerialBackend, Wire};
+use crate::backends::throttle::ThrottleBackend;
+use crate::backends::websocket::WebSocketBackend;
+use crate::backends::Backend;
+use crate::clock::DEFAULT_PPQN;
+use crate::json;
+use crate::routing::Route;
+use crate::scheduler::Scheduler;
+
+// the outputs of a tonic process and which tags go where, read from a JSON
+// file:
+//
+//   {"backends": [
+//     {"Midi": {"device": "Prophet"}},
+//     {"Midi": {"device": "TR-8", "channels": {"0": 9}}},
+//     {"Midi": {"device": "IAC Driver"}},
+//     {"Log": {"path": "events.csv", "format": "Csv"}}
+//   ],
+//   "routes": [
+//     {"tag": "lead", "backend": "Prophet"},
+//     {"tag": "drums", "backend": "TR-8"},
+//     {"tag": "drums", "backend": "IAC Driver", "channel": 9}
+//   ]}
+//
+// each backend indexed in the order listed. Routes name their backend the
+// way Backend::name does, the device for MIDI; untagged events and tags
+// without routes go to every backend. Backends that need something only the
+// program has (the metronome's clock, a stream's receiving end) are still
+// added in code
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub backends: Vec<BackendConfig>,
+    #[serde(default)]
+    pub routes: Vec<RouteConfig>,
+}
+
+impl Config {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("can't read {}: {}", path.display(), error))?;
+        json::from_str(&text).map_err(|error| format!("bad config {}: {}", path.display(), error))
+    }
+
+    pub fn backends(&self) -> Vec<Box<dyn Backend>> {
+        self.backends.iter().map(BackendConfig::build).collect()
+    }
+
+    // sets up the routes on a scheduler built with backends(); fails on the
+    // first that names no backend, leaving the earlier ones in place
+    pub fn route(&self, scheduler: &Scheduler) -> Result<(), String> {
+        for route in self.routes.iter() {
+            let backend = scheduler
+                .find_backend(&route.backend)
+                .ok_or_else(|| format!("no backend called \"{}\" to route to", route.backend))?;
+            scheduler.route(&route.tag, route.build(backend));
+        }
+        Ok(())
+    }
+}
+
+// events tagged `tag` to the backend called `backend`, optionally moved to
+// another channel (0-15) on the way
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteConfig {
+    pub tag: String,
+    pub backend: String,
+    pub channel: Option<u8>,
+}
+
+impl RouteConfig {
+    pub fn build(&self, backend: usize) -> Route {
+        let route = Route::to(backend);
+        match self.channel {
+            Some(channel) => route.channel(channel),
+            None => route,
+        }
+    }
+}
+
+// one backend, named by its variant
+#[derive(Debug, Clone, Deserialize)]
+pub enum BackendConfig {
+    Dummy,
+    Midi(MidiBackendConfig),
+    Osc(OscBackendConfig),
+    Scsynth(ScsynthBackendConfig),
+    Net(NetBackendConfig),
+    ArtNet(ArtNetBackendConfig),
+    Serial(SerialBackendConfig),
+    Log(LogBackendConfig),
+    MidiFile(MidiFileBackendConfig),
+    WebSocket(WebSocketBackendConfig),
+    RtpMidi(RtpMidiBackendConfig),
+    Throttle(ThrottleBackendConfig),
+    Plugin(PluginBackendConfig),
+    Process(ProcessBackendConfig),
+    #[cfg(target_os = "linux")]
+    AlsaSeq(AlsaSeqBackendConfig),
+    Audio(AudioBackendConfig),
+    #[cfg(feature = "jack")]
+    Jack(JackBackendConfig),
+}
+
+impl BackendConfig {
+    pub fn build(&self) -> Box<dyn Backend> {
+        match self {
+            BackendConfig::Dummy => Box::new(DummyBackend::new()),
+            BackendConfig::Midi(config) => Box::new(config.build()),
+            BackendConfig::Osc(config) => Box::new(config.build()),
+            BackendConfig::Scsynth(config) => Box::new(config.build()),
+            BackendConfig::Net(config) => Box::new(config.build()),
+            BackendConfig::ArtNet(config) => Box::new(config.build()),
+            BackendConfig::Serial(config) => Box::new(config.build()),
+            BackendConfig::Log(config) => Box::new(config.build()),
+            BackendConfig::MidiFile(config) => Box::new(config.build()),
+            BackendConfig::WebSocket(config) => Box::new(config.build()),
+            BackendConfig::RtpMidi(config) => Box::new(config.build()),
+            BackendConfig::Throttle(config) => Box::new(config.build()),
+            BackendConfig::Plugin(config) => config.build(),
+            BackendConfig::Process(config) => Box::new(config.build()),
+            #[cfg(target_os = "linux")]
+            BackendConfig::AlsaSeq(config) => Box::new(config.build()),
+            BackendConfig::Audio(config) => Box::new(config.build()),
+            #[cfg(feature = "jack")]
+            BackendConfig::Jack(config) => Box::new(config.build()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MidiBackendConfig {
+    pub device: String,
+    #[serde(default)]
+    pub virtual_port: bool,
+    // number of MPE member channels
+    pub mpe: Option<u8>,
+    // "Drop" or {"Buffer": capacity}
+    pub unplugged: Option<Unplugged>,
+    pub velocity_scale: Option<f64>,
+    pub velocity_offset: Option<i16>,
+    // event channel to device channel, both 0-15
+    #[serde(default)]
+    pub channels: HashMap<u8, u8>,
+}
+
+impl MidiBackendConfig {
+    pub fn build(&self) -> MidiBackend {
+        let mut backend = if self.virtual_port {
+            MidiBackend::virtual_port(&self.device)
+        } else {
+            MidiBackend::new(&self.device)
+        };
+        if let Some(members) = self.mpe {
+            backend = backend.with_mpe(members);
+        }
+        if let Some(unplugged) = self.unplugged {
+            backend = backend.when_unplugged(unplugged);
+        }
+        if self.velocity_scale.is_some() || self.velocity_offset.is_some() {
+            backend = backend.with_velocity(
+                self.velocity_scale.unwrap_or(1.0),
+                self.velocity_offset.unwrap_or(0),
+            );
+        }
+        for (&from, &to) in self.channels.iter() {
+            backend = backend.map_channel(from, to);
+        }
+        backend
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OscBackendConfig {
+    pub target: String,
+    // any left out keep their default
+    pub addresses: Option<Addresses>,
+}
+
+impl OscBackendConfig {
+    pub fn build(&self) -> OscBackend {
+        let backend = OscBackend::new(&self.target);
+        match self.addresses.clone() {
+            Some(addresses) => backend.with_addresses(addresses),
+            None => backend,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScsynthBackendConfig {
+    pub target: String,
+    pub synthdef: Option<String>,
+    // tag to synthdef
+    #[serde(default)]
+    pub synthdefs: HashMap<String, String>,
+    pub group: Option<i32>,
+    pub latency_ms: Option<u64>,
+}
+
+impl ScsynthBackendConfig {
+    pub fn build(&self) -> ScsynthBackend {
+        let mut backend = ScsynthBackend::new(&self.target);
+        if let Some(synthdef) = self.synthdef.as_ref() {
+            backend = backend.with_synthdef(synthdef);
+        }
+        for (tag, synthdef) in self.synthdefs.iter() {
+            backend = backend.map_tag(tag, synthdef);
+        }
+        if let Some(group) = self.group {
+            backend = backend.with_group(group);
+        }
+        if let Some(latency) = self.latency_ms {
+            backend = backend.with_latency(Duration::from_millis(latency));
+        }
+        backend
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NetBackendConfig {
+    pub target: String,
+    pub protocol: Protocol,
+}
+
+impl NetBackendConfig {
+    pub fn build(&self) -> NetBackend {
+        NetBackend::new(&self.target, self.protocol)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArtNetBackendConfig {
+    pub target: String,
+    #[serde(default)]
+    pub universe: u16,
+    // note to DMX channel
+    #[serde(default)]
+    pub notes: HashMap<u8, u16>,
+    // controller to DMX channel
+    #[serde(default)]
+    pub controls: HashMap<u8, u16>,
+}
+
+impl ArtNetBackendConfig {
+    pub fn build(&self) -> ArtNetBackend {
+        let mut backend = ArtNetBackend::new(&self.target, self.universe);
+        for (&note, &channel) in self.notes.iter() {
+            backend = backend.map_note(note, channel);
+        }
+        for (&controller, &channel) in self.controls.iter() {
+            backend = backend.map_control(controller, channel);
+        }
+        backend
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SerialBackendConfig {
+    pub path: String,
+    pub baud: u32,
+    pub wire: Option<Wire>,
+}
+
+impl SerialBackendConfig {
+    pub fn build(&self) -> SerialBackend {
+        let backend = SerialBackend::new(&self.path, self.baud);
+        match self.wire {
+            Some(wire) => backend.with_wire(wire),
+            None => backend,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogBackendConfig {
+    pub path: PathBuf,
+    pub format: LogFormat,
+}
+
+impl LogBackendConfig {
+    pub fn build(&self) -> LogBackend {
+        LogBackend::new(self.path.clone(), self.format)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MidiFileBackendConfig {
+    pub path: PathBuf,
+    pub bpm: f64,
+    pub ppqn: Option<u64>,
+}
+
+impl MidiFileBackendConfig {
+    pub fn build(&self) -> MidiFileBackend {
+        MidiFileBackend::new(
+            self.path.clone(),
+            self.ppqn.unwrap_or(DEFAULT_PPQN),
+            self.bpm,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebSocketBackendConfig {
+    pub address: String,
+}
+
+impl WebSocketBackendConfig {
+    pub fn build(&self) -> WebSocketBackend {
+        WebSocketBackend::new(&self.address)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RtpMidiBackendConfig {
+    pub target: String,
+    pub session_name: Option<String>,
+}
+
+impl RtpMidiBackendConfig {
+    pub fn build(&self) -> RtpMidiBackend {
+        let backend = RtpMidiBackend::new(&self.target);
+        match self.session_name.as_ref() {
+            Some(session_name) => backend.with_session_name(session_name),
+            None => backend,
+        }
+    }
+}
+
+// another backend with its controller streams thinned out, e.g.
+//   {"Throttle": {"interval_ms": 20, "backend": {"Midi": {"device": "TR-8"}}}}
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThrottleBackendConfig {
+    pub backend: Box<BackendConfig>,
+    pub interval_ms: Option<u64>,
+}
+
+impl ThrottleBackendConfig {
+    pub fn build(&self) -> ThrottleBackend {
+        let backend = ThrottleBackend::new(self.backend.build());
+        match self.interval_ms {
+            Some(interval) => backend.with_interval(Duration::from_millis(interval)),
+            None => backend,
+        }
+    }
+}
+
+// a backend from a plugin (see backends::plugin), loaded before the config
+// is, with options that are the plugin's business, e.g.
+//   {"Plugin": {"name": "eurorack", "options": {"port": "/dev/ttyUSB0"}}}
+// A plugin that isn't there fails to start rather than taking the rest of
+// the config with it
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PluginBackendConfig {
+    pub name: String,
+    pub options: Option<json::Value>,
+}
+
+impl PluginBackendConfig {
+    pub fn build(&self) -> Box<dyn Backend> {
+        let options = json::to_string(&self.options).unwrap_or_default();
+        plugin::create(&self.name, &options).unwrap_or_else(|error| {
+            Box::new(Unavailable {
+                name: self.name.clone(),
+                error,
+            })
+        })
+    }
+}
+
+// an output running as a process of its own, fed over its stdin or, given
+// `socket`, a Unix socket at that path
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProcessBackendConfig {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub socket: Option<PathBuf>,
+}
+
+impl ProcessBackendConfig {
+    pub fn build(&self) -> ProcessBackend {
+        let args: Vec<&str> = self.args.iter().map(String::as_str).collect();
+        let backend = ProcessBackend::new(&self.command, &args);
+        match self.socket.as_ref() {
+            #[cfg(unix)]
+            Some(path) => backend.with_socket(path.clone()),
+            _ => backend,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlsaSeqBackendConfig {
+    pub device: String,
+    pub client_name: Option<String>,
+    pub delay_ms: Option<u64>,
+}
+
+#[cfg(target_os = "linux")]
+impl AlsaSeqBackendConfig {
+    pub fn build(&self) -> crate::backends::alsa_seq::AlsaSeqBackend {
+        let mut backend = crate::backends::alsa_seq::AlsaSeqBackend::new(&self.device);
+        if let Some(client_name) = self.client_name.as_ref() {
+            backend.client_name = client_name.clone();
+        }
+        if let Some(delay) = self.delay_ms {
+            backend = backend.with_delay(Duration::from_millis(delay));
+        }
+        backend
+    }
+}
+
+// the audio backend's synthesized voices; samplers need the samples loaded
+// in code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Waveform {
+    Sine,
+    Square,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AudioBackendConfig {
+    pub device: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub volume: Option<f32>,
+    // channel to waveform, sine where not given
+    #[serde(default)]
+    pub voices: HashMap<u8, Waveform>,
+}
+
+impl AudioBackendConfig {
+    pub fn build(&self) -> crate::backends::audio::AudioBackend {
+        use crate::backends::audio::{AudioBackend, Voice};
+
+        let mut backend = AudioBackend::new();
+        if let Some(device) = self.device.as_ref() {
+            backend = backend.with_device(device);
+        }
+        if let Some(sample_rate) = self.sample_rate {
+            backend.sample_rate = sample_rate;
+        }
+        if let Some(volume) = self.volume {
+            backend.volume = volume;
+        }
+        for (&channel, waveform) in self.voices.iter() {
+            let voice = match waveform {
+                Waveform::Sine => Voice::Sine,
+                Waveform::Square => Voice::Square,
+            };
+            backend = backend.with_voice(channel, voice);
+        }
+        backend
+    }
+}
+
+#[cfg(feature = "jack")]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JackBackendConfig {
+    pub client_name: String,
+    pub port_name: Option<String>,
+    pub delay_ms: Option<u64>,
+}
+
+#[cfg(feature = "jack")]
+impl JackBackendConfig {
+    pub fn build(&self) -> crate::backends::jack::JackBackend {
+        let mut backend = crate::backends::jack::JackBackend::new(&self.client_name);
+        if let Some(port_name) = self.port_name.as_ref() {
+            backend.port_name = port_name.clone();
+        }
+        if let Some(delay) = self.delay_ms {
+            backend = backend.with_delay(Duration::from_millis(delay));
+        }
+        backend
+    }
+}
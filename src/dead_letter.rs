@@ -0,0 +1,70 @@
+use std::fmt;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Mutex, RwLock};
+use std::time::Instant;
+
+use crate::event::Event;
+
+// an event a backend failed to send, and why
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    pub event: Event,
+    // which output gave up, e.g. a device name
+    pub backend: String,
+    pub error: String,
+    pub at: Instant,
+}
+
+impl fmt::Display for DeadLetter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} failed to send {:?}: {}",
+            self.backend, self.event.payload, self.error
+        )
+    }
+}
+
+type Hook = Box<dyn Fn(&DeadLetter) + Send + Sync>;
+
+// process wide since backends run on their own and know nothing of the app
+static HOOK: RwLock<Option<Hook>> = RwLock::new(None);
+static SUBSCRIBERS: Mutex<Vec<Sender<DeadLetter>>> = Mutex::new(Vec::new());
+
+// called on the backend's thread for every dead letter, e.g. to log it or to
+// schedule the event again
+pub fn set_hook<F: Fn(&DeadLetter) + Send + Sync + 'static>(hook: F) {
+    *HOOK.write().unwrap() = Some(Box::new(hook));
+}
+
+pub fn clear_hook() {
+    *HOOK.write().unwrap() = None;
+}
+
+// collects dead letters on a channel, for handling them on a thread of your own
+pub fn subscribe() -> Receiver<DeadLetter> {
+    let (sender, receiver) = channel();
+    SUBSCRIBERS.lock().unwrap().push(sender);
+    receiver
+}
+
+// reports a failed send; with nobody listening it goes to stderr rather than
+// getting lost
+pub fn post<E: fmt::Display>(event: Event, backend: &str, error: E) {
+    let letter = DeadLetter {
+        event,
+        backend: backend.to_string(),
+        error: error.to_string(),
+        at: Instant::now(),
+    };
+    let subscribed = {
+        let mut subscribers = SUBSCRIBERS.lock().unwrap();
+        subscribers.retain(|subscriber| subscriber.send(letter.clone()).is_ok());
+        !subscribers.is_empty()
+    };
+    match HOOK.read().unwrap().as_ref() {
+        Some(hook) => hook(&letter),
+        None if !subscribed => eprintln!("[dead letter] {}", letter),
+        None => {}
+    }
+}
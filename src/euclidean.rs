@@ -0,0 +1,176 @@
+use crate::clock::DEFAULT_PPQN;
+use crate::event::{Event, Message, DEFAULT_VELOCITY};
+use crate::generator::Generator;
+use crate::pitch::Pitch;
+
+// a hair under a step, so float steps like a triplet's land where they should
+const EPSILON: f64 = 1e-9;
+
+// `pulses` hits spread as evenly as they go over `steps` steps (Bjorklund's
+// algorithm), starting on a hit: E(3, 8) is x..x..x., the tresillo, and
+// E(5, 8) x.xx.xx., the cinquillo
+pub fn pattern(pulses: usize, steps: usize) -> Vec<bool> {
+    let pulses = pulses.min(steps);
+    let mut front = vec![vec![true]; pulses];
+    let mut back = vec![vec![false]; steps - pulses];
+    // pairs the front groups with the back ones until at most one is left over
+    while back.len() > 1 && !front.is_empty() {
+        let paired = front.len().min(back.len());
+        let rest = if front.len() > paired {
+            front.split_off(paired)
+        } else {
+            back.split_off(paired)
+        };
+        for (group, tail) in front.iter_mut().zip(back) {
+            group.extend(tail);
+        }
+        back = rest;
+    }
+    front.into_iter().chain(back).flatten().collect()
+}
+
+// plays a Euclidean rhythm E(pulses, steps) on one note, a sixteenth per step
+// by default, looping for as long as it runs: three numbers for a clave, a
+// bembé or a techno hat pattern. Rotation starts the pattern that many steps
+// in, e.g. E(3, 8) rotated by 2 is .x..x.x.
+#[derive(Debug, Clone)]
+pub struct Euclidean {
+    pub pulses: usize,
+    pub steps: usize,
+    pub rotation: usize,
+    pub note: u8,
+    pub velocity: u8,
+    // MIDI channel, 0-15
+    pub channel: u8,
+    pub tag: Option<String>,
+    // beats per step
+    pub step: f64,
+    // beats each hit lasts
+    pub gate: f64,
+    // the clock's, for placing steps between beats
+    pub ppqn: u64,
+}
+
+impl Euclidean {
+    pub fn new<P: Into<Pitch>>(pulses: usize, steps: usize, note: P) -> Self {
+        Self {
+            pulses,
+            steps,
+            rotation: 0,
+            note: note.into().0,
+            velocity: DEFAULT_VELOCITY,
+            channel: 0,
+            tag: None,
+            step: 0.25,
+            gate: 0.125,
+            ppqn: DEFAULT_PPQN,
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: usize) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_velocity(mut self, velocity: u8) -> Self {
+        self.velocity = velocity;
+        self
+    }
+
+    pub fn with_channel(mut self, channel: u8) -> Self {
+        self.channel = channel & 0x0F;
+        self
+    }
+
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tag = Some(tag.to_string());
+        self
+    }
+
+    // e.g. 0.5 for eighths, 1.0 / 3.0 for eighth-note triplets
+    pub fn with_step(mut self, beats: f64) -> Self {
+        self.step = beats;
+        self
+    }
+
+    pub fn with_gate(mut self, beats: f64) -> Self {
+        self.gate = beats;
+        self
+    }
+
+    pub fn with_ppqn(mut self, ppqn: u64) -> Self {
+        self.ppqn = ppqn;
+        self
+    }
+
+    // the pattern as it plays, rotation included
+    pub fn hits(&self) -> Vec<bool> {
+        let mut hits = pattern(self.pulses, self.steps);
+        if !hits.is_empty() {
+            let rotation = self.rotation % hits.len();
+            hits.rotate_left(rotation);
+        }
+        hits
+    }
+}
+
+impl Generator for Euclidean {
+    fn generate(&mut self, beat: u64) -> Vec<Event> {
+        let hits = self.hits();
+        if hits.is_empty() || self.step <= 0.0 {
+            return vec![];
+        }
+        let first = (beat as f64 / self.step - EPSILON).ceil() as u64;
+        let end = ((beat + 1) as f64 / self.step - EPSILON).ceil() as u64;
+        (first..end)
+            .filter(|&step| hits[step as usize % hits.len()])
+            .map(|step| {
+                let message = Message::NoteOn {
+                    note: self.note,
+                    velocity: self.velocity,
+                };
+                let event = Event::at_position(message, step as f64 * self.step, self.ppqn)
+                    .on_channel(self.channel)
+                    .with_duration(self.gate);
+                match self.tag.as_ref() {
+                    Some(tag) => event.tagged(tag),
+                    None => event,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(hits: &[bool]) -> String {
+        hits.iter()
+            .map(|&hit| if hit { 'x' } else { '.' })
+            .collect()
+    }
+
+    #[test]
+    fn spreads_pulses_evenly() {
+        assert_eq!(render(&pattern(3, 8)), "x..x..x.");
+        assert_eq!(render(&pattern(5, 8)), "x.xx.xx.");
+        assert_eq!(render(&pattern(2, 5)), "x.x..");
+        assert_eq!(render(&pattern(4, 16)), "x...x...x...x...");
+    }
+
+    #[test]
+    fn edges() {
+        assert_eq!(render(&pattern(0, 4)), "....");
+        assert_eq!(render(&pattern(4, 4)), "xxxx");
+        assert_eq!(render(&pattern(9, 4)), "xxxx");
+        assert!(pattern(0, 0).is_empty());
+    }
+
+    #[test]
+    fn rotation_starts_later_in_the_pattern() {
+        let tresillo = Euclidean::new(3, 8, 36).with_rotation(2);
+        assert_eq!(render(&tresillo.hits()), ".x..x.x.");
+        assert_eq!(render(&tresillo.with_rotation(10).hits()), ".x..x.x.");
+    }
+}
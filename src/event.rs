@@ -1,11 +1,529 @@
-#[derive(Debug, Clone)]
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::chord::Chord;
+use crate::clock::{split_position, Clock};
+use crate::pitch::Pitch;
+use crate::time::MusicalTime;
+
+pub const DEFAULT_VELOCITY: u8 = 0x64;
+
+// what an event does when it fires, modelled on MIDI channel messages
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Message {
+    NoteOn { note: u8, velocity: u8 },
+    NoteOff { note: u8, velocity: u8 },
+    // polyphonic aftertouch, pressure on a single held note
+    PolyPressure { note: u8, pressure: u8 },
+    ControlChange { controller: u8, value: u8 },
+    ProgramChange { program: u8 },
+    // channel aftertouch, one pressure for every note on the channel
+    ChannelPressure { pressure: u8 },
+    // -8192..=8191, 0 is centered
+    PitchBend { value: i16 },
+    // sets one of a synth's non-registered parameters, both 0-16383: the
+    // deep ones, e.g. filter settings on many hardware synths. Goes out as
+    // CC 99/98 (parameter) and 6/38 (value)
+    Nrpn { parameter: u16, value: u16 },
+    // the same for the registered ones, e.g. 0 for the pitch bend range;
+    // CC 101/100 pick the parameter
+    Rpn { parameter: u16, value: u16 },
+}
+
+impl Message {
+    pub fn note(note: u8) -> Self {
+        Message::NoteOn {
+            note,
+            velocity: DEFAULT_VELOCITY,
+        }
+    }
+
+    // parameter and value keep to their 14 bits
+    pub fn nrpn(parameter: u16, value: u16) -> Self {
+        Message::Nrpn {
+            parameter: parameter & 0x3FFF,
+            value: value & 0x3FFF,
+        }
+    }
+
+    pub fn rpn(parameter: u16, value: u16) -> Self {
+        Message::Rpn {
+            parameter: parameter & 0x3FFF,
+            value: value & 0x3FFF,
+        }
+    }
+
+    // sets the velocity of notes, other messages are left alone
+    pub fn with_velocity(self, velocity: u8) -> Self {
+        match self {
+            Message::NoteOn { note, .. } => Message::NoteOn { note, velocity },
+            Message::NoteOff { note, .. } => Message::NoteOff { note, velocity },
+            message => message,
+        }
+    }
+
+    pub fn velocity(&self) -> Option<u8> {
+        match *self {
+            Message::NoteOn { velocity, .. } | Message::NoteOff { velocity, .. } => Some(velocity),
+            _ => None,
+        }
+    }
+
+    // the note-off ending a note-on
+    pub fn release(&self) -> Option<Self> {
+        match *self {
+            Message::NoteOn { note, velocity } if velocity > 0 => {
+                Some(Message::NoteOff { note, velocity: 0 })
+            }
+            _ => None,
+        }
+    }
+
+    // a note-on at velocity 0 releases the note, as in MIDI running status
+    pub fn is_note_off(&self) -> bool {
+        match *self {
+            Message::NoteOn { velocity, .. } => velocity == 0,
+            Message::NoteOff { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+// which of the events landing on the same instant go out first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Priority {
+    Low,
+    Normal,
+    // note-offs, panics: what keeps notes from getting stuck
+    High,
+}
+
+// per-note expression, as used by MPE synths
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Expression {
+    // -8192..=8191, 0 is centered
+    PitchBend(i16),
+    Pressure(u8),
+    // the MPE "Y" axis, sent as CC 74
+    Timbre(u8),
+}
+
+// typed OSC argument
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum OscArg {
+    Int(i32),
+    Float(f32),
+    String(String),
+    Blob(Vec<u8>),
+}
+
+impl From<i32> for OscArg {
+    fn from(value: i32) -> Self {
+        OscArg::Int(value)
+    }
+}
+
+impl From<f32> for OscArg {
+    fn from(value: f32) -> Self {
+        OscArg::Float(value)
+    }
+}
+
+// float literals are f64, OSC floats are 32 bit
+impl From<f64> for OscArg {
+    fn from(value: f64) -> Self {
+        OscArg::Float(value as f32)
+    }
+}
+
+impl<'a> From<&'a str> for OscArg {
+    fn from(value: &'a str) -> Self {
+        OscArg::String(value.to_string())
+    }
+}
+
+impl From<String> for OscArg {
+    fn from(value: String) -> Self {
+        OscArg::String(value)
+    }
+}
+
+impl From<Vec<u8>> for OscArg {
+    fn from(value: Vec<u8>) -> Self {
+        OscArg::Blob(value)
+    }
+}
+
+// runtime settings for backends, scheduled like notes so changes land on the
+// beat; tag the event to address only the backends subscribed to that tag
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Control {
+    Mute,
+    Unmute,
+    // switch to another output device
+    SetDevice(String),
+    // 0.0-1.0
+    SetVolume(f64),
+    // silence everything right now, for when something hangs; see
+    // Scheduler::panic
+    Panic,
+}
+
+// what an event carries to the backends, each backend picks out what it
+// understands and ignores the rest
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Payload {
+    Midi(Message),
+    // expanded into its notes on the way to the backends
+    Chord(Chord),
+    Control(Control),
+    // applies to a sounding note, or to the next one started with that number
+    Expression {
+        note: u8,
+        expression: Expression,
+    },
+    // system exclusive data, framing F0/F7 optional
+    SysEx(Vec<u8>),
+    // an OSC message, e.g. "/synth/1/cutoff" with [0.5]
+    Osc {
+        address: String,
+        args: Vec<OscArg>,
+    },
+    // anything else, for backends that know what to downcast it to;
+    // it only lives in memory and fails to serialize
+    #[serde(skip)]
+    Custom(Arc<dyn Any + Send + Sync>),
+}
+
+impl Payload {
+    pub fn custom<T: Any + Send + Sync>(value: T) -> Self {
+        Payload::Custom(Arc::new(value))
+    }
+
+    // sets the velocity of notes and chords, other payloads are left alone
+    pub fn with_velocity(self, velocity: u8) -> Self {
+        match self {
+            Payload::Midi(message) => Payload::Midi(message.with_velocity(velocity)),
+            Payload::Chord(chord) => Payload::Chord(Chord { velocity, ..chord }),
+            payload => payload,
+        }
+    }
+
+    // what ends a note or chord
+    pub fn release(&self) -> Option<Self> {
+        match self {
+            Payload::Midi(message) => message.release().map(Payload::Midi),
+            Payload::Chord(chord) if chord.velocity > 0 => Some(Payload::Chord(Chord {
+                velocity: 0,
+                ..chord.clone()
+            })),
+            _ => None,
+        }
+    }
+
+    pub fn is_note_off(&self) -> bool {
+        match self {
+            Payload::Midi(message) => message.is_note_off(),
+            Payload::Chord(chord) => chord.velocity == 0,
+            _ => false,
+        }
+    }
+}
+
+impl From<Message> for Payload {
+    fn from(message: Message) -> Self {
+        Payload::Midi(message)
+    }
+}
+
+impl From<Control> for Payload {
+    fn from(control: Control) -> Self {
+        Payload::Control(control)
+    }
+}
+
+impl From<Chord> for Payload {
+    fn from(chord: Chord) -> Self {
+        Payload::Chord(chord)
+    }
+}
+
+impl fmt::Debug for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Payload::Midi(message) => f.debug_tuple("Midi").field(message).finish(),
+            Payload::Chord(chord) => f.debug_tuple("Chord").field(chord).finish(),
+            Payload::Control(control) => f.debug_tuple("Control").field(control).finish(),
+            Payload::SysEx(data) => write!(f, "SysEx({} bytes)", data.len()),
+            Payload::Osc { address, args } => f
+                .debug_struct("Osc")
+                .field("address", address)
+                .field("args", args)
+                .finish(),
+            Payload::Expression { note, expression } => f
+                .debug_struct("Expression")
+                .field("note", note)
+                .field("expression", expression)
+                .finish(),
+            Payload::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Event {
-    pub value: String,
+    pub payload: Payload,
     pub beat: u64,
+    pub tick: u64,
+    // MIDI channel, 0-15
+    pub channel: u8,
+    // e.g. "drums", for backends subscribed to only some of the events
+    pub tag: Option<String>,
+    // beats until the note is released, notes without one are held forever
+    pub duration: Option<f64>,
+    // chance of the event playing at all, 0.0-1.0
+    pub probability: f64,
+    // note-offs default to high, everything else to normal
+    pub priority: Priority,
+    // ratchets: play `repeats` times, `subdivision` beats apart
+    pub repeats: u32,
+    pub subdivision: f64,
 }
 
 impl Event {
-    pub fn new(value: String, beat: u64) -> Self {
-        Self { value, beat }
+    // takes MIDI numbers as well as names: Event::note(61), Event::note("C#4")
+    pub fn note<P: Into<Pitch>>(pitch: P) -> EventBuilder {
+        Self::build(Message::note(pitch.into().0))
+    }
+
+    // Event::chord("Cmaj7"), or Event::chord(Chord::new("C3", &[0, 7, 16]))
+    pub fn chord<C: Into<Chord>>(chord: C) -> EventBuilder {
+        Self::build(chord.into())
+    }
+
+    pub fn expression<P: Into<Pitch>>(pitch: P, expression: Expression) -> EventBuilder {
+        Self::build(Payload::Expression {
+            note: pitch.into().0,
+            expression,
+        })
+    }
+
+    // Event::osc("/filter", vec![0.5.into(), "lowpass".into()])
+    pub fn osc(address: &str, args: Vec<OscArg>) -> EventBuilder {
+        Self::build(Payload::Osc {
+            address: address.to_string(),
+            args,
+        })
+    }
+
+    pub fn build<P: Into<Payload>>(payload: P) -> EventBuilder {
+        EventBuilder {
+            event: Self::new(payload, 0),
+        }
+    }
+
+    pub fn new<P: Into<Payload>>(payload: P, beat: u64) -> Self {
+        Self::with_tick(payload, beat, 0)
+    }
+
+    pub fn with_tick<P: Into<Payload>>(payload: P, beat: u64, tick: u64) -> Self {
+        let payload = payload.into();
+        let priority = if payload.is_note_off() {
+            Priority::High
+        } else {
+            Priority::Normal
+        };
+        Self {
+            payload,
+            priority,
+            beat,
+            tick,
+            channel: 0,
+            tag: None,
+            duration: None,
+            probability: 1.0,
+            repeats: 1,
+            subdivision: 0.0,
+        }
+    }
+
+    pub fn on_channel(mut self, channel: u8) -> Self {
+        self.channel = channel & 0x0F;
+        self
+    }
+
+    pub fn tagged(mut self, tag: &str) -> Self {
+        self.tag = Some(tag.to_string());
+        self
+    }
+
+    pub fn with_duration(mut self, beats: f64) -> Self {
+        self.duration = Some(beats);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_probability(mut self, probability: f64) -> Self {
+        self.probability = probability.clamp(0.0, 1.0);
+        self
+    }
+
+    // e.g. (4, 0.25) retriggers four times within the beat
+    pub fn with_ratchet(mut self, repeats: u32, subdivision: f64) -> Self {
+        self.repeats = repeats.max(1);
+        self.subdivision = subdivision;
+        self
+    }
+
+    // the ratchet's retriggers as single events, notes shortened to fit the gap
+    pub fn retriggers(&self, ppqn: u64) -> Vec<Event> {
+        let start = self.position(ppqn);
+        (0..self.repeats)
+            .map(|i| {
+                let (beat, tick) = split_position(start + i as f64 * self.subdivision, ppqn);
+                let duration = match self.duration {
+                    Some(duration) if self.repeats > 1 => Some(duration.min(self.subdivision)),
+                    duration => duration,
+                };
+                Event {
+                    beat,
+                    tick,
+                    duration,
+                    repeats: 1,
+                    ..self.clone()
+                }
+            })
+            .collect()
+    }
+
+    // the event releasing this note once its duration is up
+    pub fn note_off(&self, ppqn: u64) -> Option<Self> {
+        let release = self.payload.release()?;
+        let end = self.position(ppqn) + self.duration?;
+        let mut note_off = Self::at_position(release, end, ppqn).on_channel(self.channel);
+        note_off.tag = self.tag.clone();
+        Some(note_off)
+    }
+
+    // at a fractional beat, rounded to the nearest of `ppqn` ticks per beat
+    pub fn at_position<P: Into<Payload>>(payload: P, position: f64, ppqn: u64) -> Self {
+        let (beat, tick) = split_position(position, ppqn);
+        Self::with_tick(payload, beat, tick)
+    }
+
+    pub fn position(&self, ppqn: u64) -> f64 {
+        self.beat as f64 + self.tick as f64 / ppqn as f64
+    }
+
+    pub fn at<P: Into<Payload>>(payload: P, time: MusicalTime, clock: &Clock) -> Self {
+        let (beat, tick) = clock.beat_tick(time);
+        Self::with_tick(payload, beat, tick)
+    }
+
+    pub fn message(&self) -> Option<&Message> {
+        match &self.payload {
+            Payload::Midi(message) => Some(message),
+            _ => None,
+        }
+    }
+
+    pub fn is_note_off(&self) -> bool {
+        self.payload.is_note_off()
+    }
+
+    pub fn control(&self) -> Option<&Control> {
+        match &self.payload {
+            Payload::Control(control) => Some(control),
+            _ => None,
+        }
+    }
+
+    // chords as their single notes, anything else as is
+    pub fn expand(&self) -> Vec<Event> {
+        match &self.payload {
+            Payload::Chord(chord) => chord
+                .notes
+                .iter()
+                .map(|&note| Event {
+                    payload: Payload::Midi(Message::NoteOn {
+                        note,
+                        velocity: chord.velocity,
+                    }),
+                    duration: None,
+                    ..self.clone()
+                })
+                .collect(),
+            _ => vec![self.clone()],
+        }
+    }
+
+    pub fn custom<T: Any>(&self) -> Option<&T> {
+        match &self.payload {
+            Payload::Custom(value) => value.downcast_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn musical_time(&self, clock: &Clock) -> MusicalTime {
+        clock.musical_time(self.position(clock.ppqn()))
+    }
+}
+
+// reads like the note it makes: Event::note(60).vel(90).chan(2).dur_beats(0.5).at(beat)
+pub struct EventBuilder {
+    event: Event,
+}
+
+impl EventBuilder {
+    pub fn vel(mut self, velocity: u8) -> Self {
+        self.event.payload = self.event.payload.with_velocity(velocity);
+        self
+    }
+
+    pub fn chan(mut self, channel: u8) -> Self {
+        self.event = self.event.on_channel(channel);
+        self
+    }
+
+    pub fn dur_beats(mut self, beats: f64) -> Self {
+        self.event = self.event.with_duration(beats);
+        self
+    }
+
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.event = self.event.with_priority(priority);
+        self
+    }
+
+    pub fn prob(mut self, probability: f64) -> Self {
+        self.event = self.event.with_probability(probability);
+        self
+    }
+
+    pub fn ratchet(mut self, repeats: u32, subdivision: f64) -> Self {
+        self.event = self.event.with_ratchet(repeats, subdivision);
+        self
+    }
+
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.event = self.event.tagged(tag);
+        self
+    }
+
+    pub fn at(self, beat: u64) -> Event {
+        self.at_tick(beat, 0)
+    }
+
+    pub fn at_tick(mut self, beat: u64, tick: u64) -> Event {
+        self.event.beat = beat;
+        self.event.tick = tick;
+        self.event
     }
 }
@@ -0,0 +1,76 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::clock_service::Tick;
+use crate::event::Event;
+use crate::transport::Transport;
+
+// makes the events for each beat as it comes up, keeping whatever it likes
+// between beats: counters, RNGs, loaded patterns
+pub trait Generator: Send {
+    // called once per beat with the upcoming beat number
+    fn generate(&mut self, beat: u64) -> Vec<Event>;
+}
+
+// the plain kind: a function of the beat alone
+pub type GeneratorFn = fn(&u64) -> Vec<Event>;
+
+// functions and closures are generators too, closures with state of their own
+impl<F: FnMut(&u64) -> Vec<Event> + Send> Generator for F {
+    fn generate(&mut self, beat: u64) -> Vec<Event> {
+        self(&beat)
+    }
+}
+
+type Generators = Arc<Mutex<Vec<Option<Box<dyn Generator>>>>>;
+
+// runs generators on every beat tick of a transport, in the order they were
+// added, scheduling what they return; generators come and go while it plays.
+// Runs until the transport shuts down
+pub struct Engine {
+    generators: Generators,
+}
+
+impl Engine {
+    pub fn new(transport: &Transport) -> Self {
+        let generators: Generators = Arc::new(Mutex::new(vec![]));
+        let ticks = transport.subscribe();
+        let scheduling = transport.clone();
+        let running = generators.clone();
+        let worker = thread::spawn(move || {
+            for tick in ticks {
+                if let Tick::BeatTick(beat) = tick {
+                    let events: Vec<Event> = running
+                        .lock()
+                        .unwrap()
+                        .iter_mut()
+                        .flatten()
+                        .flat_map(|generator| generator.generate(beat))
+                        .collect();
+                    scheduling.schedule_batch(events);
+                }
+            }
+        });
+        transport.attach(worker);
+        Self { generators }
+    }
+
+    // runs from the next beat on; the index is for remove()
+    pub fn add<G: Generator + 'static>(&self, generator: G) -> usize {
+        let mut generators = self.generators.lock().unwrap();
+        generators.push(Some(Box::new(generator)));
+        generators.len() - 1
+    }
+
+    // the generator back, or None if there's none at `index`; the other
+    // indices stay as they are
+    pub fn remove(&self, index: usize) -> Option<Box<dyn Generator>> {
+        self.generators.lock().unwrap().get_mut(index)?.take()
+    }
+}
+
+// runs `generator` on every beat tick of the transport, scheduling what it
+// returns, until the transport shuts down
+pub fn register<G: Generator + 'static>(transport: &Transport, generator: G) {
+    Engine::new(transport).add(generator);
+}
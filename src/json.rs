@@ -0,0 +1,5 @@
+// JSON for anything that derives Serialize and Deserialize (events, takes,
+// configs), by way of serde_json: enum variants as {"Variant": ...}, unit
+// variants as "Variant", None as null. Value is any JSON at all, for fields
+// whose shape is up to someone else; objects keep their order
+pub use serde_json::{from_str, to_string, Error, Value};
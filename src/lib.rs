@@ -0,0 +1,63 @@
+extern crate cpal;
+extern crate rand;
+extern crate rand_distr;
+extern crate serde;
+extern crate serde_json;
+#[cfg(feature = "async")]
+extern crate tokio;
+extern crate tungstenite;
+
+#[cfg(feature = "async")]
+pub mod async_scheduler;
+pub mod backends;
+pub mod chord;
+pub mod clock;
+pub mod clock_service;
+pub mod config;
+pub mod dead_letter;
+pub mod euclidean;
+pub mod event;
+pub mod generator;
+pub mod json;
+#[cfg(feature = "link")]
+pub mod link;
+pub mod metrics;
+pub mod midi_clock;
+pub mod midi_input;
+pub mod mtc;
+pub mod notation;
+pub mod outlet;
+pub mod outputs;
+pub mod pitch;
+pub mod recorder;
+pub mod remote;
+pub mod render;
+pub mod routing;
+pub mod scheduler;
+pub mod sequence;
+pub mod tempo;
+pub mod time;
+pub mod timing;
+pub mod transport;
+
+#[cfg(feature = "async")]
+pub use async_scheduler::{AsyncBackend, AsyncScheduler};
+pub use backends::Backend;
+pub use chord::Chord;
+pub use clock::{Clock, Grid, Meter};
+pub use clock_service::{ClockService, Tick};
+pub use dead_letter::DeadLetter;
+pub use euclidean::Euclidean;
+pub use event::{Control, Event, Expression, Message, OscArg, Payload, Priority};
+pub use generator::{Engine, Generator, GeneratorFn};
+pub use notation::Pattern;
+pub use outlet::{Backpressure, Overflow};
+pub use outputs::Restart;
+pub use pitch::Pitch;
+pub use recorder::Take;
+pub use routing::Route;
+pub use scheduler::{EventId, LatePolicy, PendingEvent, Scheduler};
+pub use sequence::{Step, StepSequence};
+pub use tempo::TempoMap;
+pub use time::MusicalTime;
+pub use transport::{Every, Repeat, Transport};
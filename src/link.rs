@@ -0,0 +1,89 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::clock::Clock;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+// how far off the session's phase the clock may drift, in beats, before it's
+// moved back in line
+const PHASE_TOLERANCE: f64 = 0.01;
+
+// follows an Ableton Link session: tempo and beat phase from the session are
+// pushed into the shared clock, so everything scheduled from it stays locked.
+// Only a change of the session's tempo replaces the clock's, so a tempo map
+// or a ramp set locally plays out between changes; it follows from run()
+// until stop()
+pub struct LinkSync {
+    link: Arc<rusty_link::AblLink>,
+    running: Arc<AtomicBool>,
+    follower: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl LinkSync {
+    pub fn new(bpm: f64) -> Self {
+        let link = rusty_link::AblLink::new(bpm);
+        link.enable(true);
+        Self {
+            link: Arc::new(link),
+            running: Arc::new(AtomicBool::new(false)),
+            follower: Mutex::new(None),
+        }
+    }
+
+    pub fn peers(&self) -> u64 {
+        self.link.num_peers()
+    }
+
+    // proposes a new tempo to every peer in the session
+    pub fn set_bpm(&self, bpm: f64) {
+        let mut state = rusty_link::SessionState::new();
+        self.link.capture_app_session_state(&mut state);
+        state.set_tempo(bpm, self.link.clock_micros());
+        self.link.commit_app_session_state(&state);
+    }
+
+    pub fn run(&self, clock: Arc<RwLock<Clock>>) {
+        self.stop();
+        let link = self.link.clone();
+        // stop() has seen the last one off
+        let running = self.running.clone();
+        running.store(true, Ordering::SeqCst);
+
+        *self.follower.lock().unwrap() = Some(thread::spawn(move || {
+            let mut state = rusty_link::SessionState::new();
+            let mut tempo = None;
+            while running.load(Ordering::SeqCst) {
+                link.capture_app_session_state(&mut state);
+                let mut clock = clock.write().unwrap();
+                let quantum = clock.bpb();
+                let beat = state.beat_at_time(link.clock_micros(), quantum);
+                if tempo != Some(state.tempo()) {
+                    tempo = Some(state.tempo());
+                    clock.sync(beat, state.tempo());
+                } else if (clock.position() - beat).abs() > PHASE_TOLERANCE {
+                    clock.seek(beat);
+                }
+                drop(clock);
+                thread::sleep(POLL_INTERVAL);
+            }
+        }));
+    }
+
+    // stops following the session and waits for the thread to end
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(follower) = self.follower.lock().unwrap().take() {
+            let _ = follower.join();
+        }
+    }
+}
+
+impl Drop for LinkSync {
+    fn drop(&mut self) {
+        self.stop();
+        self.link.enable(false);
+    }
+}
@@ -1,107 +1,163 @@
-#![feature(div_duration)]
-
-mod clock;
-use clock::{beat_ms, Clock};
-use std::cell::RefCell;
-use std::sync::Arc;
-
-mod event;
-use event::Event;
-
-mod scheduler;
-use scheduler::Scheduler;
-
-mod backends;
-use backends::dummy::DummyBackend;
-use backends::midi::MidiBackend;
-
-use std::sync::mpsc::{channel, Sender};
+extern crate tonic;
+
+use tonic::backends::dummy::DummyBackend;
+use tonic::backends::midi::{self, MidiBackend};
+use tonic::backends::midi_file::MidiFileBackend;
+use tonic::backends::plugin;
+use tonic::clock::DEFAULT_PPQN;
+use tonic::config::Config;
+use tonic::{
+    render, Backend, Clock, ClockService, Engine, Event, Generator, GeneratorFn, Message, Route,
+    Scheduler, Transport,
+};
+
+use std::env;
+use std::io;
 use std::thread;
 
-const BPM: u64 = 120; // beats per minute
-
-fn gen(s: &Sender<Event>, f: fn(&u64) -> Vec<Event>) {
-    let out = s.clone();
-    thread::spawn(move || {
-        let mut beat = 1;
-        loop {
-            let events = f(&beat);
-            for e in events {
-                out.send(e).unwrap();
-            }
-            // sleep for a beat
-            beat += 1;
-            thread::sleep(beat_ms(1, BPM));
-        }
-    });
-}
+const BPM: f64 = 120.0; // beats per minute
+const NOTE_LENGTH: f64 = 0.5; // an eighth note, in beats
 
 /* TODO:
-1. graceful shutdown
-2. lock generators to until clock is started
-3. ableton-link
-4. generators composition (beat merge?)
-5. crossbeam-channel (mpMc)
+1. generators composition (beat merge?)
+2. crossbeam-channel (mpMc)
 */
 
-pub fn main() {
-    let (sender, receiver) = channel();
-
-    gen(&sender, |&beat| {
-        if beat < 50 && beat % 4 == 0 {
-            return vec![
-                Event::new("60".to_string(), beat),
-                Event::new("65".to_string(), beat + 1),
-                Event::new("73".to_string(), beat + 2),
-            ];
-        }
-
-        vec![]
-    });
-
-    gen(&sender, |&beat| {
-        if beat < 100 && beat % 7 == 0 {
-            return vec![
-                Event::new("35".to_string(), beat),
-                Event::new("40".to_string(), beat + 1),
-                Event::new("43".to_string(), beat + 2),
-            ];
-        }
-
-        vec![]
-    });
-
-    gen(&sender, |&beat| {
-        let mut events: Vec<Event> = vec![];
+fn chords(&beat: &u64) -> Vec<Event> {
+    if beat < 50 && beat % 4 == 0 {
+        return vec![
+            Event::note("C4").vel(110).dur_beats(NOTE_LENGTH).at(beat),
+            Event::note("F4").dur_beats(NOTE_LENGTH).at(beat + 1),
+            Event::note("C#5").dur_beats(NOTE_LENGTH).at(beat + 2),
+            Event::chord("Fmaj7").vel(80).dur_beats(1.0).at(beat + 3),
+        ];
+    }
+
+    vec![]
+}
 
-        if beat > 50 && beat % 3 == 0 {
-            events.push(Event::new("81".to_string(), beat))
-        }
+fn bass(&beat: &u64) -> Vec<Event> {
+    if beat < 100 && beat % 7 == 0 {
+        return vec![
+            Event::note(35)
+                .vel(120)
+                .tag("bass")
+                .dur_beats(NOTE_LENGTH)
+                .at(beat),
+            Event::note(40)
+                .tag("bass")
+                .dur_beats(NOTE_LENGTH)
+                .at(beat + 1),
+            Event::note(43)
+                .tag("bass")
+                .dur_beats(NOTE_LENGTH)
+                .at(beat + 2),
+        ];
+    }
+
+    vec![]
+}
 
-        if beat > 100 && beat % 5 == 0 {
-            events.push(Event::new("86".to_string(), beat))
-        }
+fn hits(&beat: &u64) -> Vec<Event> {
+    let mut events: Vec<Event> = vec![];
 
-        events
-    });
+    if beat > 50 && beat % 3 == 0 {
+        events.push(Event::note(81).vel(70).prob(0.6).at(beat))
+    }
 
-    let clock = Arc::new(Clock::new(BPM));
+    if beat > 100 && beat % 5 == 0 {
+        events.push(Event::note(86).ratchet(3, 1.0 / 6.0).at(beat));
+        // and a pickup on the offbeat
+        let offbeat = beat as f64 + 0.5;
+        events.push(Event::at_position(Message::note(86), offbeat, DEFAULT_PPQN))
+    }
 
-    let player = thread::spawn(move || {
-        let scheduler = Scheduler::new(RefCell::new(vec![
-            Box::new(MidiBackend {
-                device_name: String::from("IAC Driver"),
-            }),
-            Box::new(DummyBackend {}),
-        ]));
+    events
+}
 
-        scheduler.start_backends();
+const GENERATORS: [GeneratorFn; 3] = [chords, bass, hits];
+const RENDER_BEATS: u64 = 128;
 
-        loop {
-            let event = receiver.recv().unwrap();
-            scheduler.schedule_at(clock.beat_at(event.beat), event);
+pub fn main() {
+    // `--render <path>` writes the piece to a MIDI file instead of playing it,
+    // `--list-devices` shows the MIDI ports there are to pick from and
+    // `--config <path>` plays through the backends listed there. Plugins are
+    // loaded from $TONIC_PLUGIN_DIR, ./plugins if it's not set
+    let args: Vec<String> = env::args().collect();
+    if args.len() > 1 && args[1] == "--list-devices" {
+        print!("{}", midi::list_devices());
+        return;
+    }
+    if args.len() > 2 && args[1] == "--render" {
+        let mut clock = Clock::new(BPM);
+        let scheduler = Scheduler::offline(vec![Box::new(MidiFileBackend::new(
+            &args[2],
+            clock.ppqn(),
+            BPM,
+        ))]);
+        let mut generators: Vec<Box<dyn Generator>> = GENERATORS
+            .iter()
+            .map(|&generator| Box::new(generator) as Box<dyn Generator>)
+            .collect();
+        render::render(&mut clock, &scheduler, &mut generators, RENDER_BEATS);
+        return;
+    }
+
+    let clock = ClockService::new(Clock::new(BPM));
+
+    #[cfg(feature = "link")]
+    tonic::link::LinkSync::new(BPM).run(clock.clock().clone());
+
+    // backends from shared libraries, for configs to name
+    plugin::discover(env::var("TONIC_PLUGIN_DIR").unwrap_or_else(|_| "plugins".to_string()));
+    let config = if args.len() > 2 && args[1] == "--config" {
+        Some(Config::load(&args[2]).unwrap_or_else(|error| panic!("{}", error)))
+    } else {
+        None
+    };
+    let backends: Vec<Box<dyn Backend>> = match config.as_ref() {
+        Some(config) => config.backends(),
+        None => vec![
+            Box::new(MidiBackend::new("IAC Driver")),
+            Box::new(DummyBackend::new()),
+        ],
+    };
+    let scheduler = Scheduler::new(backends);
+    scheduler.start_backends();
+    match config.as_ref() {
+        Some(config) => config
+            .route(&scheduler)
+            .unwrap_or_else(|error| panic!("{}", error)),
+        // the bass only goes to the MIDI port, on its own channel
+        None => scheduler.route("bass", Route::to(0).channel(1)),
+    }
+
+    let transport = Transport::new(clock, scheduler);
+
+    let engine = Engine::new(&transport);
+    for &generator in GENERATORS.iter() {
+        engine.add(generator);
+    }
+
+    transport.start();
+
+    // "p" and Enter silences hanging notes, Enter alone stops playback and
+    // shuts everything down; with no terminal attached it plays until killed
+    loop {
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line).unwrap() == 0 {
+            loop {
+                thread::park();
+            }
+        }
+        if line.trim() != "p" {
+            break;
         }
-    });
+        transport.panic();
+    }
+    transport.shutdown();
 
-    player.join().unwrap();
+    for (backend, stats) in transport.scheduler().latency().iter().enumerate() {
+        println!("[latency] backend {}: {}", backend, stats);
+    }
 }
@@ -0,0 +1,164 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// how many of the latest sends the percentiles are taken over
+pub const LATENCY_WINDOW: usize = 1024;
+// what events per second are counted over
+pub const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+// how late sends went out against the instant they were scheduled for
+#[derive(Debug, Clone, Default)]
+pub struct Latency {
+    recent: VecDeque<Duration>,
+    count: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl Latency {
+    pub fn record(&mut self, late: Duration) {
+        if self.recent.len() == LATENCY_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(late);
+        self.count += 1;
+        self.total += late;
+        self.max = self.max.max(late);
+    }
+
+    // mean and max cover every send, the percentiles only the recent ones
+    pub fn stats(&self) -> LatencyStats {
+        let mut recent: Vec<Duration> = self.recent.iter().cloned().collect();
+        recent.sort();
+        let percentile = |p: f64| match recent.len() {
+            0 => Duration::default(),
+            n => recent[((n - 1) as f64 * p).round() as usize],
+        };
+        LatencyStats {
+            count: self.count,
+            mean: match self.count {
+                0 => Duration::default(),
+                n => Duration::from_secs_f64(self.total.as_secs_f64() / n as f64),
+            },
+            max: self.max,
+            p50: percentile(0.5),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LatencyStats {
+    pub count: u64,
+    pub mean: Duration,
+    pub max: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+}
+
+impl fmt::Display for LatencyStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} sent, mean {:?}, p50 {:?}, p95 {:?}, p99 {:?}, max {:?}",
+            self.count, self.mean, self.p50, self.p95, self.p99, self.max
+        )
+    }
+}
+
+// how a backend is doing, as far as its thread can tell
+#[derive(Debug, Clone, PartialEq)]
+pub enum Health {
+    // not started yet, or stopped
+    Stopped,
+    Connected,
+    // waiting for its device to come back, or to be restarted
+    Reconnecting,
+    // what its last start, send, flush or poll failed with
+    Error(String),
+}
+
+impl fmt::Display for Health {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Health::Stopped => write!(f, "stopped"),
+            Health::Connected => write!(f, "connected"),
+            Health::Reconnecting => write!(f, "reconnecting"),
+            Health::Error(error) => write!(f, "error: {}", error),
+        }
+    }
+}
+
+// one backend at a glance, e.g. for a status line during a performance
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackendStatus {
+    pub name: String,
+    pub health: Health,
+    // sends over the last RATE_WINDOW
+    pub events_per_second: f64,
+    // from when the last event was due until the backend was done with it
+    pub last_latency: Option<Duration>,
+}
+
+impl fmt::Display for BackendStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}, {:.1} events/s",
+            self.name, self.health, self.events_per_second
+        )?;
+        if let Some(latency) = self.last_latency {
+            write!(f, ", last {:?}", latency)?;
+        }
+        Ok(())
+    }
+}
+
+// what a BackendStatus is made from, kept up to date by the backend's thread
+#[derive(Debug, Clone)]
+pub struct Status {
+    pub health: Health,
+    // when each send within the last RATE_WINDOW finished
+    sends: VecDeque<Instant>,
+    last_latency: Option<Duration>,
+}
+
+impl Default for Status {
+    fn default() -> Self {
+        Self {
+            health: Health::Stopped,
+            sends: VecDeque::new(),
+            last_latency: None,
+        }
+    }
+}
+
+impl Status {
+    pub fn record(&mut self, due: Instant, sent: Instant) {
+        self.sends.push_back(sent);
+        self.last_latency = Some(sent.saturating_duration_since(due));
+        self.forget(sent);
+    }
+
+    fn forget(&mut self, now: Instant) {
+        while let Some(&oldest) = self.sends.front() {
+            if now.saturating_duration_since(oldest) < RATE_WINDOW {
+                break;
+            }
+            self.sends.pop_front();
+        }
+    }
+
+    pub fn snapshot(&mut self, name: &str) -> BackendStatus {
+        self.forget(Instant::now());
+        BackendStatus {
+            name: name.to_string(),
+            health: self.health.clone(),
+            events_per_second: self.sends.len() as f64 / RATE_WINDOW.as_secs_f64(),
+            last_latency: self.last_latency,
+        }
+    }
+}
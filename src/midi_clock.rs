@@ -0,0 +1,244 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::backends::midi::{try_input_port, try_open_output};
+use crate::clock::Clock;
+use crate::timing::sleep_until;
+
+pub const PPQN: u64 = 24;
+
+// longest the pulse thread sleeps before checking it should still run
+const STOP_POLL: Duration = Duration::from_millis(50);
+
+// song position is counted in MIDI beats (16th notes) of 6 pulses each
+const PULSES_PER_SIXTEENTH: u64 = 6;
+
+// weight of each new inter-pulse estimate, pulses jitter by a fair amount
+// so the tempo is averaged over roughly a beat's worth of them
+const BPM_SMOOTHING: f64 = 0.05;
+
+const SONG_POSITION_MSG: u8 = 0xF2;
+const TIMING_CLOCK_MSG: u8 = 0xF8;
+const START_MSG: u8 = 0xFA;
+const CONTINUE_MSG: u8 = 0xFB;
+const STOP_MSG: u8 = 0xFC;
+
+// sends MIDI beat clock derived from the shared clock: pulses go out from a
+// thread of their own between start()/continue_() and stop(), and not while
+// the clock is paused
+pub struct MidiClock {
+    out: Arc<Mutex<midir::MidiOutputConnection>>,
+    clock: Arc<RwLock<Clock>>,
+    running: Arc<AtomicBool>,
+    pulses: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl MidiClock {
+    pub fn new(device_name: &str, clock: Arc<RwLock<Clock>>) -> Result<Self, String> {
+        Ok(Self {
+            out: Arc::new(Mutex::new(try_open_output(device_name)?)),
+            clock,
+            running: Arc::new(AtomicBool::new(false)),
+            pulses: Mutex::new(None),
+        })
+    }
+
+    pub fn start(&self) -> Result<(), String> {
+        self.halt();
+        self.send(&[START_MSG])?;
+        self.spawn();
+        Ok(())
+    }
+
+    pub fn stop(&self) -> Result<(), String> {
+        self.halt();
+        self.send(&[STOP_MSG])
+    }
+
+    pub fn continue_(&self) -> Result<(), String> {
+        self.halt();
+        self.send(&[CONTINUE_MSG])?;
+        self.spawn();
+        Ok(())
+    }
+
+    // tells followers where playback will pick up from on the next continue_()
+    pub fn locate(&self, position: f64) -> Result<(), String> {
+        let sixteenths = ((position * 4.0).round() as u16).min(0x3FFF);
+        self.send(&[
+            SONG_POSITION_MSG,
+            (sixteenths & 0x7F) as u8,
+            (sixteenths >> 7) as u8,
+        ])
+    }
+
+    fn send(&self, msg: &[u8]) -> Result<(), String> {
+        self.out
+            .lock()
+            .unwrap()
+            .send(msg)
+            .map_err(|error| error.to_string())
+    }
+
+    fn spawn(&self) {
+        let out = self.out.clone();
+        let clock = self.clock.clone();
+        // halt() has seen the last one off
+        let running = self.running.clone();
+        running.store(true, Ordering::SeqCst);
+        *self.pulses.lock().unwrap() = Some(thread::spawn(move || pulses(&out, &clock, &running)));
+    }
+
+    // ends the pulses and waits for their thread
+    fn halt(&self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(pulses) = self.pulses.lock().unwrap().take() {
+            let _ = pulses.join();
+        }
+    }
+}
+
+impl Drop for MidiClock {
+    fn drop(&mut self) {
+        self.halt();
+    }
+}
+
+// one pulse per 1/24 beat until `running` goes, waking at least every
+// STOP_POLL to notice; a failing port is reported once until it recovers.
+// Pulses are counted, so a pulse longer than STOP_POLL is waited out rather
+// than skipped; only a jump of the clock (a seek) starts the count over
+fn pulses(out: &Mutex<midir::MidiOutputConnection>, clock: &RwLock<Clock>, running: &AtomicBool) {
+    let mut failing = false;
+    // the last pulse sent, by its index from beat 0
+    let mut last: Option<i64> = None;
+    while running.load(Ordering::SeqCst) {
+        let (next, at) = {
+            let clock = clock.read().unwrap();
+            if clock.is_paused() {
+                drop(clock);
+                thread::sleep(STOP_POLL);
+                continue;
+            }
+            // half-pulse margin so waking a hair early doesn't count as the next pulse
+            let current = (clock.position() * PPQN as f64 + 0.5).floor() as i64;
+            let next = match last {
+                Some(last) if current <= last + PPQN as i64 && current + 1 >= last => last + 1,
+                _ => current + 1,
+            };
+            (next, clock.instant_at(next as f64 / PPQN as f64))
+        };
+        // slept towards in chunks, to notice stopping
+        if at > Instant::now() + STOP_POLL {
+            thread::sleep(STOP_POLL);
+            last = Some(next - 1);
+            continue;
+        }
+        sleep_until(at);
+        if !running.load(Ordering::SeqCst) {
+            break;
+        }
+        last = Some(next);
+        match out.lock().unwrap().send(&[TIMING_CLOCK_MSG]) {
+            Ok(()) => failing = false,
+            Err(error) if !failing => {
+                eprintln!("[midi clock] can't send clock: {}", error);
+                failing = true;
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+struct FollowState {
+    clock: Arc<RwLock<Clock>>,
+    running: bool,
+    pulses: u64,
+    last_pulse: Option<u64>,
+    bpm: Option<f64>,
+}
+
+impl FollowState {
+    // folds the interval since the last pulse into a running tempo estimate
+    fn estimate_bpm(&mut self, timestamp: u64) -> Option<f64> {
+        let last = self.last_pulse?;
+        if timestamp <= last {
+            return self.bpm;
+        }
+        let raw = 60_000_000.0 / ((timestamp - last) * PPQN) as f64;
+        let bpm = match self.bpm {
+            Some(bpm) => bpm + (raw - bpm) * BPM_SMOOTHING,
+            None => raw,
+        };
+        self.bpm = Some(bpm);
+        self.bpm
+    }
+
+    fn handle(&mut self, timestamp: u64, message: &[u8]) {
+        match message.first() {
+            Some(&START_MSG) => {
+                self.pulses = 0;
+                self.last_pulse = None;
+                self.running = true;
+                let mut clock = self.clock.write().unwrap();
+                let bpm = clock.bpm();
+                clock.sync(0.0, bpm);
+            }
+            Some(&CONTINUE_MSG) => {
+                self.last_pulse = None;
+                self.running = true;
+            }
+            Some(&STOP_MSG) => self.running = false,
+            Some(&SONG_POSITION_MSG) if message.len() == 3 => {
+                let sixteenths = message[1] as u64 | (message[2] as u64) << 7;
+                self.pulses = sixteenths * PULSES_PER_SIXTEENTH;
+                self.last_pulse = None;
+                let mut clock = self.clock.write().unwrap();
+                let bpm = clock.bpm();
+                clock.sync(self.pulses as f64 / PPQN as f64, bpm);
+            }
+            Some(&TIMING_CLOCK_MSG) if self.running => {
+                self.pulses += 1;
+                let estimate = self.estimate_bpm(timestamp);
+                let mut clock = self.clock.write().unwrap();
+                let bpm = estimate.unwrap_or_else(|| clock.bpm());
+                clock.sync(self.pulses as f64 / PPQN as f64, bpm);
+                self.last_pulse = Some(timestamp);
+            }
+            _ => {}
+        }
+    }
+}
+
+// follows MIDI beat clock from an external master, driving the shared clock
+pub struct MidiClockFollower {
+    _connection: midir::MidiInputConnection<FollowState>,
+}
+
+impl MidiClockFollower {
+    pub fn new(device_name: &str, clock: Arc<RwLock<Clock>>) -> Result<Self, String> {
+        let midi_in = midir::MidiInput::new(device_name).map_err(|error| error.to_string())?;
+        let in_port = try_input_port(&midi_in, device_name)?;
+        let state = FollowState {
+            clock,
+            running: false,
+            pulses: 0,
+            last_pulse: None,
+            bpm: None,
+        };
+        let connection = midi_in
+            .connect(
+                &in_port,
+                "tonic-clock-in",
+                |timestamp, message, state| state.handle(timestamp, message),
+                state,
+            )
+            .map_err(|error| error.to_string())?;
+
+        Ok(Self {
+            _connection: connection,
+        })
+    }
+}
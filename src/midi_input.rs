@@ -0,0 +1,120 @@
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+
+use crate::backends::midi::{
+    try_input_port, CHANNEL_PRESSURE_MSG, CONTROL_CHANGE_MSG, NOTE_OFF_MSG, NOTE_ON_MSG,
+    PITCH_BEND_MSG, POLY_PRESSURE_MSG, PROGRAM_CHANGE_MSG,
+};
+use crate::clock::{split_position, Clock};
+use crate::event::{Event, Message};
+use crate::recorder::Take;
+use crate::transport::Transport;
+
+// what incoming events are tagged with, so routes can pick them out
+pub const INPUT_TAG: &str = "input";
+
+// the channel and message in a MIDI channel message, None for anything else
+// (clock, sysex, running status)
+pub fn parse(midi: &[u8]) -> Option<(u8, Message)> {
+    let status = *midi.first()?;
+    let data = |i: usize| midi.get(i).map(|byte| byte & 0x7F);
+    let message = match status & 0xF0 {
+        NOTE_OFF_MSG => Message::NoteOff {
+            note: data(1)?,
+            velocity: data(2)?,
+        },
+        NOTE_ON_MSG => Message::NoteOn {
+            note: data(1)?,
+            velocity: data(2)?,
+        },
+        POLY_PRESSURE_MSG => Message::PolyPressure {
+            note: data(1)?,
+            pressure: data(2)?,
+        },
+        CONTROL_CHANGE_MSG => Message::ControlChange {
+            controller: data(1)?,
+            value: data(2)?,
+        },
+        PROGRAM_CHANGE_MSG => Message::ProgramChange { program: data(1)? },
+        CHANNEL_PRESSURE_MSG => Message::ChannelPressure { pressure: data(1)? },
+        PITCH_BEND_MSG => {
+            let bend = data(1)? as i16 | (data(2)? as i16) << 7;
+            Message::PitchBend { value: bend - 8192 }
+        }
+        _ => return None,
+    };
+    Some((status & 0x0F, message))
+}
+
+// live playing from a MIDI input port, each note or CC turned into an event at
+// the beat and tick it came in on; the port stays open as long as this lives
+pub struct MidiInput {
+    _connection: midir::MidiInputConnection<()>,
+    recorded: Arc<Mutex<Vec<Event>>>,
+}
+
+impl MidiInput {
+    // hands every incoming event to `handle`, e.g. to arpeggiate or echo it
+    pub fn open<F: FnMut(Event) + Send + 'static>(
+        device_name: &str,
+        clock: Arc<RwLock<Clock>>,
+        mut handle: F,
+    ) -> Result<Self, String> {
+        let midi_in = midir::MidiInput::new(device_name).map_err(|error| error.to_string())?;
+        let in_port = try_input_port(&midi_in, device_name)?;
+        let connection = midi_in
+            .connect(
+                &in_port,
+                "tonic-in",
+                move |_, midi, _| {
+                    // stamped on arrival, the driver's timestamps don't share
+                    // an epoch with the clock
+                    let at = Instant::now();
+                    let (channel, message) = match parse(midi) {
+                        Some(parsed) => parsed,
+                        None => return,
+                    };
+                    let (beat, tick) = {
+                        let clock = clock.read().unwrap();
+                        split_position(clock.position_of(at).max(0.0), clock.ppqn())
+                    };
+                    let mut event = Event::with_tick(message, beat, tick).on_channel(channel);
+                    event.tag = Some(INPUT_TAG.to_string());
+                    handle(event);
+                },
+                (),
+            )
+            .map_err(|error| error.to_string())?;
+
+        Ok(Self {
+            _connection: connection,
+            recorded: Arc::new(Mutex::new(vec![])),
+        })
+    }
+
+    // plays what comes in right away through the transport's scheduler, so
+    // it goes wherever routes for INPUT_TAG send it
+    pub fn forward(device_name: &str, transport: &Transport) -> Result<Self, String> {
+        let transport = transport.clone();
+        Self::open(device_name, transport.clock().clone(), move |event| {
+            transport.scheduler().schedule_now(event);
+        })
+    }
+
+    // collects what comes in for take()
+    pub fn record(device_name: &str, clock: Arc<RwLock<Clock>>) -> Result<Self, String> {
+        let recorded = Arc::new(Mutex::new(vec![]));
+        let recording = recorded.clone();
+        let input = Self::open(device_name, clock, move |event| {
+            recording.lock().unwrap().push(event);
+        })?;
+        Ok(Self { recorded, ..input })
+    }
+
+    // what was recorded so far, ready to replay; empty unless opened with
+    // record()
+    pub fn take(&self) -> Take {
+        let events = self.recorded.lock().unwrap().drain(..).collect();
+        Take::new(events)
+    }
+}
@@ -0,0 +1,187 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::backends::midi::try_open_output;
+use crate::clock::Clock;
+use crate::timing::sleep_until;
+
+const QUARTER_FRAME_MSG: u8 = 0xF1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameRate {
+    Fps24,
+    Fps25,
+    Fps30Drop,
+    Fps30,
+}
+
+impl FrameRate {
+    fn fps(&self) -> f64 {
+        match self {
+            FrameRate::Fps24 => 24.0,
+            FrameRate::Fps25 => 25.0,
+            FrameRate::Fps30Drop => 29.97,
+            FrameRate::Fps30 => 30.0,
+        }
+    }
+
+    fn code(&self) -> u8 {
+        match self {
+            FrameRate::Fps24 => 0,
+            FrameRate::Fps25 => 1,
+            FrameRate::Fps30Drop => 2,
+            FrameRate::Fps30 => 3,
+        }
+    }
+
+    // frames per timecode second, drop frame keeps counting to 30
+    fn nominal(&self) -> u64 {
+        self.fps().round() as u64
+    }
+}
+
+// hours, minutes, seconds, frames
+fn timecode(elapsed: Duration, rate: FrameRate) -> [u8; 4] {
+    let mut frames = (elapsed.as_secs_f64() * rate.fps()) as u64;
+    if rate == FrameRate::Fps30Drop {
+        // frame numbers 0 and 1 are skipped every minute except each tenth
+        let tens = frames / 17982;
+        let rest = frames % 17982;
+        frames += 18 * tens + 2 * (rest.saturating_sub(2) / 1798);
+    }
+    let fps = rate.nominal();
+    [
+        (frames / (fps * 3600) % 24) as u8,
+        (frames / (fps * 60) % 60) as u8,
+        (frames / fps % 60) as u8,
+        (frames % fps) as u8,
+    ]
+}
+
+fn quarter_frame(piece: u8, time: [u8; 4], rate: FrameRate) -> [u8; 2] {
+    let [hours, minutes, seconds, frames] = time;
+    let nibble = match piece {
+        0 => frames & 0x0F,
+        1 => frames >> 4,
+        2 => seconds & 0x0F,
+        3 => seconds >> 4,
+        4 => minutes & 0x0F,
+        5 => minutes >> 4,
+        6 => hours & 0x0F,
+        _ => (hours >> 4) | (rate.code() << 1),
+    };
+    [QUARTER_FRAME_MSG, (piece << 4) | nibble]
+}
+
+// sends MIDI Time Code quarter frames following the shared clock, from a
+// thread of its own between run() and stop()
+pub struct MtcSender {
+    out: Arc<Mutex<midir::MidiOutputConnection>>,
+    rate: FrameRate,
+    running: Arc<AtomicBool>,
+    frames: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl MtcSender {
+    pub fn new(device_name: &str, rate: FrameRate) -> Result<Self, String> {
+        Ok(Self {
+            out: Arc::new(Mutex::new(try_open_output(device_name)?)),
+            rate,
+            running: Arc::new(AtomicBool::new(false)),
+            frames: Mutex::new(None),
+        })
+    }
+
+    pub fn run(&self, clock: Arc<RwLock<Clock>>) {
+        self.stop();
+        let out = self.out.clone();
+        let rate = self.rate;
+        // stop() has seen the last one off
+        let running = self.running.clone();
+        running.store(true, Ordering::SeqCst);
+        *self.frames.lock().unwrap() = Some(thread::spawn(move || {
+            quarter_frames(&out, &clock, rate, &running)
+        }));
+    }
+
+    // ends the quarter frames and waits for their thread
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(frames) = self.frames.lock().unwrap().take() {
+            let _ = frames.join();
+        }
+    }
+
+    // full frame message, for jumps the quarter frames can't express
+    pub fn locate(&self, elapsed: Duration) -> Result<(), String> {
+        let [hours, minutes, seconds, frames] = timecode(elapsed, self.rate);
+        let msg = [
+            0xF0,
+            0x7F,
+            0x7F,
+            0x01,
+            0x01,
+            hours | (self.rate.code() << 5),
+            minutes,
+            seconds,
+            frames,
+            0xF7,
+        ];
+        self.out
+            .lock()
+            .unwrap()
+            .send(&msg)
+            .map_err(|error| error.to_string())
+    }
+}
+
+impl Drop for MtcSender {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// a quarter frame every quarter of a frame until `running` goes, none while
+// the clock is paused; a failing port is reported once until it recovers
+fn quarter_frames(
+    out: &Mutex<midir::MidiOutputConnection>,
+    clock: &RwLock<Clock>,
+    rate: FrameRate,
+    running: &AtomicBool,
+) {
+    let interval = Duration::from_secs_f64(1.0 / (rate.fps() * 4.0));
+    let mut next = Instant::now();
+    let mut piece = 0;
+    let mut time = [0; 4];
+    let mut failing = false;
+    while running.load(Ordering::SeqCst) {
+        let (paused, elapsed) = {
+            let clock = clock.read().unwrap();
+            (clock.is_paused(), clock.elapsed())
+        };
+        if paused {
+            piece = 0;
+            thread::sleep(interval);
+            next = Instant::now();
+            continue;
+        }
+        // a full time is spread over eight pieces, latch it on the first
+        if piece == 0 {
+            time = timecode(elapsed, rate);
+        }
+        let msg = quarter_frame(piece, time, rate);
+        match out.lock().unwrap().send(&msg) {
+            Ok(()) => failing = false,
+            Err(error) if !failing => {
+                eprintln!("[mtc] can't send quarter frame: {}", error);
+                failing = true;
+            }
+            Err(_) => {}
+        }
+        piece = (piece + 1) % 8;
+        next += interval;
+        sleep_until(next);
+    }
+}
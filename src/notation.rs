@@ -0,0 +1,539 @@
+use std::error::Error;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::{CharIndices, FromStr};
+
+use crate::clock::DEFAULT_PPQN;
+use crate::euclidean;
+use crate::event::{Event, Message, DEFAULT_VELOCITY};
+use crate::generator::Generator;
+use crate::pitch::Pitch;
+
+// most steps a pattern may walk through in a cycle, rests and missed hits
+// included, so "bd*64*64*64" is turned down rather than expanded every beat
+const MAX_STEPS: usize = 4096;
+
+// deepest brackets may nest
+const MAX_DEPTH: usize = 64;
+
+// General MIDI drum names, as in TidalCycles' sample banks
+const DRUMS: [(&str, u8); 16] = [
+    ("bd", 36),
+    ("rim", 37),
+    ("sn", 38),
+    ("sd", 38),
+    ("cp", 39),
+    ("lt", 41),
+    ("hh", 42),
+    ("ch", 42),
+    ("mt", 45),
+    ("oh", 46),
+    ("ht", 48),
+    ("cr", 49),
+    ("rd", 51),
+    ("cb", 56),
+    ("tb", 54),
+    ("cl", 75),
+];
+
+// the note a word in a pattern plays: a drum name like "bd", a pitch name
+// like "C4" or a MIDI note number
+pub fn note(word: &str) -> Option<u8> {
+    if let Some(&(_, note)) = DRUMS.iter().find(|&&(name, _)| name == word) {
+        return Some(note);
+    }
+    match word.parse::<u8>() {
+        Ok(note) => (note <= 127).then_some(note),
+        Err(_) => Pitch::parse(word).ok().map(|pitch| pitch.0),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsePatternError {
+    pub position: usize,
+    pub reason: String,
+}
+
+impl fmt::Display for ParsePatternError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid pattern at {}: {}", self.position, self.reason)
+    }
+}
+
+impl Error for ParsePatternError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Note(u8),
+    Rest,
+    // one after the other, sharing the span equally
+    Sequence(Vec<Node>),
+    // all at once, over the whole span
+    Stack(Vec<Node>),
+    // one per cycle, taking turns
+    Alternate(Vec<Node>),
+    // played `n` times within its span
+    Fast(Box<Node>, usize),
+    // on the hits of E(pulses, steps) rotated, within its span
+    Euclid(Box<Node>, usize, usize, usize),
+}
+
+impl Node {
+    // the most steps query() goes through in a cycle
+    fn steps(&self) -> usize {
+        match self {
+            Node::Note(_) | Node::Rest => 1,
+            Node::Sequence(nodes) | Node::Stack(nodes) => nodes
+                .iter()
+                .fold(0, |steps, node| steps.saturating_add(node.steps())),
+            Node::Alternate(nodes) => nodes.iter().map(Node::steps).max().unwrap_or(0),
+            Node::Fast(node, times) => node.steps().saturating_mul(*times),
+            Node::Euclid(node, _, steps, _) => node.steps().saturating_mul(*steps),
+        }
+    }
+
+    // the notes it plays in `cycle`, as (start, length, note) with the span
+    // it's given
+    fn query(&self, cycle: u64, start: f64, length: f64, notes: &mut Vec<(f64, f64, u8)>) {
+        match self {
+            Node::Note(note) => notes.push((start, length, *note)),
+            Node::Rest => {}
+            Node::Sequence(nodes) => {
+                let step = length / nodes.len() as f64;
+                for (i, node) in nodes.iter().enumerate() {
+                    node.query(cycle, start + i as f64 * step, step, notes);
+                }
+            }
+            Node::Stack(nodes) => {
+                for node in nodes {
+                    node.query(cycle, start, length, notes);
+                }
+            }
+            Node::Alternate(nodes) => {
+                let node = &nodes[(cycle % nodes.len() as u64) as usize];
+                node.query(cycle / nodes.len() as u64, start, length, notes);
+            }
+            // each time round counts as a cycle of its own, so "<bd sn>*2"
+            // plays both every cycle
+            Node::Fast(node, times) => {
+                let step = length / *times as f64;
+                for i in 0..*times {
+                    let repeat = cycle.wrapping_mul(*times as u64) + i as u64;
+                    node.query(repeat, start + i as f64 * step, step, notes);
+                }
+            }
+            Node::Euclid(node, pulses, steps, rotation) => {
+                let step = length / *steps as f64;
+                let hits = euclidean::pattern(*pulses, *steps);
+                for i in (0..*steps).filter(|i| hits[(i + rotation) % steps]) {
+                    node.query(cycle, start + i as f64 * step, step, notes);
+                }
+            }
+        }
+    }
+}
+
+// recursive descent over the text, one character of lookahead
+struct Parser<'a> {
+    text: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    // brackets we're in
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&mut self, reason: &str) -> Result<T, ParsePatternError> {
+        let position = self.position();
+        Err(ParsePatternError {
+            position,
+            reason: reason.to_string(),
+        })
+    }
+
+    fn position(&mut self) -> usize {
+        self.chars.peek().map_or(self.text.len(), |&(i, _)| i)
+    }
+
+    // the next character that isn't whitespace, left in place
+    fn peek(&mut self) -> Option<char> {
+        while let Some(&(_, c)) = self.chars.peek() {
+            if !c.is_whitespace() {
+                return Some(c);
+            }
+            self.chars.next();
+        }
+        None
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParsePatternError> {
+        if self.peek() != Some(expected) {
+            return self.error(&format!("expected '{}'", expected));
+        }
+        self.chars.next();
+        Ok(())
+    }
+
+    // sequences separated by commas, up to `end` (None for the end of the
+    // text), which is left in place
+    fn stack(&mut self, end: Option<char>) -> Result<Node, ParsePatternError> {
+        let mut layers = vec![self.sequence(end)?];
+        while self.peek() == Some(',') {
+            self.chars.next();
+            layers.push(self.sequence(end)?);
+        }
+        Ok(match layers.len() {
+            1 => layers.pop().unwrap(),
+            _ => Node::Stack(layers),
+        })
+    }
+
+    fn sequence(&mut self, end: Option<char>) -> Result<Node, ParsePatternError> {
+        let mut steps = vec![];
+        loop {
+            match self.peek() {
+                Some(',') => break,
+                c if c == end => break,
+                None => return self.error("unclosed bracket"),
+                Some(_) => steps.push(self.step()?),
+            }
+        }
+        if steps.is_empty() {
+            return self.error("empty sequence");
+        }
+        Ok(match steps.len() {
+            1 => steps.pop().unwrap(),
+            _ => Node::Sequence(steps),
+        })
+    }
+
+    // a word, a rest or a bracketed group, then any "*n" and "(k,n,r)"
+    fn step(&mut self) -> Result<Node, ParsePatternError> {
+        if matches!(self.peek(), Some('[') | Some('<')) {
+            if self.depth == MAX_DEPTH {
+                return self.error(&format!("brackets nested over {} deep", MAX_DEPTH));
+            }
+            self.depth += 1;
+        }
+        let mut node = match self.peek() {
+            Some('~') => {
+                self.chars.next();
+                Node::Rest
+            }
+            Some('[') => {
+                self.chars.next();
+                let node = self.stack(Some(']'))?;
+                self.expect(']')?;
+                self.depth -= 1;
+                node
+            }
+            Some('<') => {
+                self.chars.next();
+                let node = match self.sequence(Some('>'))? {
+                    Node::Sequence(nodes) => Node::Alternate(nodes),
+                    node => node,
+                };
+                self.expect('>')?;
+                self.depth -= 1;
+                node
+            }
+            _ => self.word()?,
+        };
+        loop {
+            match self.chars.peek().map(|&(_, c)| c) {
+                Some('*') => {
+                    self.chars.next();
+                    let start = self.position();
+                    let times = self.number()?;
+                    if times == 0 {
+                        return self.error("can't repeat 0 times");
+                    }
+                    node = Node::Fast(Box::new(node), times);
+                    if node.steps() > MAX_STEPS {
+                        return too_many(start);
+                    }
+                }
+                Some('(') => {
+                    self.chars.next();
+                    let start = self.position();
+                    let pulses = self.number()?;
+                    self.expect(',')?;
+                    let steps = self.number()?;
+                    let rotation = match self.peek() {
+                        Some(',') => {
+                            self.chars.next();
+                            self.number()?
+                        }
+                        _ => 0,
+                    };
+                    self.expect(')')?;
+                    if steps == 0 {
+                        return self.error("no steps to spread over");
+                    }
+                    node = Node::Euclid(Box::new(node), pulses, steps, rotation % steps);
+                    if node.steps() > MAX_STEPS {
+                        return too_many(start);
+                    }
+                }
+                _ => return Ok(node),
+            }
+        }
+    }
+
+    fn word(&mut self) -> Result<Node, ParsePatternError> {
+        let start = self.position();
+        while let Some(&(_, c)) = self.chars.peek() {
+            if !(c.is_alphanumeric() || c == '#' || c == '-') {
+                break;
+            }
+            self.chars.next();
+        }
+        let word = &self.text[start..self.position()];
+        if word.is_empty() {
+            return self.error("expected a note, '~', '[' or '<'");
+        }
+        match note(word) {
+            Some(note) => Ok(Node::Note(note)),
+            None => Err(ParsePatternError {
+                position: start,
+                reason: format!("unknown note {:?}", word),
+            }),
+        }
+    }
+
+    fn number(&mut self) -> Result<usize, ParsePatternError> {
+        self.peek();
+        let start = self.position();
+        while self.chars.peek().is_some_and(|&(_, c)| c.is_ascii_digit()) {
+            self.chars.next();
+        }
+        // too big to count counts as not a number, from where it starts
+        self.text[start..self.position()]
+            .parse()
+            .map_err(|_| ParsePatternError {
+                position: start,
+                reason: "expected a number".to_string(),
+            })
+    }
+}
+
+fn too_many<T>(position: usize) -> Result<T, ParsePatternError> {
+    Err(ParsePatternError {
+        position,
+        reason: format!("over {} steps a cycle", MAX_STEPS),
+    })
+}
+
+// a rhythm written in a line, TidalCycles' mini-notation style, that plays
+// once per cycle of `beats` beats (a bar of 4 by default):
+//
+//     "bd ~ [sn sn] hh*4"
+//
+// Steps share the cycle equally; "~" rests, "[...]" fits a sequence into a
+// step, "a*n" plays a step n times over, "<a b>" takes turns cycle by cycle,
+// "a(3,8)" plays a on the hits of E(3, 8) (a third number rotates it) and
+// commas layer sequences, at the top or in brackets. Words are drum names
+// (see note()), pitch names or MIDI note numbers, and notes last their step
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    root: Node,
+    // beats per cycle
+    pub beats: u64,
+    pub velocity: u8,
+    // MIDI channel, 0-15
+    pub channel: u8,
+    pub tag: Option<String>,
+    // the clock's, for placing notes between beats
+    pub ppqn: u64,
+}
+
+impl Pattern {
+    pub fn parse(text: &str) -> Result<Self, ParsePatternError> {
+        let mut parser = Parser {
+            text,
+            chars: text.char_indices().peekable(),
+            depth: 0,
+        };
+        let root = parser.stack(None)?;
+        // layers and sequences add up too
+        if root.steps() > MAX_STEPS {
+            return too_many(0);
+        }
+        Ok(Self {
+            root,
+            beats: 4,
+            velocity: DEFAULT_VELOCITY,
+            channel: 0,
+            tag: None,
+            ppqn: DEFAULT_PPQN,
+        })
+    }
+
+    pub fn with_beats(mut self, beats: u64) -> Self {
+        self.beats = beats;
+        self
+    }
+
+    pub fn with_velocity(mut self, velocity: u8) -> Self {
+        self.velocity = velocity;
+        self
+    }
+
+    pub fn with_channel(mut self, channel: u8) -> Self {
+        self.channel = channel & 0x0F;
+        self
+    }
+
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tag = Some(tag.to_string());
+        self
+    }
+
+    pub fn with_ppqn(mut self, ppqn: u64) -> Self {
+        self.ppqn = ppqn;
+        self
+    }
+
+    // what plays in `cycle`, positioned from the start of the song
+    pub fn cycle(&self, cycle: u64) -> Vec<Event> {
+        let start = (cycle * self.beats) as f64;
+        let mut notes = vec![];
+        self.root.query(cycle, start, self.beats as f64, &mut notes);
+        notes
+            .into_iter()
+            .map(|(position, length, note)| {
+                let message = Message::NoteOn {
+                    note,
+                    velocity: self.velocity,
+                };
+                let event = Event::at_position(message, position, self.ppqn)
+                    .on_channel(self.channel)
+                    .with_duration(length);
+                match self.tag.as_ref() {
+                    Some(tag) => event.tagged(tag),
+                    None => event,
+                }
+            })
+            .collect()
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = ParsePatternError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Self::parse(text)
+    }
+}
+
+// for literals in generators, like Pitch
+impl<'a> From<&'a str> for Pattern {
+    fn from(text: &'a str) -> Self {
+        Self::parse(text).unwrap()
+    }
+}
+
+impl Generator for Pattern {
+    fn generate(&mut self, beat: u64) -> Vec<Event> {
+        if self.beats == 0 {
+            return vec![];
+        }
+        self.cycle(beat / self.beats)
+            .into_iter()
+            .filter(|event| event.beat == beat)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // (position, length, note) of everything in `cycle`
+    fn played(text: &str, cycle: u64) -> Vec<(f64, f64, u8)> {
+        Pattern::parse(text)
+            .unwrap()
+            .cycle(cycle)
+            .iter()
+            .map(|event| {
+                let note = match event.message() {
+                    Some(&Message::NoteOn { note, .. }) => note,
+                    other => panic!("not a note-on: {:?}", other),
+                };
+                (event.position(DEFAULT_PPQN), event.duration.unwrap(), note)
+            })
+            .collect()
+    }
+
+    fn error_at(text: &str) -> usize {
+        Pattern::parse(text).unwrap_err().position
+    }
+
+    #[test]
+    fn steps_share_the_cycle() {
+        assert_eq!(
+            played("bd ~ [sn sn] hh*4", 0),
+            vec![
+                (0.0, 1.0, 36),
+                (2.0, 0.5, 38),
+                (2.5, 0.5, 38),
+                (3.0, 0.25, 42),
+                (3.25, 0.25, 42),
+                (3.5, 0.25, 42),
+                (3.75, 0.25, 42),
+            ]
+        );
+    }
+
+    #[test]
+    fn later_cycles_start_later() {
+        assert_eq!(played("bd sn", 2), vec![(8.0, 2.0, 36), (10.0, 2.0, 38)]);
+    }
+
+    #[test]
+    fn alternates_take_turns() {
+        assert_eq!(played("<bd sn>", 0), vec![(0.0, 4.0, 36)]);
+        assert_eq!(played("<bd sn>", 1), vec![(4.0, 4.0, 38)]);
+        assert_eq!(played("<bd sn>*2", 0), vec![(0.0, 2.0, 36), (2.0, 2.0, 38)]);
+    }
+
+    #[test]
+    fn euclid_plays_on_the_hits() {
+        let positions: Vec<f64> = played("bd(3,8)", 0).iter().map(|n| n.0).collect();
+        assert_eq!(positions, vec![0.0, 1.5, 3.0]);
+        let rotated: Vec<f64> = played("bd(3,8,2)", 0).iter().map(|n| n.0).collect();
+        assert_eq!(rotated, vec![0.5, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn layers_play_together() {
+        assert_eq!(
+            played("bd, hh hh", 0),
+            vec![(0.0, 4.0, 36), (0.0, 2.0, 42), (2.0, 2.0, 42)]
+        );
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        assert_eq!(error_at("bd xx"), 3);
+        assert_eq!(error_at("bd [sn"), 6);
+        assert_eq!(error_at("bd <sn hh"), 9);
+        assert_eq!(error_at("bd*0"), 4);
+        assert_eq!(error_at("bd(3,0)"), 7);
+        assert_eq!(error_at("bd*"), 3);
+        assert_eq!(error_at(""), 0);
+    }
+
+    #[test]
+    fn huge_patterns_are_turned_down() {
+        assert!(Pattern::parse("bd*64*64").is_ok());
+        assert_eq!(error_at("bd*64*64*64"), 9);
+        assert_eq!(error_at("bd*99999999999999999999"), 3);
+        assert_eq!(error_at("bd(1,5000)"), 3);
+        assert_eq!(error_at("[bd*4096]*2"), 10);
+        assert_eq!(error_at("bd*4096 bd"), 0);
+        assert_eq!(error_at("~*64*128"), 5);
+        assert_eq!(error_at("bd(1,64)*128"), 9);
+        let deep = format!("{}bd{}", "[".repeat(65), "]".repeat(65));
+        assert_eq!(error_at(&deep), 64);
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use crate::dead_letter;
+use crate::event::Event;
+use crate::metrics::Latency;
+use crate::timing::sleep_until;
+
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+// a drop is reported the first time and then every this many
+const REPORT_EVERY: u64 = 100;
+
+// what a full outlet does with one more event
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    // waits for the backend to catch up, holding up every backend behind it
+    Block,
+    DropOldest,
+    DropNewest,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Backpressure {
+    pub capacity: usize,
+    pub overflow: Overflow,
+}
+
+impl Default for Backpressure {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_CAPACITY,
+            overflow: Overflow::Block,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    // with the instant each is due at the backend
+    events: VecDeque<(Instant, Event)>,
+    dropped: u64,
+    // no more events are coming, or the backend stopped taking them
+    closed: bool,
+}
+
+// a bounded queue in front of a backend's receiver: a forwarding thread hands
+// events over one at a time when they're due, along with that instant, so a
+// backend that stalls backs up here and not in an ever growing channel
+pub struct Outlet {
+    backend: usize,
+    queue: Arc<(Mutex<Queue>, Condvar)>,
+    backpressure: Arc<Mutex<Backpressure>>,
+    forwarder: Option<JoinHandle<()>>,
+}
+
+impl Outlet {
+    // `latency` is shared by all outlets and records into slot `backend`;
+    // `failed` is called on the forwarding thread if the backend stops receiving
+    pub fn new<F: FnOnce() + Send + 'static>(
+        backend: usize,
+        backpressure: Arc<Mutex<Backpressure>>,
+        latency: Arc<Mutex<Vec<Latency>>>,
+        failed: F,
+    ) -> (Self, Receiver<(Instant, Event)>) {
+        let (sender, receiver) = sync_channel(0);
+        let queue = Arc::new((Mutex::new(Queue::default()), Condvar::new()));
+        let forwarding = queue.clone();
+        let forwarder = thread::spawn(move || {
+            let (queue, changed) = &*forwarding;
+            loop {
+                let (due, event) = {
+                    let mut queued = queue.lock().unwrap();
+                    loop {
+                        if let Some(next) = queued.events.pop_front() {
+                            changed.notify_all();
+                            break next;
+                        }
+                        // whatever was queued goes out before hanging up
+                        if queued.closed {
+                            return;
+                        }
+                        queued = changed.wait(queued).unwrap();
+                    }
+                };
+                sleep_until(due);
+                // the backend's thread is gone, nothing queued for it can go out
+                if let Err(unsent) = sender.send((due, event)) {
+                    let name = format!("backend {}", backend);
+                    let lost: Vec<Event> = {
+                        let mut queued = queue.lock().unwrap();
+                        queued.closed = true;
+                        changed.notify_all();
+                        queued.events.drain(..).map(|(_, event)| event).collect()
+                    };
+                    for event in Some((unsent.0).1).into_iter().chain(lost) {
+                        dead_letter::post(event, &name, "backend stopped receiving");
+                    }
+                    failed();
+                    return;
+                }
+                let late = Instant::now().saturating_duration_since(due);
+                latency.lock().unwrap()[backend].record(late);
+            }
+        });
+        let outlet = Self {
+            backend,
+            queue,
+            backpressure,
+            forwarder: Some(forwarder),
+        };
+        (outlet, receiver)
+    }
+
+    pub fn send(&self, due: Instant, event: Event) {
+        let backpressure = *self.backpressure.lock().unwrap();
+        let (queue, changed) = &*self.queue;
+        let mut queued = queue.lock().unwrap();
+        while queued.events.len() >= backpressure.capacity.max(1) && !queued.closed {
+            match backpressure.overflow {
+                Overflow::Block => queued = changed.wait(queued).unwrap(),
+                Overflow::DropOldest => {
+                    queued.events.pop_front();
+                    self.report(&mut queued);
+                }
+                Overflow::DropNewest => {
+                    self.report(&mut queued);
+                    return;
+                }
+            }
+        }
+        if queued.closed {
+            let name = format!("backend {}", self.backend);
+            drop(queued);
+            dead_letter::post(event, &name, "backend stopped receiving");
+            return;
+        }
+        queued.events.push_back((due, event));
+        changed.notify_all();
+    }
+
+    fn report(&self, queued: &mut Queue) {
+        queued.dropped += 1;
+        if queued.dropped % REPORT_EVERY == 1 {
+            eprintln!(
+                "[outlet] backend {} is falling behind, {} events dropped",
+                self.backend, queued.dropped
+            );
+        }
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.queue.0.lock().unwrap().dropped
+    }
+
+    // lets the queue run dry, then disconnects the backend
+    pub fn close(&mut self) {
+        let (queue, changed) = &*self.queue;
+        queue.lock().unwrap().closed = true;
+        changed.notify_all();
+        if let Some(forwarder) = self.forwarder.take() {
+            forwarder.join().unwrap();
+        }
+    }
+}
+
+impl Drop for Outlet {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
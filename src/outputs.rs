@@ -0,0 +1,440 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::backends::{Backend, BackendError};
+use crate::dead_letter;
+use crate::event::Event;
+use crate::metrics::{BackendStatus, Health, Latency, Status};
+use crate::outlet::{Backpressure, Outlet};
+
+// locked by the backend's thread for as long as it runs
+pub type SharedBackend = Arc<Mutex<Box<dyn Backend>>>;
+
+// None where a backend was removed, so the others keep their indices
+pub type Backends = Vec<Option<SharedBackend>>;
+
+// which of a backend's methods failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Start,
+    Send,
+    Flush,
+    Poll,
+    Stop,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Stage::Start => "start",
+            Stage::Send => "send",
+            Stage::Flush => "flush",
+            Stage::Poll => "poll",
+            Stage::Stop => "stop",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// a backend method that returned an error
+#[derive(Debug, Clone)]
+pub struct BackendFailure {
+    // its index in the scheduler
+    pub backend: usize,
+    pub name: String,
+    pub stage: Stage,
+    pub error: BackendError,
+    pub at: Instant,
+}
+
+// whether what the backend plays through is there right now
+fn connection(backend: &dyn Backend) -> Health {
+    if backend.is_connected() {
+        return Health::Connected;
+    }
+    match backend.missing() {
+        Some(error) => Health::Error(error),
+        None => Health::Reconnecting,
+    }
+}
+
+impl fmt::Display for BackendFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "backend {} ({}) failed to {}: {}",
+            self.backend, self.name, self.stage, self.error
+        )
+    }
+}
+
+// how a backend that stopped taking events is brought back: after `initial`,
+// doubling with every failure in a row up to `max`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Restart {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Restart {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self { initial, max }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.min(16));
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+}
+
+// the backends of a scheduler (and its shares) with an outlet and a thread
+// each; a backend that fails to start, panics or otherwise stops receiving is
+// reported and, with a Restart set, started again behind a fresh outlet
+#[derive(Clone)]
+pub struct Outputs {
+    pub backends: Arc<Mutex<Backends>>,
+    // Backend::name of each, taken when it was added since a running
+    // backend is locked by its thread
+    pub names: Arc<Mutex<Vec<String>>>,
+    // indexed like `backends`, None for removed, stopped or not yet started ones
+    pub producers: Arc<Mutex<Vec<Option<Outlet>>>>,
+    pub latency: Arc<Mutex<Vec<Latency>>>,
+    // indexed like `backends`, kept by each backend's thread
+    pub status: Arc<Mutex<Vec<Status>>>,
+    pub backpressure: Arc<Mutex<Backpressure>>,
+    pub restart: Arc<Mutex<Option<Restart>>>,
+    started: Arc<AtomicBool>,
+    // the thread running each backend, by index
+    threads: Arc<Mutex<HashMap<usize, JoinHandle<()>>>>,
+    subscribers: Arc<Mutex<Vec<Sender<BackendFailure>>>>,
+}
+
+impl Outputs {
+    pub fn new(backends: Vec<Box<dyn Backend>>) -> Self {
+        let names = backends.iter().map(|backend| backend.name()).collect();
+        let status = backends.iter().map(|_| Status::default()).collect();
+        Self {
+            names: Arc::new(Mutex::new(names)),
+            status: Arc::new(Mutex::new(status)),
+            backends: Arc::new(Mutex::new(
+                backends
+                    .into_iter()
+                    .map(|backend| Some(Arc::new(Mutex::new(backend))))
+                    .collect(),
+            )),
+            producers: Arc::new(Mutex::new(vec![])),
+            latency: Arc::new(Mutex::new(vec![])),
+            backpressure: Arc::new(Mutex::new(Backpressure::default())),
+            restart: Arc::new(Mutex::new(None)),
+            started: Arc::new(AtomicBool::new(false)),
+            threads: Arc::new(Mutex::new(HashMap::new())),
+            subscribers: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started.load(Ordering::SeqCst)
+    }
+
+    // starts every backend not running yet
+    pub fn start_all(&self) {
+        self.started.store(true, Ordering::SeqCst);
+        let count = self.backends.lock().unwrap().len();
+        let mut producers = self.producers.lock().unwrap();
+        for i in producers.len()..count {
+            let mut latency = self.latency.lock().unwrap();
+            if latency.len() <= i {
+                latency.push(Latency::default());
+            }
+            drop(latency);
+            producers.push(self.start(i, 0));
+        }
+    }
+
+    // runs the backend at `index` behind a new outlet, None if it was removed
+    fn start(&self, index: usize, attempt: u32) -> Option<Outlet> {
+        let backend = self.backends.lock().unwrap().get(index)?.clone()?;
+        let outputs = self.clone();
+        let started_at = Instant::now();
+        let (outlet, receiver) = Outlet::new(
+            index,
+            self.backpressure.clone(),
+            self.latency.clone(),
+            move || outputs.failed(index, attempt, started_at),
+        );
+        let outputs = self.clone();
+        let thread = thread::spawn(move || outputs.drive(index, backend, receiver));
+        // one that failed before is done with or about to be, the new thread
+        // waits for it to let go of the backend
+        self.threads.lock().unwrap().insert(index, thread);
+        Some(outlet)
+    }
+
+    // the backend's thread: starts it, hands it events as the outlet lets
+    // them through and stops it once the outlet disconnects. A backend that
+    // fails to start or panics drops the receiver, so the outlet notices on
+    // its next send
+    fn drive(&self, index: usize, backend: SharedBackend, receiver: Receiver<(Instant, Event)>) {
+        // one that panicked earlier may be in any state, start() sorts it out
+        let mut backend = backend.lock().unwrap_or_else(PoisonError::into_inner);
+        let name = backend.name();
+        if let Err(error) = backend.start() {
+            self.report(index, &name, Stage::Start, error);
+            return;
+        }
+        self.set_health(index, connection(&**backend));
+        let interval = backend.poll_interval();
+        let wait = |receiver: &Receiver<(Instant, Event)>| match interval {
+            Some(interval) => receiver.recv_timeout(interval),
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        let mut next = wait(&receiver);
+        loop {
+            match next {
+                Ok((due, event)) => match backend.send_at(&event, due) {
+                    Ok(()) => {
+                        let mut status = self.status.lock().unwrap();
+                        status[index].record(due, Instant::now());
+                        status[index].health = connection(&**backend);
+                    }
+                    Err(error) => {
+                        dead_letter::post(event, &name, &error);
+                        self.report(index, &name, Stage::Send, error);
+                    }
+                },
+                Err(RecvTimeoutError::Timeout) => match backend.poll() {
+                    Ok(()) => self.set_health(index, connection(&**backend)),
+                    Err(error) => self.report(index, &name, Stage::Poll, error),
+                },
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            // events due together go out before the flush
+            next = match receiver.try_recv() {
+                Ok(event) => Ok(event),
+                Err(TryRecvError::Empty) => {
+                    if let Err(error) = backend.flush() {
+                        self.report(index, &name, Stage::Flush, error);
+                    }
+                    wait(&receiver)
+                }
+                Err(TryRecvError::Disconnected) => Err(RecvTimeoutError::Disconnected),
+            };
+        }
+        if let Err(error) = backend.flush() {
+            self.report(index, &name, Stage::Flush, error);
+        }
+        if let Err(error) = backend.stop() {
+            self.report(index, &name, Stage::Stop, error);
+        }
+        self.set_health(index, Health::Stopped);
+    }
+
+    fn set_health(&self, index: usize, health: Health) {
+        if let Some(status) = self.status.lock().unwrap().get_mut(index) {
+            status.health = health;
+        }
+    }
+
+    // each backend's status, None for removed ones
+    pub fn statuses(&self) -> Vec<Option<BackendStatus>> {
+        let backends = self.backends.lock().unwrap();
+        let names = self.names.lock().unwrap();
+        let mut status = self.status.lock().unwrap();
+        status
+            .iter_mut()
+            .enumerate()
+            .map(|(index, status)| {
+                backends
+                    .get(index)?
+                    .as_ref()
+                    .map(|_| status.snapshot(&names[index]))
+            })
+            .collect()
+    }
+
+    // passes a failure on to whoever subscribed; with nobody listening it goes
+    // to stderr, except for failed sends, which are dead letters already.
+    // Anything but a failed stop leaves the backend in error until it does
+    // better
+    fn report(&self, backend: usize, name: &str, stage: Stage, error: BackendError) {
+        if stage != Stage::Stop {
+            self.set_health(backend, Health::Error(error.to_string()));
+        }
+        let failure = BackendFailure {
+            backend,
+            name: name.to_string(),
+            stage,
+            error,
+            at: Instant::now(),
+        };
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|subscriber| subscriber.send(failure.clone()).is_ok());
+        if subscribers.is_empty() && stage != Stage::Send {
+            eprintln!("[outputs] {}", failure);
+        }
+    }
+
+    // collects backend failures on a channel
+    pub fn failures(&self) -> Receiver<BackendFailure> {
+        let (sender, receiver) = channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    fn join(&self, index: usize) {
+        let thread = self.threads.lock().unwrap().remove(&index);
+        if let Some(thread) = thread {
+            // a panic has had its say already, it isn't passed on
+            let _ = thread.join();
+        }
+    }
+
+    // called by the outlet once the backend stopped receiving
+    fn failed(&self, index: usize, attempt: u32, started_at: Instant) {
+        eprintln!("[outputs] backend {} stopped", index);
+        let restart = match *self.restart.lock().unwrap() {
+            Some(restart) => restart,
+            None => {
+                // a failed start has said why already
+                let mut status = self.status.lock().unwrap();
+                if let Some(status) = status.get_mut(index) {
+                    if !matches!(status.health, Health::Error(_)) {
+                        status.health = Health::Error("stopped receiving".to_string());
+                    }
+                }
+                return;
+            }
+        };
+        self.set_health(index, Health::Reconnecting);
+        // a backend that ran fine for a while starts over with a short wait
+        let attempt = if started_at.elapsed() > restart.max {
+            0
+        } else {
+            attempt
+        };
+        let delay = restart.backoff(attempt);
+        let outputs = self.clone();
+        thread::spawn(move || {
+            thread::sleep(delay);
+            if !outputs.is_started() {
+                return;
+            }
+            eprintln!("[outputs] restarting backend {}", index);
+            let outlet = outputs.start(index, attempt + 1);
+            // the dead outlet's forwarder is gone already, so replacing it
+            // doesn't block; a slot emptied by remove() stays empty
+            if let Some(slot) = outputs.producers.lock().unwrap().get_mut(index) {
+                if slot.is_some() {
+                    *slot = outlet;
+                }
+            }
+        });
+    }
+
+    // adds a backend, running it right away if the others are; returns its index
+    pub fn add(&self, backend: Box<dyn Backend>) -> usize {
+        let index = {
+            let mut backends = self.backends.lock().unwrap();
+            self.names.lock().unwrap().push(backend.name());
+            self.status.lock().unwrap().push(Status::default());
+            backends.push(Some(Arc::new(Mutex::new(backend))));
+            backends.len() - 1
+        };
+        if self.is_started() {
+            self.start_all();
+        }
+        index
+    }
+
+    // lets whatever is queued for the backend go out, stops it and waits for
+    // it to wrap up, keeping it for resume(); false if there's no backend
+    // running at `index`
+    pub fn stop(&self, index: usize) -> bool {
+        let outlet = self
+            .producers
+            .lock()
+            .unwrap()
+            .get_mut(index)
+            .and_then(Option::take);
+        let stopped = outlet.is_some();
+        drop(outlet);
+        self.join(index);
+        stopped
+    }
+
+    // starts a backend stop() stopped; false if there's none at `index` or
+    // it's running already
+    pub fn resume(&self, index: usize) -> bool {
+        if !self.is_started() {
+            return false;
+        }
+        let mut producers = self.producers.lock().unwrap();
+        match producers.get_mut(index) {
+            Some(slot) if slot.is_none() => {
+                *slot = self.start(index, 0);
+                slot.is_some()
+            }
+            _ => false,
+        }
+    }
+
+    // like stop(), and lets go of the backend; false if there's no backend
+    // at `index`
+    pub fn remove(&self, index: usize) -> bool {
+        self.stop(index);
+        self.backends
+            .lock()
+            .unwrap()
+            .get_mut(index)
+            .and_then(Option::take)
+            .is_some()
+    }
+
+    // the index of the backend `name` names, ignoring case; removed ones
+    // don't count
+    pub fn find(&self, name: &str) -> Option<usize> {
+        let backends = self.backends.lock().unwrap();
+        self.names
+            .lock()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .find(|&(index, known)| {
+                known.eq_ignore_ascii_case(name) && backends.get(index).is_some_and(Option::is_some)
+            })
+            .map(|(index, _)| index)
+    }
+
+    // events each backend lost to overflow
+    pub fn dropped(&self) -> Vec<u64> {
+        self.producers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|outlet| outlet.as_ref().map_or(0, Outlet::dropped))
+            .collect()
+    }
+
+    // disconnects the backends once their queues ran dry and waits for them
+    // to wrap up
+    pub fn close(&self) {
+        self.started.store(false, Ordering::SeqCst);
+        let outlets: Vec<Option<Outlet>> = self.producers.lock().unwrap().drain(..).collect();
+        drop(outlets);
+        let threads: Vec<JoinHandle<()>> = self
+            .threads
+            .lock()
+            .unwrap()
+            .drain()
+            .map(|(_, thread)| thread)
+            .collect();
+        for thread in threads {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+const NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+// which octave number middle C (MIDI 60) gets, gear doesn't agree on it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OctaveConvention {
+    // C4 = 60, as in scientific pitch notation
+    Scientific,
+    // C3 = 60, as on Yamaha and many DAWs
+    Yamaha,
+}
+
+impl OctaveConvention {
+    fn middle_c(self) -> i32 {
+        match self {
+            OctaveConvention::Scientific => 4,
+            OctaveConvention::Yamaha => 3,
+        }
+    }
+}
+
+static CONVENTION: AtomicU8 = AtomicU8::new(0);
+
+// the convention used by parse(), FromStr and Display
+pub fn set_octave_convention(convention: OctaveConvention) {
+    CONVENTION.store(convention as u8, Ordering::SeqCst);
+}
+
+pub fn octave_convention() -> OctaveConvention {
+    match CONVENTION.load(Ordering::SeqCst) {
+        0 => OctaveConvention::Scientific,
+        _ => OctaveConvention::Yamaha,
+    }
+}
+
+// the semitone of a leading note name like "C#" or "Bb" (C is 0, may run
+// outside 0-11 for "Cb" or "B#"), and whatever follows it
+pub fn split_pitch_class(name: &str) -> Option<(i32, &str)> {
+    let mut chars = name.char_indices();
+    let (_, letter) = chars.next()?;
+    let mut class = match letter.to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    for (i, accidental) in chars {
+        match accidental {
+            '#' => class += 1,
+            'b' => class -= 1,
+            _ => return Some((class, &name[i..])),
+        }
+    }
+    Some((class, ""))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsePitchError(String);
+
+impl fmt::Display for ParsePitchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid pitch name: {:?}", self.0)
+    }
+}
+
+impl Error for ParsePitchError {}
+
+// a MIDI note number, written as a name like "C#4" or "Bb2"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Pitch(pub u8);
+
+impl Pitch {
+    pub fn parse(name: &str) -> Result<Self, ParsePitchError> {
+        Self::parse_with(name, octave_convention())
+    }
+
+    pub fn parse_with(name: &str, convention: OctaveConvention) -> Result<Self, ParsePitchError> {
+        let error = || ParsePitchError(name.to_string());
+        let (class, octave) = split_pitch_class(name.trim()).ok_or_else(error)?;
+        let octave: i32 = octave.parse().map_err(|_| error())?;
+
+        let note = (octave - convention.middle_c() + 5) * 12 + class;
+        if !(0..=127).contains(&note) {
+            return Err(error());
+        }
+        Ok(Pitch(note as u8))
+    }
+
+    pub fn octave(&self) -> i32 {
+        self.0 as i32 / 12 - 5 + octave_convention().middle_c()
+    }
+}
+
+impl FromStr for Pitch {
+    type Err = ParsePitchError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::parse(name)
+    }
+}
+
+impl fmt::Display for Pitch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", NAMES[self.0 as usize % 12], self.octave())
+    }
+}
+
+impl From<u8> for Pitch {
+    fn from(note: u8) -> Self {
+        Pitch(note)
+    }
+}
+
+// for literals in generators, a typo'd name panics like any other bad literal
+impl<'a> From<&'a str> for Pitch {
+    fn from(name: &'a str) -> Self {
+        Self::parse(name).unwrap()
+    }
+}
@@ -0,0 +1,45 @@
+use std::cmp::Reverse;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::Event;
+
+// everything a scheduler placed on the beat timeline while recording, after
+// probability rolls and ratchets were settled, so a replay comes out the same
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Take {
+    pub events: Vec<Event>,
+}
+
+impl Take {
+    pub fn new(mut events: Vec<Event>) -> Self {
+        events.sort_by_key(|event| (event.beat, event.tick, Reverse(event.priority)));
+        Self { events }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    // beats from the first event to the last one
+    pub fn length(&self) -> u64 {
+        match (self.events.first(), self.events.last()) {
+            (Some(first), Some(last)) => last.beat - first.beat,
+            _ => 0,
+        }
+    }
+
+    // the same take starting `beats` later, e.g. to loop it
+    pub fn shifted(&self, beats: u64) -> Self {
+        let events = self
+            .events
+            .iter()
+            .cloned()
+            .map(|mut event| {
+                event.beat += beats;
+                event
+            })
+            .collect();
+        Self { events }
+    }
+}
@@ -0,0 +1,130 @@
+use std::io::{self, BufRead, BufReader, ErrorKind, Read};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::backends::net::Protocol;
+use crate::event::{Control, Event};
+use crate::json;
+use crate::transport::Transport;
+
+const POLL: Duration = Duration::from_millis(100);
+
+// longest line a sender may send, as much as a datagram can carry
+const MAX_LINE: usize = 65536;
+
+fn receive(text: &str, transport: &Transport) {
+    match json::from_str::<Event>(text.trim()) {
+        // can't wait for its beat
+        Ok(event) if event.control() == Some(&Control::Panic) => transport.panic(),
+        // its note-off was sent along too, another one here would cut a
+        // retrigger short
+        Ok(mut event) => {
+            event.duration = None;
+            transport.schedule(event);
+        }
+        Err(error) => eprintln!("[remote] dropped a message: {}", error),
+    }
+}
+
+// one sender's lines until it hangs up, sends a line over MAX_LINE or the
+// remote is stopped
+fn connection(stream: TcpStream, from: SocketAddr, transport: &Transport, listening: &AtomicBool) {
+    if stream.set_read_timeout(Some(POLL)).is_err() {
+        return;
+    }
+    let mut reader = BufReader::new(stream);
+    let mut line = vec![];
+    while listening.load(Ordering::SeqCst) {
+        // a line cut short by the timeout carries on where it left off
+        let limit = (MAX_LINE + 1 - line.len()) as u64;
+        match (&mut reader).take(limit).read_until(b'\n', &mut line) {
+            Err(ref error)
+                if error.kind() == ErrorKind::WouldBlock || error.kind() == ErrorKind::TimedOut => {
+            }
+            Err(_) => break,
+            Ok(_) if line.len() > MAX_LINE => {
+                eprintln!("[remote] dropped {}: line over {} bytes", from, MAX_LINE);
+                break;
+            }
+            Ok(_) => {
+                let hung_up = !line.ends_with(b"\n");
+                if !line.is_empty() {
+                    receive(&String::from_utf8_lossy(&line), transport);
+                    line.clear();
+                }
+                if hung_up {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn bind_error(address: &str, error: io::Error) -> io::Error {
+    io::Error::new(
+        error.kind(),
+        format!("can't listen on {}: {}", address, error),
+    )
+}
+
+// the receiving end of a NetBackend: events sent from another tonic process
+// are scheduled on this transport at the beat they carry, so they go through
+// the routes and backends here; listens until stopped
+pub struct Remote {
+    running: Arc<AtomicBool>,
+}
+
+impl Remote {
+    // `address` is host:port to listen on, e.g. "0.0.0.0:7400"
+    pub fn listen(address: &str, protocol: Protocol, transport: &Transport) -> io::Result<Self> {
+        let running = Arc::new(AtomicBool::new(true));
+        let listening = running.clone();
+        let transport = transport.clone();
+        match protocol {
+            Protocol::Udp => {
+                let socket =
+                    UdpSocket::bind(address).map_err(|error| bind_error(address, error))?;
+                socket.set_read_timeout(Some(POLL))?;
+                thread::spawn(move || {
+                    let mut buffer = vec![0; MAX_LINE];
+                    while listening.load(Ordering::SeqCst) {
+                        if let Ok((read, _)) = socket.recv_from(&mut buffer) {
+                            receive(&String::from_utf8_lossy(&buffer[..read]), &transport);
+                        }
+                    }
+                });
+            }
+            Protocol::Tcp => {
+                let listener =
+                    TcpListener::bind(address).map_err(|error| bind_error(address, error))?;
+                listener.set_nonblocking(true)?;
+                thread::spawn(move || {
+                    while listening.load(Ordering::SeqCst) {
+                        let (stream, from) = match listener.accept() {
+                            Ok(accepted) => accepted,
+                            Err(_) => {
+                                thread::sleep(POLL);
+                                continue;
+                            }
+                        };
+                        println!("[remote] {} connected", from);
+                        let _ = stream.set_nonblocking(false);
+                        let transport = transport.clone();
+                        let listening = listening.clone();
+                        // one per sender, until it hangs up
+                        thread::spawn(move || connection(stream, from, &transport, &listening));
+                    }
+                });
+            }
+        }
+        Ok(Self { running })
+    }
+
+    // stops taking new events, open connections included
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+}
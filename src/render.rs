@@ -0,0 +1,24 @@
+use crate::clock::Clock;
+use crate::generator::Generator;
+use crate::scheduler::Scheduler;
+
+// plays `beats` beats of the generators without waiting for them: the clock is
+// frozen and stepped one beat at a time, and an offline scheduler hands the
+// events to its backends in order at the end
+pub fn render(
+    clock: &mut Clock,
+    scheduler: &Scheduler,
+    generators: &mut [Box<dyn Generator>],
+    beats: u64,
+) {
+    scheduler.start_backends();
+    clock.stop();
+    for beat in 1..=beats {
+        clock.seek((beat - 1) as f64);
+        for generator in generators.iter_mut() {
+            scheduler.schedule_batch(clock, generator.generate(beat));
+        }
+    }
+    scheduler.drain();
+    scheduler.close();
+}
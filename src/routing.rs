@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::event::Event;
+use crate::scheduler::LatePolicy;
+
+// rewrites an event on its way to one backend, None drops it
+pub type Transform = Arc<dyn Fn(Event) -> Option<Event> + Send + Sync>;
+
+// one edge of the routing matrix: a backend, plus what to change on the way
+#[derive(Clone)]
+pub struct Route {
+    pub backend: usize,
+    pub channel: Option<u8>,
+    pub transform: Option<Transform>,
+    // overrides the scheduler's late policy for the tag
+    pub late: Option<LatePolicy>,
+}
+
+impl Route {
+    // `backend` is the backend's index in Scheduler::new()
+    pub fn to(backend: usize) -> Self {
+        Self {
+            backend,
+            channel: None,
+            transform: None,
+            late: None,
+        }
+    }
+
+    pub fn channel(mut self, channel: u8) -> Self {
+        self.channel = Some(channel & 0x0F);
+        self
+    }
+
+    pub fn transform<F>(mut self, transform: F) -> Self
+    where
+        F: Fn(Event) -> Option<Event> + Send + Sync + 'static,
+    {
+        self.transform = Some(Arc::new(transform));
+        self
+    }
+
+    pub fn late(mut self, policy: LatePolicy) -> Self {
+        self.late = Some(policy);
+        self
+    }
+
+    fn apply(&self, mut event: Event) -> Option<Event> {
+        if let Some(channel) = self.channel {
+            event.channel = channel;
+        }
+        match &self.transform {
+            Some(transform) => transform(event),
+            None => Some(event),
+        }
+    }
+}
+
+// decides which backends get an event: tags with routes go exactly where
+// their routes say, everything else goes to every backend not subscribed
+// to just some tags
+#[derive(Default)]
+pub struct Router {
+    routes: HashMap<String, Vec<Route>>,
+    subscriptions: HashMap<usize, Vec<String>>,
+}
+
+impl Router {
+    pub fn route(&mut self, tag: &str, route: Route) {
+        self.routes.entry(tag.to_string()).or_default().push(route);
+    }
+
+    pub fn unroute(&mut self, tag: &str) {
+        self.routes.remove(tag);
+    }
+
+    pub fn subscribe(&mut self, backend: usize, tags: Vec<String>) {
+        self.subscriptions.insert(backend, tags);
+    }
+
+    pub fn unsubscribe(&mut self, backend: usize) {
+        self.subscriptions.remove(&backend);
+    }
+
+    // the late policy of the first of the event's routes that has one
+    pub fn late_policy(&self, event: &Event) -> Option<LatePolicy> {
+        let routes = self.routes.get(event.tag.as_ref()?)?;
+        routes.iter().find_map(|route| route.late)
+    }
+
+    // (backend index, event for it) for an event among `backends` backends
+    pub fn dispatch(&self, event: &Event, backends: usize) -> Vec<(usize, Event)> {
+        if let Some(routes) = event.tag.as_ref().and_then(|tag| self.routes.get(tag)) {
+            return routes
+                .iter()
+                .filter(|route| route.backend < backends)
+                .filter_map(|route| Some((route.backend, route.apply(event.clone())?)))
+                .collect();
+        }
+        (0..backends)
+            .filter(
+                |backend| match (self.subscriptions.get(backend), &event.tag) {
+                    (None, _) => true,
+                    (Some(tags), Some(tag)) => tags.contains(tag),
+                    (Some(_), None) => false,
+                },
+            )
+            .map(|backend| (backend, event.clone()))
+            .collect()
+    }
+}
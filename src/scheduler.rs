@@ -1,42 +1,1014 @@
-use std::sync::mpsc::{channel, Sender};
-use std::time::Instant;
-use RefCell;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, Normal};
 
 use crate::backends::Backend;
-use crate::event::Event;
+use crate::clock::{split_position, Clock, Grid};
+use crate::event::{Control, Event, Priority};
+use crate::metrics::{BackendStatus, Latency, LatencyStats};
+use crate::outlet::{Backpressure, Outlet};
+use crate::outputs::{BackendFailure, Outputs, Restart};
+use crate::recorder::Take;
+use crate::routing::{Route, Router};
+use crate::time::MusicalTime;
+use crate::timing::{shift, sleep_until, SPIN_THRESHOLD};
+
+// how often the timing thread looks at the followed clock again while events
+// at a beat are pending
+const RESOLVE_INTERVAL: Duration = Duration::from_millis(10);
+
+// gaussian timing jitter, seeded so a take can be reproduced
+struct Humanize {
+    rng: StdRng,
+    jitter: Normal<f64>,
+}
+
+// what to do with an event that's already due by the time it's scheduled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LatePolicy {
+    // note-offs are still sent so nothing hangs
+    Drop,
+    PlayNow,
+    // plays it and says how late it was
+    Warn,
+    // moves it (and so its note-off) to the next beat; note-offs and events
+    // scheduled at an instant rather than a beat play right away
+    ClampToNextBeat,
+}
+
+// unique per scheduler, handed out by schedule_at for cancel()
+pub type EventId = u64;
+
+// a look at one event waiting in the queue
+#[derive(Debug, Clone)]
+pub struct PendingEvent {
+    pub id: EventId,
+    pub at: Instant,
+    pub event: Event,
+}
+
+impl fmt::Display for PendingEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let event = &self.event;
+        write!(
+            f,
+            "#{} beat {}:{} {:?}",
+            self.id, event.beat, event.tick, event.payload
+        )?;
+        if let Some(tag) = &event.tag {
+            write!(f, " [{}]", tag)?;
+        }
+        Ok(())
+    }
+}
+
+// when a pending event goes out
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Due {
+    At(Instant),
+    // a beat and tick looked up on the followed clock only as it comes up, so
+    // tempo changes in the meantime still move it; plus its humanize offset
+    // in seconds
+    Tick(u64, u64, f64),
+}
+
+impl Due {
+    // None for a beat without a clock to place it
+    fn instant(&self, clock: Option<&Clock>) -> Option<Instant> {
+        match *self {
+            Due::At(at) => Some(at),
+            Due::Tick(beat, tick, jitter) => {
+                clock.map(|clock| shift(clock.tick_at(beat, tick), jitter))
+            }
+        }
+    }
+}
+
+// heap order: earliest first, then most urgent, then first scheduled
+type Slot = Reverse<(Instant, Reverse<Priority>, EventId)>;
+type TickSlot = Reverse<(u64, u64, Reverse<Priority>, EventId)>;
+
+// events waiting to be sent; cancelling or moving an event only updates
+// `events`, stale heap slots are skipped when they come up
+#[derive(Default)]
+struct Pending {
+    heap: BinaryHeap<Slot>,
+    // events due at a beat, in beat order which no tempo change can upset
+    ticks: BinaryHeap<TickSlot>,
+    events: HashMap<EventId, (Due, Event)>,
+    // beat positions of what was pending at an instant when paused, None
+    // while running
+    frozen: Option<HashMap<EventId, f64>>,
+    // tells the timing thread to quit
+    closed: bool,
+}
+
+impl Pending {
+    fn push(&mut self, due: Due, id: EventId, event: Event) {
+        let priority = Reverse(event.priority);
+        match due {
+            Due::At(at) => self.heap.push(Reverse((at, priority, id))),
+            Due::Tick(beat, tick, _) => self.ticks.push(Reverse((beat, tick, priority, id))),
+        }
+        self.events.insert(id, (due, event));
+    }
+
+    fn is_live(&self, at: Instant, id: EventId) -> bool {
+        self.events
+            .get(&id)
+            .is_some_and(|&(queued, _)| queued == Due::At(at))
+    }
+
+    // when the event in a tick slot is due, None if the slot is stale
+    fn tick_due(&self, beat: u64, tick: u64, id: EventId, clock: &Clock) -> Option<Instant> {
+        match self.events.get(&id) {
+            Some(&(Due::Tick(b, t, jitter), _)) if (b, t) == (beat, tick) => {
+                Some(shift(clock.tick_at(beat, tick), jitter))
+            }
+            _ => None,
+        }
+    }
+
+    // the next instant anything is due, dropping stale slots on the way;
+    // events at a beat only count with a running clock to place them
+    fn next_due(&mut self, clock: Option<&Clock>) -> Option<Instant> {
+        let mut next = None;
+        while let Some(&Reverse((at, _, id))) = self.heap.peek() {
+            if self.is_live(at, id) {
+                next = Some(at);
+                break;
+            }
+            self.heap.pop();
+        }
+        let clock = match clock {
+            Some(clock) => clock,
+            None => return next,
+        };
+        while let Some(&Reverse((beat, tick, _, id))) = self.ticks.peek() {
+            if let Some(at) = self.tick_due(beat, tick, id, clock) {
+                return Some(next.map_or(at, |next: Instant| next.min(at)));
+            }
+            self.ticks.pop();
+        }
+        next
+    }
+
+    // what's due along with when it was meant to go out
+    fn pop_due(&mut self, now: Instant, clock: Option<&Clock>) -> Vec<(Instant, Event)> {
+        let mut due = vec![];
+        while let Some(&Reverse((at, _, id))) = self.heap.peek() {
+            if at > now {
+                break;
+            }
+            self.heap.pop();
+            if self.is_live(at, id) {
+                due.extend(self.events.remove(&id).map(|(_, event)| (at, event)));
+            }
+        }
+        if let Some(clock) = clock {
+            while let Some(&Reverse((beat, tick, _, id))) = self.ticks.peek() {
+                let at = self.tick_due(beat, tick, id, clock);
+                if at.is_some_and(|at| at > now) {
+                    break;
+                }
+                self.ticks.pop();
+                if let Some(at) = at {
+                    due.extend(self.events.remove(&id).map(|(_, event)| (at, event)));
+                }
+            }
+        }
+        // a stable sort keeps heap order among events due together
+        due.sort_by_key(|(at, event)| (*at, Reverse(event.priority)));
+        due
+    }
+
+    fn pop_all(&mut self, clock: Option<&Clock>) -> Vec<Event> {
+        let mut all: Vec<(Option<Instant>, EventId, Event)> = self
+            .drain_with_ids()
+            .into_iter()
+            .map(|(id, (due, event))| (due.instant(clock), id, event))
+            .collect();
+        all.sort_by_key(|(at, id, event)| (*at, Reverse(event.priority), *id));
+        all.into_iter().map(|(_, _, event)| event).collect()
+    }
+
+    fn remove(&mut self, id: EventId) -> Option<Event> {
+        self.events.remove(&id).map(|(_, event)| event)
+    }
+
+    // drops the events `doomed` picks, returning how many
+    fn remove_where<F: Fn(&Event) -> bool>(&mut self, doomed: F) -> usize {
+        let before = self.events.len();
+        self.events.retain(|_, (_, event)| !doomed(event));
+        before - self.events.len()
+    }
+
+    fn move_to(&mut self, id: EventId, due: Due) -> bool {
+        match self.events.remove(&id) {
+            Some((_, event)) => {
+                self.push(due, id, event);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn drain_with_ids(&mut self) -> Vec<(EventId, (Due, Event))> {
+        self.frozen = None;
+        self.heap.clear();
+        self.ticks.clear();
+        self.events.drain().collect()
+    }
+
+    // empties the queue, which also ends a pause since there's nothing to hold
+    fn drain(&mut self) -> Vec<Event> {
+        self.drain_with_ids()
+            .into_iter()
+            .map(|(_, (_, event))| event)
+            .collect()
+    }
+}
+
+// the clock a scheduler places beats on as they come up, if it follows one
+type Followed = Arc<Mutex<Option<Arc<RwLock<Clock>>>>>;
+
+// a copy of the followed clock, so its lock is never held along with the
+// queue's or `followed`'s (whoever schedules holds them the other way round)
+fn snapshot(followed: &Followed) -> Option<Clock> {
+    let clock = followed.lock().unwrap().clone();
+    clock.map(|clock| clock.read().unwrap().clone())
+}
+
+// send-ahead per backend in seconds, negative to hold a backend back
+type Offsets = HashMap<usize, f64>;
+
+// how far ahead of an event the timing thread has to wake up so the backend
+// with the biggest send-ahead still gets it in time
+fn lead(offsets: &Offsets) -> f64 {
+    offsets.values().cloned().fold(0.0, f64::max)
+}
+
+// hands the event to its backends, each outlet holding it until `at` less
+// that backend's offset
+fn deliver(
+    producers: &[Option<Outlet>],
+    router: &Mutex<Router>,
+    offsets: &Offsets,
+    at: Instant,
+    event: &Event,
+) {
+    let routed = router.lock().unwrap().dispatch(event, producers.len());
+    for (backend, event) in routed {
+        // removed backends keep their slot so the indices stay put
+        let outlet = match producers[backend].as_ref() {
+            Some(outlet) => outlet,
+            None => continue,
+        };
+        let due = shift(at, -offsets.get(&backend).cloned().unwrap_or(0.0));
+        for event in event.expand() {
+            outlet.send(due, event);
+        }
+    }
+}
+
+// one timing thread per scheduler: sleeps on the condvar until the earliest
+// pending event is nearly due (or something earlier is queued), spins the
+// rest of the way and sends everything due in heap order. beats are placed
+// on the followed clock every time round, so tempo changes reach them
+fn run(
+    queue: Arc<(Mutex<Pending>, Condvar)>,
+    producers: Arc<Mutex<Vec<Option<Outlet>>>>,
+    router: Arc<Mutex<Router>>,
+    offsets: Arc<Mutex<Offsets>>,
+    followed: Followed,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let (pending, wakeup) = &*queue;
+        loop {
+            // a paused clock can't place anything
+            let clock = snapshot(&followed).filter(|clock| !clock.is_paused());
+            let mut queued = pending.lock().unwrap();
+            if queued.closed {
+                return;
+            }
+            let lead = lead(&offsets.lock().unwrap());
+            let now = Instant::now();
+            let at = match queued.next_due(clock.as_ref()) {
+                Some(at) if queued.frozen.is_none() => Some(shift(at, -lead)),
+                _ => None,
+            };
+            let at = match at {
+                Some(at) if at <= now + SPIN_THRESHOLD => at,
+                _ => {
+                    let mut timeout = at.map(|at| at - now - SPIN_THRESHOLD);
+                    // the tempo may change under events at a beat, or the
+                    // clock resume, without anyone telling the queue
+                    if !queued.ticks.is_empty() {
+                        timeout = Some(
+                            timeout
+                                .map_or(RESOLVE_INTERVAL, |timeout| timeout.min(RESOLVE_INTERVAL)),
+                        );
+                    }
+                    match timeout {
+                        Some(timeout) => drop(wakeup.wait_timeout(queued, timeout).unwrap()),
+                        None => drop(wakeup.wait(queued).unwrap()),
+                    }
+                    continue;
+                }
+            };
+            drop(queued);
+            sleep_until(at);
+            let clock = snapshot(&followed).filter(|clock| !clock.is_paused());
+            let mut queued = pending.lock().unwrap();
+            if queued.frozen.is_some() {
+                continue;
+            }
+            let due = queued.pop_due(shift(Instant::now(), lead), clock.as_ref());
+            drop(queued);
+
+            let producers = producers.lock().unwrap();
+            let offsets = offsets.lock().unwrap().clone();
+            for (at, event) in due.iter() {
+                deliver(&producers, &router, &offsets, *at, event);
+            }
+        }
+    })
+}
 
 pub struct Scheduler {
-    thread_pool: scheduled_thread_pool::ScheduledThreadPool,
-    producers: RefCell<Vec<Sender<Event>>>,
-    backends: RefCell<Vec<Box<dyn Backend>>>,
+    outputs: Outputs,
+    router: Arc<Mutex<Router>>,
+    offsets: Arc<Mutex<Offsets>>,
+    pending: Arc<(Mutex<Pending>, Condvar)>,
+    next_id: AtomicU64,
+    humanize: Mutex<Option<Humanize>>,
+    // rolls the dice for events with a probability
+    rng: Mutex<StdRng>,
+    late_policy: Mutex<LatePolicy>,
+    // what schedule() placed since record(), None when not recording
+    recording: Mutex<Option<Vec<Event>>>,
+    // offline schedulers have no timing thread, drain() sends everything
+    offline: bool,
+    timer: Mutex<Option<JoinHandle<()>>>,
+    followed: Followed,
 }
 
 impl Scheduler {
-    pub fn new(backends: RefCell<Vec<Box<dyn Backend>>>) -> Self {
-        let thread_pool = scheduled_thread_pool::ScheduledThreadPool::new(num_cpus::get());
+    pub fn new(backends: Vec<Box<dyn Backend>>) -> Self {
+        let scheduler = Self::offline(backends);
+        scheduler.start_timer()
+    }
+
+    // a scheduler that never sleeps: events pile up until drain() hands them
+    // to the backends in time order, for rendering faster than real time
+    pub fn offline(backends: Vec<Box<dyn Backend>>) -> Self {
         Self {
-            thread_pool,
-            producers: RefCell::new(vec![]),
-            backends: backends,
+            outputs: Outputs::new(backends),
+            router: Arc::new(Mutex::new(Router::default())),
+            offsets: Arc::new(Mutex::new(HashMap::new())),
+            pending: Arc::new((Mutex::new(Pending::default()), Condvar::new())),
+            next_id: AtomicU64::new(0),
+            humanize: Mutex::new(None),
+            rng: Mutex::new(StdRng::from_entropy()),
+            late_policy: Mutex::new(LatePolicy::PlayNow),
+            recording: Mutex::new(None),
+            offline: true,
+            timer: Mutex::new(None),
+            followed: Arc::new(Mutex::new(None)),
         }
     }
 
+    fn start_timer(mut self) -> Self {
+        self.offline = false;
+        let timer = run(
+            self.pending.clone(),
+            self.outputs.producers.clone(),
+            self.router.clone(),
+            self.offsets.clone(),
+            self.followed.clone(),
+        );
+        self.timer = Mutex::new(Some(timer));
+        self
+    }
+
+    // a scheduler feeding the same backends, with its own pending queue
+    pub fn share(&self) -> Self {
+        let scheduler = Self {
+            outputs: self.outputs.clone(),
+            router: self.router.clone(),
+            offsets: self.offsets.clone(),
+            pending: Arc::new((Mutex::new(Pending::default()), Condvar::new())),
+            next_id: AtomicU64::new(0),
+            humanize: Mutex::new(None),
+            rng: Mutex::new(StdRng::from_entropy()),
+            late_policy: Mutex::new(*self.late_policy.lock().unwrap()),
+            recording: Mutex::new(None),
+            offline: true,
+            timer: Mutex::new(None),
+            followed: Arc::new(Mutex::new(None)),
+        };
+        if self.offline {
+            scheduler
+        } else {
+            scheduler.start_timer()
+        }
+    }
+
+    // keeps events scheduled at a beat on their beat, placing them on `clock`
+    // only as they come up so that set_bpm and tempo ramps move what's
+    // already queued; without it they're fixed to an instant when scheduled
+    pub fn follow(&self, clock: Arc<RwLock<Clock>>) {
+        *self.followed.lock().unwrap() = Some(clock);
+        self.pending.1.notify_one();
+    }
+
+    // shifts every event by a normally distributed offset, `amount` being one
+    // standard deviation
+    pub fn set_humanize(&self, amount: Duration, seed: u64) {
+        *self.humanize.lock().unwrap() = Some(Humanize {
+            rng: StdRng::seed_from_u64(seed),
+            jitter: Normal::new(0.0, amount.as_secs_f64()).unwrap(),
+        });
+    }
+
+    pub fn clear_humanize(&self) {
+        *self.humanize.lock().unwrap() = None;
+    }
+
+    // seconds to shift the next event by
+    fn jitter(&self) -> f64 {
+        match self.humanize.lock().unwrap().as_mut() {
+            Some(humanize) => humanize.jitter.sample(&mut humanize.rng),
+            None => 0.0,
+        }
+    }
+
+    // limits the backend at `backend` (its index in new()) to events tagged
+    // with one of `tags`
+    pub fn subscribe(&self, backend: usize, tags: &[&str]) {
+        let tags = tags.iter().map(|tag| tag.to_string()).collect();
+        self.router.lock().unwrap().subscribe(backend, tags);
+    }
+
+    pub fn unsubscribe(&self, backend: usize) {
+        self.router.lock().unwrap().unsubscribe(backend);
+    }
+
+    // the index of the backend called `name` (see Backend::name: the device
+    // for MIDI, host:port for network outputs), for routing to it when there
+    // are several of a kind:
+    //
+    //   let drums = scheduler.find_backend("TR-8").unwrap();
+    //   scheduler.route("drums", Route::to(drums).channel(9));
+    pub fn find_backend(&self, name: &str) -> Option<usize> {
+        self.outputs.find(name)
+    }
+
+    // each backend's name, indexed like the backends
+    pub fn backend_names(&self) -> Vec<String> {
+        self.outputs.names.lock().unwrap().clone()
+    }
+
+    // how each backend is doing, indexed like the backends, None for removed
+    // ones: connected or not, the last error, events per second over the last
+    // second and how late the last event went out. For a dashboard or a
+    // status line, e.g.
+    //
+    //     for status in scheduler.status().into_iter().flatten() {
+    //         println!("{}", status);
+    //     }
+    pub fn status(&self) -> Vec<Option<BackendStatus>> {
+        self.outputs.statuses()
+    }
+
+    // sends events tagged `tag` along `route`, on top of any earlier routes
+    // for the tag; routed tags skip the subscriptions above
+    pub fn route(&self, tag: &str, route: Route) {
+        self.router.lock().unwrap().route(tag, route);
+    }
+
+    pub fn unroute(&self, tag: &str) {
+        self.router.lock().unwrap().unroute(tag);
+    }
+
+    // makes probability rolls repeatable
+    pub fn set_seed(&self, seed: u64) {
+        *self.rng.lock().unwrap() = StdRng::seed_from_u64(seed);
+    }
+
+    // the default, routes can set their own (see Route::late)
+    pub fn set_late_policy(&self, policy: LatePolicy) {
+        *self.late_policy.lock().unwrap() = policy;
+    }
+
+    fn late_policy(&self, event: &Event) -> LatePolicy {
+        let routed = self.router.lock().unwrap().late_policy(event);
+        routed.unwrap_or_else(|| *self.late_policy.lock().unwrap())
+    }
+
+    // offline schedulers run ahead of the clock so nothing is ever late for them
+    fn is_late(&self, at: Instant) -> bool {
+        !self.offline && at < Instant::now()
+    }
+
+    // false if the event is late and should be dropped
+    fn on_time(&self, at: Instant, event: &Event) -> bool {
+        if !self.is_late(at) {
+            return true;
+        }
+        match self.late_policy(event) {
+            LatePolicy::Drop => event.is_note_off(),
+            LatePolicy::PlayNow | LatePolicy::ClampToNextBeat => true,
+            LatePolicy::Warn => {
+                eprintln!("[scheduler] {:?} late: {:?}", Instant::now() - at, event);
+                true
+            }
+        }
+    }
+
+    fn triggers(&self, probability: f64) -> bool {
+        probability >= 1.0 || self.rng.lock().unwrap().gen::<f64>() < probability
+    }
+
+    // sends to `backend` (its index in new()) that many milliseconds early
+    // to make up for its latency, or late if negative, so that outputs with
+    // different latencies sound together
+    pub fn set_offset(&self, backend: usize, millis: f64) {
+        self.offsets
+            .lock()
+            .unwrap()
+            .insert(backend, millis / 1000.0);
+        // the timing thread may have to wake up earlier now
+        self.pending.1.notify_one();
+    }
+
+    // how many events may wait for each backend and what happens past that,
+    // takes effect right away
+    pub fn set_backpressure(&self, backpressure: Backpressure) {
+        *self.outputs.backpressure.lock().unwrap() = backpressure;
+    }
+
+    // events each backend lost to overflow, indexed like the backends
+    pub fn dropped(&self) -> Vec<u64> {
+        self.outputs.dropped()
+    }
+
+    // brings back backends that panic or otherwise stop receiving, None to
+    // only report them
+    pub fn set_restart(&self, restart: Option<Restart>) {
+        *self.outputs.restart.lock().unwrap() = restart;
+    }
+
+    // starts every backend not running yet
     pub fn start_backends(&self) {
-        for backend in self.backends.borrow_mut().iter_mut() {
-            let (sender, receiver) = channel();
-            self.producers.borrow_mut().push(sender);
-            backend.run(receiver);
+        self.outputs.start_all();
+    }
+
+    // brings a backend online, right away if the others are running already;
+    // returns its index for routing
+    pub fn add_backend(&self, backend: Box<dyn Backend>) -> usize {
+        self.outputs.add(backend)
+    }
+
+    // lets whatever is queued for the backend go out and stops it, events
+    // routed to it being dropped until start_backend(); false if it isn't
+    // running
+    pub fn stop_backend(&self, index: usize) -> bool {
+        self.outputs.stop(index)
+    }
+
+    // starts a backend stop_backend() stopped, once the others are running
+    pub fn start_backend(&self, index: usize) -> bool {
+        self.outputs.resume(index)
+    }
+
+    // every backend failure from here on: failed starts, sends, flushes,
+    // polls and stops. Without a subscriber they're printed
+    pub fn backend_errors(&self) -> Receiver<BackendFailure> {
+        self.outputs.failures()
+    }
+
+    // lets whatever is queued for the backend go out, disconnects it and
+    // waits for it to wrap up; false if there's no backend at `index`.
+    // other backends keep their indices
+    pub fn remove_backend(&self, index: usize) -> bool {
+        if !self.outputs.remove(index) {
+            return false;
         }
+        self.router.lock().unwrap().unsubscribe(index);
+        self.offsets.lock().unwrap().remove(&index);
+        true
     }
 
-    pub fn schedule_at(&self, at: Instant, event: Event) {
-        for producer in self.producers.borrow().iter() {
-            let sender = producer.clone();
-            let delay = at - Instant::now();
-            let evt = event.clone();
-            self.thread_pool.execute_after(delay, move || {
-                sender.send(evt).unwrap();
+    // how late events reached each backend against when they were due there
+    // (offsets included), indexed like the backends
+    pub fn latency(&self) -> Vec<LatencyStats> {
+        self.outputs
+            .latency
+            .lock()
+            .unwrap()
+            .iter()
+            .map(Latency::stats)
+            .collect()
+    }
+
+    pub fn reset_latency(&self) {
+        for latency in self.outputs.latency.lock().unwrap().iter_mut() {
+            *latency = Latency::default();
+        }
+    }
+
+    pub fn schedule_at(&self, at: Instant, event: Event) -> EventId {
+        if !self.triggers(event.probability) {
+            return self.next_id.fetch_add(1, Ordering::SeqCst);
+        }
+        self.push(vec![(at, event)], false)[0]
+    }
+
+    // for events that should go out the moment they arrive, like MIDI thru:
+    // due as they're queued, so the late policy never drops them, and still
+    // routed like the rest
+    pub fn schedule_now(&self, event: Event) -> EventId {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        if !self.triggers(event.probability) {
+            return id;
+        }
+        let (pending, wakeup) = &*self.pending;
+        pending
+            .lock()
+            .unwrap()
+            .push(Due::At(Instant::now()), id, event);
+        wakeup.notify_one();
+        id
+    }
+
+    // queues everything under one lock with a single wakeup, handing back
+    // an id per event (events dropped as late still use one up); `at_beat`
+    // keeps them on their beat and tick if a clock is followed
+    fn push(&self, placed: Vec<(Instant, Event)>, at_beat: bool) -> Vec<EventId> {
+        let at_beat = at_beat && self.followed.lock().unwrap().is_some();
+        let (pending, wakeup) = &*self.pending;
+        let mut pending = pending.lock().unwrap();
+        let ids = placed
+            .into_iter()
+            .map(|(at, event)| {
+                let jitter = self.jitter();
+                let at = shift(at, jitter);
+                let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+                if self.on_time(at, &event) {
+                    let due = if at_beat {
+                        Due::Tick(event.beat, event.tick, jitter)
+                    } else {
+                        Due::At(at)
+                    };
+                    pending.push(due, id, event);
+                }
+                id
+            })
+            .collect();
+        // it may be due before whatever the timing thread is waiting for
+        wakeup.notify_one();
+        ids
+    }
+
+    // the event at its beat and tick followed by its note-off, or a ratchet's
+    // retriggers with theirs; nothing if the probability roll says no
+    fn place(&self, clock: &Clock, mut event: Event) -> Vec<(Instant, Event)> {
+        if event.repeats > 1 {
+            return event
+                .retriggers(clock.ppqn())
+                .into_iter()
+                .flat_map(|retrigger| self.place(clock, retrigger))
+                .collect();
+        }
+        // one roll for the note and its note-off together
+        if !self.triggers(event.probability) {
+            return vec![];
+        }
+        event.probability = 1.0;
+        let mut at = clock.tick_at(event.beat, event.tick);
+        if self.is_late(at)
+            && !event.is_note_off()
+            && self.late_policy(&event) == LatePolicy::ClampToNextBeat
+        {
+            event.beat = clock.next_beat();
+            event.tick = 0;
+            at = clock.tick_at(event.beat, event.tick);
+        }
+        if let Some(take) = self.recording.lock().unwrap().as_mut() {
+            take.push(event.clone());
+        }
+        let note_off = event.note_off(clock.ppqn());
+        let mut placed = vec![(at, event)];
+        if let Some(note_off) = note_off {
+            placed.push((clock.tick_at(note_off.beat, note_off.tick), note_off));
+        }
+        placed
+    }
+
+    // schedules the event at its beat and tick, along with its note-off;
+    // the id returned is the event's own (the first one's for a ratchet)
+    pub fn schedule(&self, clock: &Clock, event: Event) -> EventId {
+        self.schedule_batch(clock, vec![event])[0]
+    }
+
+    // schedules a whole pattern at once, each event at its own beat and
+    // tick, with an id per event as schedule() would return
+    pub fn schedule_batch(&self, clock: &Clock, events: Vec<Event>) -> Vec<EventId> {
+        let mut placed = vec![];
+        // where each event's first placement starts, None if it didn't trigger
+        let mut firsts = vec![];
+        for event in events {
+            let start = placed.len();
+            placed.extend(self.place(clock, event));
+            firsts.push(if placed.len() > start {
+                Some(start)
+            } else {
+                None
             });
         }
+        let ids = self.push(placed, true);
+        firsts
+            .into_iter()
+            .map(|first| match first {
+                Some(i) => ids[i],
+                None => self.next_id.fetch_add(1, Ordering::SeqCst),
+            })
+            .collect()
+    }
+
+    pub fn schedule_at_time(&self, clock: &Clock, time: MusicalTime, mut event: Event) -> EventId {
+        let (beat, tick) = clock.beat_tick(time);
+        event.beat = beat;
+        event.tick = tick;
+        self.schedule(clock, event)
+    }
+
+    // schedules at a beat counted within a bar, both from 1 like bar.beat
+    // times (beat 2.5 being the offbeat of the second beat), so placement
+    // follows the meter map instead of an absolute beat count
+    pub fn schedule_at_bar(
+        &self,
+        clock: &Clock,
+        bar: u64,
+        beat_in_bar: f64,
+        mut event: Event,
+    ) -> EventId {
+        let position =
+            clock.bars_position(bar.saturating_sub(1) as f64) + (beat_in_bar - 1.0).max(0.0);
+        let (beat, tick) = split_position(position, clock.ppqn());
+        event.beat = beat;
+        event.tick = tick;
+        self.schedule(clock, event)
+    }
+
+    // snaps `at` to the grid first, so events from outside the clock (live
+    // input, OSC) still land in time; a line already behind the playhead
+    // gives way to the next one
+    pub fn schedule_quantized(
+        &self,
+        clock: &Clock,
+        at: Instant,
+        grid: Grid,
+        mut event: Event,
+    ) -> EventId {
+        let position = clock.position_of(at);
+        let mut snapped = clock.quantize(position, grid);
+        if snapped < clock.position() {
+            snapped = clock.quantize_next(position, grid);
+        }
+        let (beat, tick) = split_position(snapped, clock.ppqn());
+        event.beat = beat;
+        event.tick = tick;
+        self.schedule(clock, event)
+    }
+
+    // starts capturing what schedule() places, dropping any unfinished take
+    pub fn record(&self) {
+        *self.recording.lock().unwrap() = Some(vec![]);
+    }
+
+    pub fn stop_recording(&self) -> Take {
+        let events = self.recording.lock().unwrap().take().unwrap_or_default();
+        Take::new(events)
+    }
+
+    // schedules a take at the beats it was recorded at, see Take::shifted
+    // for playing it elsewhere
+    pub fn replay(&self, clock: &Clock, take: &Take) -> Vec<EventId> {
+        take.events
+            .iter()
+            .map(|event| self.schedule(clock, event.clone()))
+            .collect()
+    }
+
+    // drops an event that hasn't been sent yet, false if it's gone already;
+    // a cancelled note's note-off still goes out, which is harmless
+    pub fn cancel(&self, id: EventId) -> bool {
+        self.pending.0.lock().unwrap().remove(id).is_some()
+    }
+
+    // drops every pending event with the tag, except note-offs so that
+    // notes already sounding still end; returns how many were dropped
+    pub fn cancel_tag(&self, tag: &str) -> usize {
+        self.pending.0.lock().unwrap().remove_where(|event| {
+            event.tag.as_ref().is_some_and(|t| t == tag) && !event.is_note_off()
+        })
+    }
+
+    // drops every pending event at or past `beat`, again sparing note-offs
+    pub fn cancel_from(&self, beat: u64) -> usize {
+        self.pending
+            .0
+            .lock()
+            .unwrap()
+            .remove_where(|event| event.beat >= beat && !event.is_note_off())
+    }
+
+    // moves a pending event to another instant, false if it's gone already
+    pub fn reschedule(&self, id: EventId, at: Instant) -> bool {
+        let (pending, wakeup) = &*self.pending;
+        let moved = pending.lock().unwrap().move_to(id, Due::At(at));
+        wakeup.notify_one();
+        moved
+    }
+
+    // moves a pending event to another beat and tick
+    pub fn reschedule_beat(&self, clock: &Clock, id: EventId, beat: u64, tick: u64) -> bool {
+        let due = if self.followed.lock().unwrap().is_some() {
+            Due::Tick(beat, tick, 0.0)
+        } else {
+            Due::At(clock.tick_at(beat, tick))
+        };
+        let (pending, wakeup) = &*self.pending;
+        let mut pending = pending.lock().unwrap();
+        let moved = match pending.events.get_mut(&id) {
+            Some((_, event)) => {
+                event.beat = beat;
+                event.tick = tick;
+                pending.move_to(id, due)
+            }
+            None => false,
+        };
+        wakeup.notify_one();
+        moved
+    }
+
+    // recomputes when every event pending at an instant is due from its beat
+    // and tick, after the clock jumped or changed tempo under them; events
+    // kept on their beat (see follow()) move with the clock anyway
+    pub fn retime(&self, clock: &Clock) {
+        let (pending, wakeup) = &*self.pending;
+        let mut pending = pending.lock().unwrap();
+        let retimed: Vec<(EventId, Instant)> = pending
+            .events
+            .iter()
+            .filter(|(_, (due, _))| matches!(due, Due::At(_)))
+            .map(|(&id, (_, event))| (id, clock.tick_at(event.beat, event.tick)))
+            .collect();
+        pending.heap.clear();
+        for (id, at) in retimed {
+            pending.move_to(id, Due::At(at));
+        }
+        wakeup.notify_one();
+    }
+
+    // stops sending until resume(), remembering where on the beat timeline
+    // everything pending at an instant falls; call it before pausing the clock
+    pub fn pause(&self, clock: &Clock) {
+        let (pending, wakeup) = &*self.pending;
+        let mut pending = pending.lock().unwrap();
+        if pending.frozen.is_some() {
+            return;
+        }
+        let frozen = pending
+            .events
+            .iter()
+            .filter_map(|(&id, &(due, _))| match due {
+                Due::At(at) => Some((id, clock.position_of(at))),
+                Due::Tick(..) => None,
+            })
+            .collect();
+        pending.frozen = Some(frozen);
+        wakeup.notify_one();
+    }
+
+    // carries on from where pause() left off, after the clock resumed;
+    // events scheduled in between keep their instants
+    pub fn resume(&self, clock: &Clock) {
+        let (pending, wakeup) = &*self.pending;
+        let mut pending = pending.lock().unwrap();
+        if let Some(frozen) = pending.frozen.take() {
+            for (id, position) in frozen {
+                pending.move_to(id, Due::At(clock.instant_at(position)));
+            }
+        }
+        wakeup.notify_one();
+    }
+
+    // sends everything collected by an offline scheduler, earliest first
+    pub fn drain(&self) {
+        if !self.offline {
+            return;
+        }
+        let clock = snapshot(&self.followed);
+        let rendered = self.pending.0.lock().unwrap().pop_all(clock.as_ref());
+        let producers = self.outputs.producers.lock().unwrap();
+        // no time to compensate for when rendering
+        let now = Instant::now();
+        for event in rendered.iter() {
+            deliver(&producers, &self.router, &Offsets::new(), now, event);
+        }
+    }
+
+    // disconnects the backends once their queues ran dry and waits for them
+    // to wrap up
+    pub fn close(&self) {
+        self.outputs.close();
+    }
+
+    // what's waiting to be sent, earliest first
+    pub fn pending(&self) -> Vec<PendingEvent> {
+        let clock = snapshot(&self.followed);
+        let pending = self.pending.0.lock().unwrap();
+        let mut listed: Vec<PendingEvent> = pending
+            .events
+            .iter()
+            .filter_map(|(&id, (due, event))| {
+                due.instant(clock.as_ref()).map(|at| PendingEvent {
+                    id,
+                    at,
+                    event: event.clone(),
+                })
+            })
+            .collect();
+        listed.sort_by_key(|pending| (pending.at, Reverse(pending.event.priority), pending.id));
+        listed
+    }
+
+    // how many events are waiting, without copying them
+    pub fn queue_depth(&self) -> usize {
+        self.pending.0.lock().unwrap().events.len()
+    }
+
+    // cancels everything not yet dispatched and hands it back, ordered by beat
+    pub fn hold(&self) -> Vec<Event> {
+        let mut held = self.pending.0.lock().unwrap().drain();
+        held.sort_by_key(|event| (event.beat, event.tick, Reverse(event.priority)));
+        held
+    }
+
+    pub fn flush(&self) {
+        self.hold();
+    }
+
+    // drops everything pending but sends the note-offs among it right away,
+    // so that stopping doesn't leave notes hanging
+    pub fn release(&self) {
+        let releases: Vec<Event> = self
+            .hold()
+            .into_iter()
+            .filter(|event| event.is_note_off())
+            .collect();
+        let producers = self.outputs.producers.lock().unwrap();
+        let now = Instant::now();
+        for event in releases.iter() {
+            deliver(&producers, &self.router, &Offsets::new(), now, event);
+        }
+    }
+
+    // for when something hangs: every backend gets a Control::Panic right
+    // away, whatever its routes and subscriptions. MIDI backends send
+    // note-offs for what they know is sounding and All Sound Off/All Notes
+    // Off on every channel. Nothing scheduled is cancelled, playback carries on
+    pub fn panic(&self) {
+        let event = Event::new(Control::Panic, 0).with_priority(Priority::High);
+        let producers = self.outputs.producers.lock().unwrap();
+        let now = Instant::now();
+        for outlet in producers.iter().flatten() {
+            outlet.send(now, event.clone());
+        }
+    }
+
+    // releases what's pending, stops the timing thread and closes the
+    // backends; nothing can be scheduled afterwards
+    pub fn shutdown(&self) {
+        let (pending, wakeup) = &*self.pending;
+        pending.lock().unwrap().closed = true;
+        wakeup.notify_all();
+        if let Some(timer) = self.timer.lock().unwrap().take() {
+            timer.join().unwrap();
+        }
+        self.release();
+        self.close();
     }
 }
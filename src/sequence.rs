@@ -0,0 +1,186 @@
+use std::sync::{Arc, Mutex};
+
+use crate::clock::DEFAULT_PPQN;
+use crate::event::{Event, Message, DEFAULT_VELOCITY};
+use crate::generator::Generator;
+use crate::pitch::Pitch;
+
+// a hair under a step, so steps that don't divide a beat evenly land where
+// they should
+const EPSILON: f64 = 1e-9;
+
+// what one step of a sequence plays
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Step {
+    pub note: u8,
+    pub velocity: u8,
+    // how much of the step the note lasts, 1.0 ties into the next one
+    pub gate: f64,
+    // chance of it playing at all each time round, 0.0-1.0
+    pub probability: f64,
+}
+
+impl Step {
+    pub fn new<P: Into<Pitch>>(note: P) -> Self {
+        Self {
+            note: note.into().0,
+            velocity: DEFAULT_VELOCITY,
+            gate: 0.5,
+            probability: 1.0,
+        }
+    }
+
+    pub fn with_velocity(mut self, velocity: u8) -> Self {
+        self.velocity = velocity;
+        self
+    }
+
+    pub fn with_gate(mut self, gate: f64) -> Self {
+        self.gate = gate.max(0.0);
+        self
+    }
+
+    pub fn with_probability(mut self, probability: f64) -> Self {
+        self.probability = probability.clamp(0.0, 1.0);
+        self
+    }
+}
+
+// a bar of steps, x0x style: 16 steps over 4 beats are sixteenths, and each
+// step plays its note or rests. Clones share the steps, so one goes to an
+// Engine and the rest edit it while it plays, taking effect from the next beat
+#[derive(Debug, Clone)]
+pub struct StepSequence {
+    steps: Arc<Mutex<Vec<Option<Step>>>>,
+    // beats per bar
+    pub beats: u64,
+    // MIDI channel, 0-15
+    pub channel: u8,
+    pub tag: Option<String>,
+    // the clock's, for placing steps between beats
+    pub ppqn: u64,
+}
+
+impl StepSequence {
+    // `steps` rests over a bar of 4 beats
+    pub fn new(steps: usize) -> Self {
+        Self {
+            steps: Arc::new(Mutex::new(vec![None; steps])),
+            beats: 4,
+            channel: 0,
+            tag: None,
+            ppqn: DEFAULT_PPQN,
+        }
+    }
+
+    // a step of `note` for each "x" in `hits`, rests for anything else, e.g.
+    // "x...x...x...x..." for four on the floor
+    pub fn from_hits<P: Into<Pitch>>(hits: &str, note: P) -> Self {
+        let step = Step::new(note);
+        let steps = hits
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| if c == 'x' { Some(step) } else { None })
+            .collect();
+        Self {
+            steps: Arc::new(Mutex::new(steps)),
+            ..Self::new(0)
+        }
+    }
+
+    pub fn with_beats(mut self, beats: u64) -> Self {
+        self.beats = beats;
+        self
+    }
+
+    pub fn with_channel(mut self, channel: u8) -> Self {
+        self.channel = channel & 0x0F;
+        self
+    }
+
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tag = Some(tag.to_string());
+        self
+    }
+
+    pub fn with_ppqn(mut self, ppqn: u64) -> Self {
+        self.ppqn = ppqn;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // None for a rest or a step past the end
+    pub fn get(&self, index: usize) -> Option<Step> {
+        self.steps.lock().unwrap().get(index).copied().flatten()
+    }
+
+    // does nothing past the end
+    pub fn set(&self, index: usize, step: Step) {
+        if let Some(slot) = self.steps.lock().unwrap().get_mut(index) {
+            *slot = Some(step);
+        }
+    }
+
+    pub fn clear(&self, index: usize) {
+        if let Some(slot) = self.steps.lock().unwrap().get_mut(index) {
+            *slot = None;
+        }
+    }
+
+    // changes a step in place, e.g. to nudge its velocity; rests stay rests
+    pub fn update<F: FnOnce(&mut Step)>(&self, index: usize, change: F) {
+        if let Some(Some(step)) = self.steps.lock().unwrap().get_mut(index) {
+            change(step);
+        }
+    }
+
+    // all the steps at once, e.g. to load another pattern between beats
+    pub fn replace(&self, steps: Vec<Option<Step>>) {
+        *self.steps.lock().unwrap() = steps;
+    }
+
+    // more steps are rests, fewer drops them from the end
+    pub fn resize(&self, steps: usize) {
+        self.steps.lock().unwrap().resize(steps, None);
+    }
+
+    pub fn steps(&self) -> Vec<Option<Step>> {
+        self.steps.lock().unwrap().clone()
+    }
+}
+
+impl Generator for StepSequence {
+    fn generate(&mut self, beat: u64) -> Vec<Event> {
+        let steps = self.steps();
+        if steps.is_empty() || self.beats == 0 {
+            return vec![];
+        }
+        let length = self.beats as f64 / steps.len() as f64;
+        let first = (beat as f64 / length - EPSILON).ceil() as u64;
+        let end = ((beat + 1) as f64 / length - EPSILON).ceil() as u64;
+        (first..end)
+            .filter_map(|index| Some((index, steps[index as usize % steps.len()]?)))
+            .map(|(index, step)| {
+                let message = Message::NoteOn {
+                    note: step.note,
+                    velocity: step.velocity,
+                };
+                let event = Event::at_position(message, index as f64 * length, self.ppqn)
+                    .on_channel(self.channel)
+                    .with_duration(step.gate * length)
+                    .with_probability(step.probability);
+                match self.tag.as_ref() {
+                    Some(tag) => event.tagged(tag),
+                    None => event,
+                }
+            })
+            .collect()
+    }
+}
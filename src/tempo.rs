@@ -0,0 +1,204 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TempoPoint {
+    pub beat: f64,
+    pub bpm: f64,
+    // glide linearly to this bpm by the next point instead of holding `bpm`
+    pub ramp_to: Option<f64>,
+}
+
+// a tempo the map can work with: NaN, infinite, zero or negative ones would
+// turn every position after them into nonsense
+pub fn valid_bpm(bpm: f64) -> bool {
+    bpm.is_finite() && bpm > 0.0
+}
+
+// tempo changes over the beat timeline, beat positions map to seconds through it
+#[derive(Debug, Clone)]
+pub struct TempoMap {
+    points: Vec<TempoPoint>,
+}
+
+impl TempoMap {
+    // panics on a tempo valid_bpm() turns down
+    pub fn new(bpm: f64) -> Self {
+        assert!(valid_bpm(bpm), "invalid tempo: {} bpm", bpm);
+        Self {
+            points: vec![TempoPoint {
+                beat: 0.0,
+                bpm,
+                ramp_to: None,
+            }],
+        }
+    }
+
+    // one point per line: `<beat> <bpm> [ramp]`, '#' starts a comment,
+    // `ramp` glides from this point to the next one's bpm
+    //
+    //   0  100
+    //   64 100 ramp
+    //   80 140
+    pub fn parse(source: &str) -> io::Result<Self> {
+        let invalid = |line: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("bad tempo map line: {}", line),
+            )
+        };
+
+        let mut points: Vec<(TempoPoint, bool)> = vec![];
+        for line in source.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (beat, bpm, ramp) = match fields.as_slice() {
+                [beat, bpm] => (beat, bpm, false),
+                [beat, bpm, "ramp"] => (beat, bpm, true),
+                _ => return Err(invalid(line)),
+            };
+            let beat: f64 = beat.parse().map_err(|_| invalid(line))?;
+            let bpm: f64 = bpm.parse().map_err(|_| invalid(line))?;
+            if !beat.is_finite() || !valid_bpm(bpm) {
+                return Err(invalid(line));
+            }
+            let point = TempoPoint {
+                beat,
+                bpm,
+                ramp_to: None,
+            };
+            points.push((point, ramp));
+        }
+        if points.is_empty() {
+            return Err(invalid("<empty>"));
+        }
+
+        points.sort_by(|a, b| a.0.beat.total_cmp(&b.0.beat));
+        let mut map = TempoMap::new(points[0].0.bpm);
+        for i in 0..points.len() {
+            let (mut point, ramp) = points[i];
+            if ramp {
+                point.ramp_to = points.get(i + 1).map(|next| next.0.bpm);
+            }
+            map.insert(point);
+        }
+        Ok(map)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn points(&self) -> &[TempoPoint] {
+        &self.points
+    }
+
+    // adds a point, replacing one already at the same beat; one with a beat
+    // that isn't finite or a tempo valid_bpm() turns down is ignored
+    pub fn insert(&mut self, point: TempoPoint) {
+        if !point.beat.is_finite() || !valid_bpm(point.bpm) || !point.ramp_to.is_none_or(valid_bpm)
+        {
+            return;
+        }
+        self.points.retain(|p| p.beat != point.beat);
+        let index = self
+            .points
+            .iter()
+            .take_while(|p| p.beat < point.beat)
+            .count();
+        self.points.insert(index, point);
+    }
+
+    // drops every change from `beat` on, holding the tempo reached there,
+    // so new ones can be written over them without touching earlier timing
+    pub fn truncate(&mut self, beat: f64) {
+        let bpm = self.bpm_at(beat);
+        self.points.retain(|p| p.beat < beat || p.beat == 0.0);
+        if let Some(last) = self.points.last_mut() {
+            if last.ramp_to.is_some() && last.beat < beat {
+                // a ramp cut short ends at the tempo it has reached
+                last.ramp_to = Some(bpm);
+            }
+        }
+        if self.points.last().is_some_and(|last| last.beat < beat) {
+            self.insert(TempoPoint {
+                beat,
+                bpm,
+                ramp_to: None,
+            });
+        }
+    }
+
+    fn segment(&self, position: f64) -> usize {
+        self.points
+            .iter()
+            .rposition(|p| p.beat <= position)
+            .unwrap_or(0)
+    }
+
+    // bpm change per beat inside segment `i`, zero when it doesn't ramp
+    fn slope(&self, i: usize) -> f64 {
+        let point = self.points[i];
+        match (self.points.get(i + 1), point.ramp_to) {
+            (Some(next), Some(to)) => (to - point.bpm) / (next.beat - point.beat),
+            _ => 0.0,
+        }
+    }
+
+    // seconds needed to move `beats` into segment `i`
+    fn segment_secs(&self, i: usize, beats: f64) -> f64 {
+        let bpm = self.points[i].bpm;
+        let k = self.slope(i);
+        if k == 0.0 || beats <= 0.0 {
+            return beats * 60.0 / bpm;
+        }
+        60.0 / k * ((bpm + k * beats) / bpm).ln()
+    }
+
+    // inverse of segment_secs
+    fn segment_beats(&self, i: usize, secs: f64) -> f64 {
+        let bpm = self.points[i].bpm;
+        let k = self.slope(i);
+        if k == 0.0 || secs <= 0.0 {
+            return secs * bpm / 60.0;
+        }
+        bpm * ((k * secs / 60.0).exp() - 1.0) / k
+    }
+
+    pub fn bpm_at(&self, position: f64) -> f64 {
+        let i = self.segment(position);
+        let point = self.points[i];
+        let into = (position - point.beat).max(0.0);
+        point.bpm + self.slope(i) * into
+    }
+
+    pub fn secs_at(&self, position: f64) -> f64 {
+        let mut secs = 0.0;
+        for i in 0..self.segment(position) {
+            secs += self.segment_secs(i, self.points[i + 1].beat - self.points[i].beat);
+        }
+        let i = self.segment(position);
+        secs + self.segment_secs(i, position - self.points[i].beat)
+    }
+
+    pub fn position_at(&self, secs: f64) -> f64 {
+        let mut start = 0.0;
+        for (i, point) in self.points.iter().enumerate() {
+            match self.points.get(i + 1) {
+                Some(next) => {
+                    let length = self.segment_secs(i, next.beat - point.beat);
+                    if secs < start + length {
+                        return point.beat + self.segment_beats(i, secs - start);
+                    }
+                    start += length;
+                }
+                None => return point.beat + self.segment_beats(i, secs - start),
+            }
+        }
+        0.0
+    }
+}
@@ -0,0 +1,28 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+// position in bars, beats and ticks, written like a DAW does: "17.3.0"
+// bars and beats count from 1, ticks from 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct MusicalTime {
+    pub bar: u64,
+    pub beat: u64,
+    pub tick: u64,
+}
+
+impl MusicalTime {
+    pub fn new(bar: u64, beat: u64, tick: u64) -> Self {
+        Self { bar, beat, tick }
+    }
+
+    pub fn bar(bar: u64) -> Self {
+        Self::new(bar, 1, 0)
+    }
+}
+
+impl fmt::Display for MusicalTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.bar, self.beat, self.tick)
+    }
+}
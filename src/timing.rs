@@ -0,0 +1,30 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+// OS sleeps routinely overshoot by a millisecond or so, the rest is spun
+pub const SPIN_THRESHOLD: Duration = Duration::from_millis(1);
+
+// `at` moved by a signed number of seconds
+pub fn shift(at: Instant, secs: f64) -> Instant {
+    let offset = Duration::from_secs_f64(secs.abs());
+    if secs < 0.0 {
+        at - offset
+    } else {
+        at + offset
+    }
+}
+
+// waits until an absolute instant, so repeated waits don't accumulate drift
+pub fn sleep_until(target: Instant) {
+    let now = Instant::now();
+    if target <= now {
+        return;
+    }
+    let remaining = target - now;
+    if remaining > SPIN_THRESHOLD {
+        thread::sleep(remaining - SPIN_THRESHOLD);
+    }
+    while Instant::now() < target {
+        std::hint::spin_loop();
+    }
+}
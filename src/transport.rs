@@ -0,0 +1,396 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{self, JoinHandle};
+
+use crate::clock::Clock;
+use crate::clock_service::{ClockService, Tick};
+use crate::event::{Event, Message};
+use crate::recorder::Take;
+use crate::scheduler::Scheduler;
+
+// beats each count-in click lasts
+const CLICK_LENGTH: f64 = 0.25;
+
+// clicks played before the first beat so players can catch the tempo
+#[derive(Debug, Clone)]
+pub struct CountIn {
+    pub bars: u64,
+    pub accent: u8,
+    pub click: u8,
+}
+
+impl CountIn {
+    // GM hi/low wood block
+    pub fn bars(bars: u64) -> Self {
+        Self {
+            bars,
+            accent: 76,
+            click: 77,
+        }
+    }
+}
+
+// what schedule_every() plays: the same event from its own beat on, or
+// whatever a closure makes for each beat it's called on
+pub enum Repeat {
+    Event(Event),
+    With(Box<dyn Fn(u64) -> Vec<Event> + Send>),
+}
+
+impl From<Event> for Repeat {
+    fn from(event: Event) -> Self {
+        Repeat::Event(event)
+    }
+}
+
+impl<F: Fn(u64) -> Vec<Event> + Send + 'static> From<F> for Repeat {
+    fn from(make: F) -> Self {
+        Repeat::With(Box::new(make))
+    }
+}
+
+impl Repeat {
+    fn events_at(&self, beat: u64, every: u64) -> Vec<Event> {
+        match self {
+            Repeat::Event(event)
+                if beat >= event.beat && (beat - event.beat).is_multiple_of(every) =>
+            {
+                let mut event = event.clone();
+                event.beat = beat;
+                vec![event]
+            }
+            Repeat::Event(_) => vec![],
+            Repeat::With(make) if beat.is_multiple_of(every) => make(beat),
+            Repeat::With(_) => vec![],
+        }
+    }
+}
+
+// stops a schedule_every() loop, from the next beat on
+#[derive(Clone)]
+pub struct Every {
+    running: Arc<AtomicBool>,
+}
+
+impl Every {
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    Stopped,
+    Playing,
+    Paused,
+}
+
+struct Inner {
+    clock: ClockService,
+    scheduler: Scheduler,
+    state: Mutex<State>,
+    held: Mutex<Vec<Event>>,
+    // how many beats ahead events are handed to the scheduler, None for
+    // as soon as they come in
+    lookahead: Mutex<Option<f64>>,
+    // events past the lookahead window, waiting for it to reach them
+    upcoming: Mutex<Vec<Event>>,
+    count_in: Mutex<Option<CountIn>>,
+    // threads feeding the transport, joined on shutdown
+    workers: Mutex<Vec<JoinHandle<()>>>,
+}
+
+#[derive(Clone)]
+pub struct Transport {
+    inner: Arc<Inner>,
+}
+
+impl Transport {
+    pub fn new(clock: ClockService, scheduler: Scheduler) -> Self {
+        clock.clock().write().unwrap().stop();
+        // queued events stay on their beats through tempo changes
+        scheduler.follow(clock.clock().clone());
+
+        let transport = Self {
+            inner: Arc::new(Inner {
+                clock,
+                scheduler,
+                state: Mutex::new(State::Stopped),
+                held: Mutex::new(vec![]),
+                lookahead: Mutex::new(None),
+                upcoming: Mutex::new(vec![]),
+                count_in: Mutex::new(None),
+                workers: Mutex::new(vec![]),
+            }),
+        };
+        transport.run_lookahead();
+        transport
+    }
+
+    // moves events into the scheduler as the window reaches them, on every beat
+    fn run_lookahead(&self) {
+        let ticks = self.subscribe();
+        let transport = self.clone();
+        let worker = thread::spawn(move || {
+            for tick in ticks {
+                if let Tick::BeatTick(_) = tick {
+                    transport.advance();
+                }
+            }
+        });
+        self.attach(worker);
+    }
+
+    // another transport on its own clock (e.g. at a different tempo) that plays
+    // through the same backends, for polytempo layers
+    pub fn with_clock(&self, clock: ClockService) -> Self {
+        Self::new(clock, self.inner.scheduler.share())
+    }
+
+    pub fn clock(&self) -> &Arc<RwLock<Clock>> {
+        self.inner.clock.clock()
+    }
+
+    pub fn scheduler(&self) -> &Scheduler {
+        &self.inner.scheduler
+    }
+
+    // beat and bar ticks, only delivered while playing
+    pub fn subscribe(&self) -> Receiver<Tick> {
+        self.inner.clock.subscribe()
+    }
+
+    pub fn state(&self) -> State {
+        *self.inner.state.lock().unwrap()
+    }
+
+    // only events within `beats` of the playhead are scheduled, the rest wait
+    // so that later tempo changes or cancels still reach them; the window
+    // moves a beat at a time so anything under a beat is stretched to one
+    pub fn set_lookahead(&self, beats: Option<f64>) {
+        *self.inner.lookahead.lock().unwrap() = beats.map(|beats| beats.max(1.0));
+        if beats.is_none() {
+            let upcoming: Vec<Event> = self.inner.upcoming.lock().unwrap().drain(..).collect();
+            self.schedule_batch(upcoming);
+        }
+    }
+
+    pub fn set_count_in(&self, count_in: Option<CountIn>) {
+        *self.inner.count_in.lock().unwrap() = count_in;
+    }
+
+    // plays from the top, dropping anything left from a previous run but
+    // releasing the notes it left sounding
+    pub fn start(&self) {
+        self.inner.scheduler.release();
+        self.inner.held.lock().unwrap().clear();
+        self.inner.upcoming.lock().unwrap().clear();
+        let count_in = self.inner.count_in.lock().unwrap().clone();
+        self.inner.clock.rewind(|clock| match count_in {
+            Some(count_in) => self.count_in(clock, count_in),
+            None => clock.start_at(0),
+        });
+        self.set_state(State::Playing);
+    }
+
+    fn count_in(&self, clock: &mut Clock, count_in: CountIn) {
+        let bar = clock.meter_at(1).length();
+        let beats = count_in.bars as f64 * bar;
+        clock.start_from(-beats);
+
+        let mut position = -beats;
+        while position < 0.0 {
+            let accent = ((position + beats) % bar).abs() < f64::EPSILON;
+            let note = if accent {
+                count_in.accent
+            } else {
+                count_in.click
+            };
+            let event = Event::new(Message::note(note), 0).with_duration(CLICK_LENGTH);
+            // before beat 0 there's no beat to place it on, so its note-off
+            // goes by the clock as well
+            if let Some(note_off) = event.note_off(clock.ppqn()) {
+                let end = clock.instant_at(position + CLICK_LENGTH);
+                self.inner.scheduler.schedule_at(end, note_off);
+            }
+            let at = clock.instant_at(position);
+            self.inner.scheduler.schedule_at(at, event);
+            position += 1.0;
+        }
+    }
+
+    pub fn stop(&self) {
+        self.set_state(State::Stopped);
+        self.inner.scheduler.release();
+        self.inner.held.lock().unwrap().clear();
+        self.inner.upcoming.lock().unwrap().clear();
+        self.inner.clock.rewind(|clock| clock.stop());
+    }
+
+    pub fn pause(&self) {
+        if self.state() != State::Playing {
+            return;
+        }
+        self.set_state(State::Paused);
+        let mut clock = self.clock().write().unwrap();
+        self.inner.scheduler.pause(&clock);
+        clock.pause();
+    }
+
+    pub fn continue_(&self) {
+        if self.state() != State::Paused {
+            return;
+        }
+        {
+            let mut clock = self.clock().write().unwrap();
+            clock.resume();
+            self.inner.scheduler.resume(&clock);
+        }
+        let held: Vec<Event> = self.inner.held.lock().unwrap().drain(..).collect();
+        self.dispatch(held);
+        self.set_state(State::Playing);
+    }
+
+    // silences hanging notes without stopping (see Scheduler::panic)
+    pub fn panic(&self) {
+        self.inner.scheduler.panic();
+    }
+
+    // jumps to the start of `bar`, dropping whatever was queued for the old
+    // position; notes sounding there are released
+    pub fn seek(&self, bar: u64) {
+        self.inner.scheduler.release();
+        self.inner.held.lock().unwrap().clear();
+        self.inner.upcoming.lock().unwrap().clear();
+        self.inner.clock.rewind(|clock| {
+            let position = clock.bars_position(bar.saturating_sub(1) as f64);
+            clock.seek(position);
+        });
+        self.inner.clock.notify(Tick::Seek(bar));
+    }
+
+    pub fn schedule(&self, event: Event) {
+        self.schedule_batch(vec![event]);
+    }
+
+    // a generator's whole output in one go (see Scheduler::schedule_batch)
+    pub fn schedule_batch(&self, events: Vec<Event>) {
+        match self.state() {
+            State::Playing => {
+                let (due, later): (Vec<Event>, Vec<Event>) =
+                    events.into_iter().partition(|event| self.in_window(event));
+                self.inner.upcoming.lock().unwrap().extend(later);
+                self.dispatch(due);
+            }
+            State::Paused => self.inner.held.lock().unwrap().extend(events),
+            State::Stopped => {}
+        }
+    }
+
+    // plays `repeat` every `beats` beats until stopped, for material too
+    // simple to need a generator: clicks, steady hats, periodic CC resets
+    //
+    //   transport.schedule_every(1, Event::note(42).dur_beats(0.25).at(0));
+    //   transport.schedule_every(16, |beat| {
+    //       let reset = Message::ControlChange { controller: 1, value: 0 };
+    //       vec![Event::new(reset, beat)]
+    //   });
+    pub fn schedule_every<R: Into<Repeat>>(&self, beats: u64, repeat: R) -> Every {
+        let repeat = repeat.into();
+        let every = beats.max(1);
+        let handle = Every {
+            running: Arc::new(AtomicBool::new(true)),
+        };
+        let running = handle.running.clone();
+        let ticks = self.subscribe();
+        let transport = self.clone();
+        let worker = thread::spawn(move || {
+            for tick in ticks {
+                if !running.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Tick::BeatTick(beat) = tick {
+                    transport.schedule_batch(repeat.events_at(beat, every));
+                }
+            }
+        });
+        self.attach(worker);
+        handle
+    }
+
+    // plays a recorded take (see Scheduler::record) like freshly generated
+    // events, so it's held while paused and kept out of the lookahead window
+    pub fn replay(&self, take: &Take) {
+        self.schedule_batch(take.events.clone());
+    }
+
+    // kills an upcoming pattern, including whatever is held while paused
+    // (see Scheduler::cancel_tag)
+    pub fn cancel_tag(&self, tag: &str) {
+        self.inner
+            .held
+            .lock()
+            .unwrap()
+            .retain(|event| event.tag.as_ref().is_none_or(|t| t != tag) || event.is_note_off());
+        self.inner
+            .upcoming
+            .lock()
+            .unwrap()
+            .retain(|event| event.tag.as_ref().is_none_or(|t| t != tag));
+        self.inner.scheduler.cancel_tag(tag);
+    }
+
+    fn in_window(&self, event: &Event) -> bool {
+        match *self.inner.lookahead.lock().unwrap() {
+            Some(beats) => {
+                let clock = self.clock().read().unwrap();
+                event.position(clock.ppqn()) < clock.position() + beats
+            }
+            None => true,
+        }
+    }
+
+    // schedules whatever the window has reached
+    fn advance(&self) {
+        if self.state() != State::Playing {
+            return;
+        }
+        let due: Vec<Event> = {
+            let mut upcoming = self.inner.upcoming.lock().unwrap();
+            let (due, later) = upcoming.drain(..).partition(|event| self.in_window(event));
+            *upcoming = later;
+            due
+        };
+        self.dispatch(due);
+    }
+
+    // a thread that lives off the transport's ticks, e.g. a generator
+    pub fn attach(&self, worker: JoinHandle<()>) {
+        self.inner.workers.lock().unwrap().push(worker);
+    }
+
+    // stops playing, ends the ticks so attached threads run out, waits for
+    // them and closes the backends once the last note-offs went out
+    pub fn shutdown(&self) {
+        self.stop();
+        self.inner.clock.shutdown();
+        let workers: Vec<JoinHandle<()>> = self.inner.workers.lock().unwrap().drain(..).collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        self.inner.scheduler.shutdown();
+    }
+
+    fn dispatch(&self, events: Vec<Event>) {
+        if events.is_empty() {
+            return;
+        }
+        let clock = self.clock().read().unwrap();
+        self.inner.scheduler.schedule_batch(&clock, events);
+    }
+
+    fn set_state(&self, state: State) {
+        *self.inner.state.lock().unwrap() = state;
+    }
+}